@@ -0,0 +1,44 @@
+#include <tunables/global>
+
+# Docker's docker-default profile, tightened for untrusted code.
+# Load with: safecrate apparmor-profile | sudo apparmor_parser -r
+profile safecrate flags=(attach_disconnected,mediate_deleted) {
+  #include <abstractions/base>
+
+  network inet,
+  network inet6,
+  network unix,
+  deny network raw,
+  deny network packet,
+
+  capability,
+  file,
+  umount,
+
+  signal (receive) peer=unconfined,
+  signal (send,receive) peer=safecrate,
+  ptrace (trace,read,tracedby,readby) peer=safecrate,
+
+  deny mount,
+  deny pivot_root,
+
+  deny @{PROC}/* w,
+  deny @{PROC}/{[^1-9],[^1-9][^0-9],[^1-9s][^0-9y][^0-9s],[^1-9][^0-9][^0-9][^0-9/]*}/** w,
+  deny @{PROC}/sys/[^k]** w,
+  deny @{PROC}/sys/kernel/{?,??,[^s][^h][^m]**} w,
+  deny @{PROC}/sysrq-trigger rwklx,
+  deny @{PROC}/kcore rwklx,
+  deny @{PROC}/kallsyms rwklx,
+
+  deny /sys/[^f]*/** wklx,
+  deny /sys/f[^s]*/** wklx,
+  deny /sys/fs/[^c]*/** wklx,
+  deny /sys/fs/c[^g]*/** wklx,
+  deny /sys/fs/cg[^r]*/** wklx,
+  deny /sys/firmware/** rwklx,
+  deny /sys/kernel/security/** rwklx,
+  deny /sys/kernel/debug/** rwklx,
+
+  # A Docker socket mounted by mistake would hand over the host.
+  deny /{,var/}run/docker.sock rw,
+}
@@ -0,0 +1,108 @@
+//! Per-directory results of a batch operation such as `safecrate remove a b c`.
+
+use anyhow::Result;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::style::{green, red, Marker};
+
+/// Outcome of running one operation over several project directories.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Each directory with its error message, if it failed.
+    entries: Vec<(PathBuf, Option<String>)>,
+}
+
+impl BatchReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the result for `dir`.
+    pub fn record(&mut self, dir: &Path, result: Result<()>) {
+        let error = result.err().map(|err| format!("{:#}", err));
+        self.entries.push((dir.to_path_buf(), error));
+    }
+
+    pub fn succeeded(&self) -> usize {
+        self.entries.len() - self.failed()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|(_, error)| error.is_some())
+            .count()
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = |n: usize, label: &str, paint: fn(String) -> String| {
+            let text = format!("{} {}", n, label);
+            if n > 0 {
+                paint(text)
+            } else {
+                text
+            }
+        };
+        write!(
+            f,
+            "{}, {}",
+            count(self.succeeded(), "succeeded", green),
+            count(self.failed(), "failed", red)
+        )?;
+        let width = self
+            .entries
+            .iter()
+            .map(|(dir, _)| dir.display().to_string().chars().count())
+            .max()
+            .unwrap_or(0);
+        for (dir, error) in &self.entries {
+            let dir = dir.display().to_string();
+            match error {
+                None => write!(f, "\n{} {}", Marker::Ok, dir)?,
+                Some(error) => write!(
+                    f,
+                    "\n{} {:<width$}  {}",
+                    Marker::Error,
+                    dir,
+                    error,
+                    width = width
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn summarizes_and_aligns_failures() {
+        let mut report = BatchReport::new();
+        report.record(Path::new("a"), Ok(()));
+        report.record(
+            Path::new("submissions/b"),
+            Err(anyhow!("No such container")),
+        );
+        report.record(Path::new("c"), Err(anyhow!("inner").context("outer")));
+
+        assert_eq!((report.succeeded(), report.failed()), (1, 2));
+        let (ok, error) = (Marker::Ok, Marker::Error);
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "{}, {}\n\
+                 {ok} a\n\
+                 {error} submissions/b  No such container\n\
+                 {error} c              outer: inner",
+                green("1 succeeded"),
+                red("2 failed")
+            )
+        );
+    }
+}
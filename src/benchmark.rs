@@ -0,0 +1,204 @@
+//! Timing and resource peaks of a run, sampled from `docker stats` (`open --benchmark`).
+
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::runtime::{args, ContainerRuntime};
+
+/// How often `docker stats` is sampled while the command runs.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How a [`BenchmarkReport`] is printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    #[default]
+    Human,
+    /// One JSON object on stdout.
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "human" => Ok(ReportFormat::Human),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(anyhow!(
+                "Unknown report format '{}': expected human or json",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReportFormat::Human => "human",
+            ReportFormat::Json => "json",
+        })
+    }
+}
+
+/// What a benchmarked run took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkReport {
+    pub container: String,
+    pub wall: Duration,
+    /// Highest CPU usage seen, where 100 is one full CPU.
+    pub peak_cpu_percent: Option<f64>,
+    pub peak_memory_bytes: Option<u64>,
+    /// Number of successful `docker stats` samples; peaks are `None` without any.
+    pub samples: usize,
+}
+
+impl BenchmarkReport {
+    /// The report as one line of JSON, with `null` for peaks that weren't sampled.
+    pub fn to_json(&self) -> String {
+        let or_null = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+        format!(
+            "{{\"container\":{},\"wall_seconds\":{:.3},\"peak_cpu_percent\":{},\"peak_memory_bytes\":{},\"samples\":{}}}",
+            crate::events::json_string(&self.container),
+            self.wall.as_secs_f64(),
+            or_null(self.peak_cpu_percent.map(|cpu| format!("{:.2}", cpu))),
+            or_null(self.peak_memory_bytes.map(|bytes| bytes.to_string())),
+            self.samples
+        )
+    }
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Benchmark of {}:", self.container)?;
+        writeln!(f, "  Wall time:   {:.2}s", self.wall.as_secs_f64())?;
+        match self.peak_cpu_percent {
+            Some(cpu) => writeln!(f, "  Peak CPU:    {:.1}%", cpu)?,
+            None => writeln!(f, "  Peak CPU:    n/a")?,
+        }
+        match self.peak_memory_bytes {
+            Some(bytes) => writeln!(f, "  Peak memory: {:.1} MiB", bytes as f64 / 1_048_576.0)?,
+            None => writeln!(f, "  Peak memory: n/a")?,
+        }
+        write!(f, "  Samples:     {}", self.samples)
+    }
+}
+
+/// Run `run`, sampling the stats of `container` every `interval` until it returns.
+///
+/// Runs shorter than Docker's first sample report wall time only.
+pub fn benchmark<T>(
+    runtime: &dyn ContainerRuntime,
+    container: &str,
+    interval: Duration,
+    run: impl FnOnce() -> T,
+) -> (T, BenchmarkReport) {
+    let done = AtomicBool::new(false);
+    let start = Instant::now();
+    let (result, samples) = std::thread::scope(|scope| {
+        let sampler = scope.spawn(|| {
+            let mut samples = Vec::new();
+            while !done.load(Ordering::Relaxed) {
+                samples.extend(sample(runtime, container));
+                std::thread::sleep(interval);
+            }
+            samples
+        });
+        let result = run();
+        done.store(true, Ordering::Relaxed);
+        (result, sampler.join().unwrap_or_default())
+    });
+    let report = BenchmarkReport {
+        container: container.to_string(),
+        wall: start.elapsed(),
+        peak_cpu_percent: samples.iter().map(|s| s.0).reduce(f64::max),
+        peak_memory_bytes: samples.iter().map(|s| s.1).max(),
+        samples: samples.len(),
+    };
+    (result, report)
+}
+
+/// One CPU percentage and memory usage reading, if the container is running.
+fn sample(runtime: &dyn ContainerRuntime, container: &str) -> Option<(f64, u64)> {
+    let output = runtime
+        .output(&args(&[
+            "stats",
+            "--no-stream",
+            "--format",
+            "{{.CPUPerc}}\t{{.MemUsage}}",
+            container,
+        ]))
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_sample(output.stdout.trim())
+}
+
+/// Parse `12.34%\t56.7MiB / 1.944GiB`.
+fn parse_sample(line: &str) -> Option<(f64, u64)> {
+    let (cpu, memory) = line.split_once('\t')?;
+    let cpu = cpu.trim().strip_suffix('%')?.parse().ok()?;
+    let used = memory.split('/').next()?.trim();
+    Some((cpu, parse_bytes(used)?))
+}
+
+/// Parse a size as `docker stats` prints it, e.g. `56.7MiB` or `1.2GB`.
+fn parse_bytes(size: &str) -> Option<u64> {
+    let split = size.find(|c: char| c.is_ascii_alphabetic())?;
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let scale: f64 = match unit {
+        "B" => 1.0,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        "kB" | "KB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        _ => return None,
+    };
+    Some((number * scale).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{MockRuntime, Output};
+
+    #[test]
+    fn parses_stats_samples() {
+        assert_eq!(
+            parse_sample("187.25%\t512MiB / 1.944GiB"),
+            Some((187.25, 512 * 1024 * 1024))
+        );
+        assert_eq!(parse_sample("0.00%\t1.5kB / 2GB"), Some((0.0, 1500)));
+        assert_eq!(parse_sample("--\t-- / --"), None);
+    }
+
+    #[test]
+    fn reports_peaks_of_samples() {
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("50.00%\t100MiB / 2GiB\n"));
+        runtime.respond(Output::success("150.00%\t10MiB / 2GiB\n"));
+        runtime.respond(Output::failure(1, "Error: No such container"));
+
+        let (result, report) = benchmark(&runtime, "app_isolated", Duration::ZERO, || {
+            while runtime.calls().len() < 3 {
+                std::thread::yield_now();
+            }
+            7
+        });
+        assert_eq!(result, 7);
+        assert_eq!(report.peak_cpu_percent, Some(150.0));
+        assert_eq!(report.peak_memory_bytes, Some(100 * 1024 * 1024));
+        assert!(report.samples >= 2);
+        assert!(report.to_json().contains("\"peak_cpu_percent\":150.00"));
+        assert_eq!("json".parse::<ReportFormat>().unwrap(), ReportFormat::Json);
+    }
+}
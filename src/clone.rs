@@ -0,0 +1,122 @@
+//! Fetching a Git repository for `safecrate open-url` without running git on the host.
+
+use anyhow::{anyhow, Result};
+use std::path::Path;
+
+use crate::runtime::{args, run_checked, ContainerRuntime};
+
+/// Directory name for a clone of `url`, e.g. `bar` for `https://github.com/foo/bar.git`.
+pub fn repo_name(url: &str) -> String {
+    let last = url
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or_default();
+    let name: String = last
+        .trim_end_matches(".git")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    match name.trim_start_matches('.') {
+        "" => "repo".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Clone `url` into the empty directory `dest` from a throwaway container running `image`.
+///
+/// The clone container has the default network, since it has to fetch, but nothing else
+/// of the host besides `dest`; it runs as the owner of `dest` so the files can be cleaned up.
+pub fn clone_repo(
+    runtime: &dyn ContainerRuntime,
+    url: &str,
+    dest: &Path,
+    image: &str,
+) -> Result<()> {
+    if !["https://", "http://", "git://"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+    {
+        return Err(anyhow!(
+            "Unsupported repository URL '{}': expected https://, http:// or git://",
+            url
+        ));
+    }
+    let dest = std::fs::canonicalize(dest)?;
+
+    let mut clone = args(&["run", "--rm"]);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = std::fs::metadata(&dest)?;
+        clone.extend([
+            "--user".into(),
+            format!("{}:{}", metadata.uid(), metadata.gid()),
+        ]);
+    }
+    clone.extend(args(&[
+        "-e",
+        "HOME=/tmp",
+        "-v",
+        &format!("{}:/workspace", dest.display()),
+        "-w",
+        "/workspace",
+        "--entrypoint",
+        "git",
+        image,
+        "clone",
+        "--",
+        url,
+        ".",
+    ]));
+    run_checked(runtime, &clone, "Failed to clone repository")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::MockRuntime;
+    use crate::testutil::project_dir;
+
+    #[test]
+    fn repo_names_from_urls() {
+        assert_eq!(repo_name("https://github.com/foo/bar"), "bar");
+        assert_eq!(repo_name("https://github.com/foo/bar.git/"), "bar");
+        assert_eq!(repo_name("git://host/a b"), "a-b");
+        assert_eq!(repo_name("https://host/.."), "repo");
+    }
+
+    #[test]
+    fn clones_inside_a_container() {
+        let dir = project_dir("clone-dest");
+        let runtime = MockRuntime::new();
+        clone_repo(
+            &runtime,
+            "https://github.com/foo/bar",
+            &dir,
+            "safecrate_default",
+        )
+        .unwrap();
+
+        let calls = runtime.calls();
+        assert_eq!(calls[0][..2], ["run", "--rm"]);
+        assert!(calls[0].ends_with(&args(&[
+            "--entrypoint",
+            "git",
+            "safecrate_default",
+            "clone",
+            "--",
+            "https://github.com/foo/bar",
+            "."
+        ])));
+
+        assert!(clone_repo(&runtime, "--upload-pack=evil", &dir, "img").is_err());
+        assert!(clone_repo(&runtime, "file:///etc", &dir, "img").is_err());
+    }
+}
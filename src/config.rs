@@ -0,0 +1,936 @@
+//! Per-project defaults stored in `.safecrate.toml` at the project root.
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::toml::{self, Value};
+use crate::validate;
+use crate::{BindPropagation, Editor, Isolation, ProjectKind, PullPolicy, Sandbox};
+
+/// File name of the per-project config, looked up in the opened directory.
+pub const CONFIG_FILE_NAME: &str = ".safecrate.toml";
+
+/// Log drivers a config file may select.
+const LOCAL_LOG_DRIVERS: [&str; 3] = ["json-file", "local", "none"];
+
+/// Options for `open`, as read from `.safecrate.toml` or gathered from flags.
+///
+/// Unset fields fall back to the [`Sandbox`] defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub subdir: Option<PathBuf>,
+    pub copy_in: Option<bool>,
+    pub image: Option<String>,
+    pub pull: Option<PullPolicy>,
+    pub cmd: Option<String>,
+    pub keep_container: Option<bool>,
+    pub keep_on_failure: Option<bool>,
+    pub network: Option<bool>,
+    pub network_mode: Option<String>,
+    pub ip: Option<String>,
+    pub mac_address: Option<String>,
+    pub tmpfs: Vec<String>,
+    pub sysctls: Vec<String>,
+    pub stop_timeout: Option<u32>,
+    pub memory: Option<String>,
+    pub oom_score_adj: Option<i32>,
+    pub isolation: Option<Isolation>,
+    pub oci_runtime: Option<String>,
+    pub annotations: Vec<String>,
+    pub health_cmd: Option<String>,
+    pub health_interval: Option<String>,
+    pub health_retries: Option<u32>,
+    pub shm_size: Option<String>,
+    pub user: Option<String>,
+    pub group_add: Vec<String>,
+    pub cpus: Option<String>,
+    pub cpu_shares: Option<u32>,
+    pub cpu_period: Option<u32>,
+    pub cpu_quota: Option<u32>,
+    pub read_only_rootfs: Option<bool>,
+    pub tmpfs_exec: Option<bool>,
+    pub entrypoint: Option<String>,
+    pub args: Vec<String>,
+    pub auto_image: Option<bool>,
+    pub detect_order: Vec<ProjectKind>,
+    pub detach_keys: Option<String>,
+    pub storage_opts: Vec<String>,
+    pub log_driver: Option<String>,
+    pub log_opts: Vec<String>,
+    pub umask: Option<String>,
+    pub cmd_timeout: Option<u32>,
+    pub cap_drop: Vec<String>,
+    pub apparmor: Option<String>,
+    pub hostname_from_project: Option<bool>,
+    pub expose: Vec<String>,
+    pub ipc: Option<String>,
+    pub editor: Option<Editor>,
+    pub max_open_containers: Option<u32>,
+    pub bind_propagation: Option<BindPropagation>,
+    pub setup: Option<String>,
+    pub mount_target_owner: Option<bool>,
+    pub cpuset_cpus: Option<String>,
+    pub cpuset_mems: Option<String>,
+    pub persist_cache: Option<bool>,
+    pub cache_path: Option<String>,
+    pub writable_subdirs: Vec<String>,
+    pub container_user_home: Option<String>,
+}
+
+/// A sandbox described in its own file (`open --spec`): the project directory plus any
+/// config keys.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Spec {
+    /// Resolved against the spec file's directory.
+    pub dir: Option<PathBuf>,
+    pub config: Config,
+}
+
+impl Spec {
+    /// Load a spec file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&content, base).with_context(|| format!("Invalid spec {}", path.display()))
+    }
+
+    /// Parse a spec document, resolving a relative `dir` against `base`.
+    pub fn parse(input: &str, base: &Path) -> Result<Self> {
+        let mut table = toml::parse(input)?;
+        let dir = take_string(&mut table, "dir")?.map(|dir| base.join(dir));
+        Ok(Self {
+            dir,
+            config: Config::from_table(table)?,
+        })
+    }
+}
+
+impl Config {
+    /// Load `.safecrate.toml` from the project directory, or defaults if there is none.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(CONFIG_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    /// Parse a config document.
+    pub fn parse(input: &str) -> Result<Self> {
+        Self::from_table(toml::parse(input)?)
+    }
+
+    fn from_table(mut table: BTreeMap<String, Value>) -> Result<Self> {
+        let config = Self {
+            subdir: take_string(&mut table, "subdir")?.map(PathBuf::from),
+            copy_in: take_bool(&mut table, "copy_in")?,
+            image: take_string(&mut table, "image")?,
+            pull: take_string(&mut table, "pull")?
+                .map(|s| s.parse())
+                .transpose()?,
+            cmd: take_string(&mut table, "cmd")?,
+            keep_container: take_bool(&mut table, "keep_container")?,
+            keep_on_failure: take_bool(&mut table, "keep_on_failure")?,
+            network: take_bool(&mut table, "network")?,
+            network_mode: take_string(&mut table, "network_mode")?,
+            ip: take_string(&mut table, "ip")?,
+            mac_address: take_string(&mut table, "mac_address")?,
+            tmpfs: take_strings(&mut table, "tmpfs")?,
+            sysctls: take_strings(&mut table, "sysctls")?,
+            stop_timeout: take_integer(&mut table, "stop_timeout")?,
+            memory: take_string(&mut table, "memory")?,
+            oom_score_adj: take_integer(&mut table, "oom_score_adj")?,
+            isolation: take_string(&mut table, "sandbox")?
+                .map(|s| s.parse())
+                .transpose()?,
+            oci_runtime: take_string(&mut table, "oci_runtime")?,
+            annotations: take_strings(&mut table, "annotations")?,
+            health_cmd: take_string(&mut table, "health_cmd")?,
+            health_interval: take_string(&mut table, "health_interval")?,
+            health_retries: take_integer(&mut table, "health_retries")?,
+            shm_size: take_string(&mut table, "shm_size")?,
+            user: take_string(&mut table, "user")?,
+            group_add: take_strings(&mut table, "group_add")?,
+            cpus: take_string(&mut table, "cpus")?,
+            cpu_shares: take_integer(&mut table, "cpu_shares")?,
+            cpu_period: take_integer(&mut table, "cpu_period")?,
+            cpu_quota: take_integer(&mut table, "cpu_quota")?,
+            read_only_rootfs: take_bool(&mut table, "read_only_rootfs")?,
+            tmpfs_exec: take_bool(&mut table, "read_only_tmpfs_exec")?,
+            entrypoint: take_string(&mut table, "entrypoint")?,
+            args: take_strings(&mut table, "args")?,
+            auto_image: take_bool(&mut table, "auto_image")?,
+            detect_order: take_strings(&mut table, "detect_order")?
+                .iter()
+                .map(|s| s.parse())
+                .collect::<Result<_>>()?,
+            detach_keys: take_string(&mut table, "detach_keys")?,
+            storage_opts: take_strings(&mut table, "storage_opts")?,
+            log_driver: take_string(&mut table, "log_driver")?,
+            log_opts: take_strings(&mut table, "log_opts")?,
+            umask: take_string(&mut table, "umask")?,
+            cmd_timeout: take_integer(&mut table, "cmd_timeout")?,
+            cap_drop: take_strings(&mut table, "cap_drop")?,
+            apparmor: take_string(&mut table, "apparmor")?,
+            hostname_from_project: take_bool(&mut table, "hostname_from_project")?,
+            expose: take_strings(&mut table, "expose")?,
+            ipc: take_string(&mut table, "ipc")?,
+            editor: take_string(&mut table, "editor")?
+                .map(|s| s.parse())
+                .transpose()?,
+            max_open_containers: take_integer(&mut table, "max_open_containers")?,
+            bind_propagation: take_string(&mut table, "bind_propagation")?
+                .map(|s| s.parse())
+                .transpose()?,
+            setup: take_string(&mut table, "setup")?,
+            mount_target_owner: take_bool(&mut table, "mount_target_owner")?,
+            cpuset_cpus: take_string(&mut table, "cpuset_cpus")?,
+            cpuset_mems: take_string(&mut table, "cpuset_mems")?,
+            persist_cache: take_bool(&mut table, "persist_cache")?,
+            cache_path: take_string(&mut table, "cache_path")?,
+            writable_subdirs: take_strings(&mut table, "writable_subdirs")?,
+            container_user_home: take_string(&mut table, "container_user_home")?,
+        };
+
+        if let Some(key) = table.keys().next() {
+            return Err(anyhow!("unknown key '{}'", key));
+        }
+        if let Some(image) = &config.image {
+            validate::image(image)?;
+        }
+        config.check_log_driver()?;
+        config.check_isolation()?;
+        Ok(config)
+    }
+
+    /// Only allow log drivers that keep output on the host's disk.
+    ///
+    /// Drivers such as syslog or gelf ship container output from the daemon itself, past
+    /// `--network none`, so the untrusted project can't choose them; pass them as flags.
+    fn check_log_driver(&self) -> Result<()> {
+        match self.log_driver.as_deref() {
+            None if !self.log_opts.is_empty() => Err(anyhow!(
+                "'log_opts' requires 'log_driver' to be set in the same file"
+            )),
+            Some(driver) if !LOCAL_LOG_DRIVERS.contains(&driver) => Err(anyhow!(
+                "'log_driver' must be one of {} in {}; pass --log-driver {} on the command line instead",
+                LOCAL_LOG_DRIVERS.join(", "),
+                CONFIG_FILE_NAME,
+                driver
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Keep the untrusted project from turning AppArmor off or sharing namespaces.
+    fn check_isolation(&self) -> Result<()> {
+        if self.apparmor.as_deref() == Some("unconfined") {
+            return Err(anyhow!(
+                "'apparmor' can't be \"unconfined\" in {}; pass --apparmor unconfined on the command line instead",
+                CONFIG_FILE_NAME
+            ));
+        }
+        if self.bind_propagation == Some(BindPropagation::Rshared) {
+            return Err(anyhow!(
+                "'bind_propagation' can't be \"rshared\" in {}; pass --bind-propagation rshared on the command line instead",
+                CONFIG_FILE_NAME
+            ));
+        }
+        if let Some(mode) = self
+            .ipc
+            .as_deref()
+            .filter(|mode| *mode != "none" && *mode != "private")
+        {
+            return Err(anyhow!(
+                "'ipc' must be \"none\" or \"private\" in {}; pass --ipc {} on the command line instead",
+                CONFIG_FILE_NAME,
+                mode
+            ));
+        }
+        if let Some(mode) = self
+            .network_mode
+            .as_deref()
+            .filter(|mode| *mode == "host" || mode.starts_with("container:"))
+        {
+            return Err(anyhow!(
+                "'network_mode' can't be \"{}\" in {}; pass --network-mode {} on the command line instead",
+                mode,
+                CONFIG_FILE_NAME,
+                mode
+            ));
+        }
+        if let Some(value) = self.oom_score_adj.filter(|value| *value < 0) {
+            return Err(anyhow!(
+                "'oom_score_adj' can't be negative in {}; pass --oom-score-adj {} on the command line instead",
+                CONFIG_FILE_NAME,
+                value
+            ));
+        }
+        // Picking the OCI runtime could swap a hardened daemon default such as runsc
+        // for runc, and annotations are acted on by runtimes like crun and kata.
+        if self.oci_runtime.is_some() {
+            return Err(anyhow!(
+                "'oci_runtime' can't be set in {}; pass --runtime-oci on the command line instead",
+                CONFIG_FILE_NAME
+            ));
+        }
+        if !self.annotations.is_empty() {
+            return Err(anyhow!(
+                "'annotations' can't be set in {}; pass --annotation on the command line instead",
+                CONFIG_FILE_NAME
+            ));
+        }
+        Ok(())
+    }
+
+    /// Serialize the set fields as a config document.
+    pub fn to_toml(&self) -> String {
+        let mut out = Writer::default();
+        out.display("subdir", self.subdir.as_ref().map(|p| p.display()));
+        out.bool("copy_in", self.copy_in);
+        out.display("image", self.image.as_deref());
+        out.display("pull", self.pull);
+        out.display("cmd", self.cmd.as_deref());
+        out.bool("keep_container", self.keep_container);
+        out.bool("keep_on_failure", self.keep_on_failure);
+        out.bool("network", self.network);
+        out.display("network_mode", self.network_mode.as_deref());
+        out.display("ip", self.ip.as_deref());
+        out.display("mac_address", self.mac_address.as_deref());
+        out.strings("tmpfs", &self.tmpfs);
+        out.strings("sysctls", &self.sysctls);
+        out.integer("stop_timeout", self.stop_timeout);
+        out.display("memory", self.memory.as_deref());
+        out.integer("oom_score_adj", self.oom_score_adj);
+        out.display("sandbox", self.isolation);
+        out.display("oci_runtime", self.oci_runtime.as_deref());
+        out.strings("annotations", &self.annotations);
+        out.display("health_cmd", self.health_cmd.as_deref());
+        out.display("health_interval", self.health_interval.as_deref());
+        out.integer("health_retries", self.health_retries);
+        out.display("shm_size", self.shm_size.as_deref());
+        out.display("user", self.user.as_deref());
+        out.strings("group_add", &self.group_add);
+        out.display("cpus", self.cpus.as_deref());
+        out.integer("cpu_shares", self.cpu_shares);
+        out.integer("cpu_period", self.cpu_period);
+        out.integer("cpu_quota", self.cpu_quota);
+        out.bool("read_only_rootfs", self.read_only_rootfs);
+        out.bool("read_only_tmpfs_exec", self.tmpfs_exec);
+        out.display("entrypoint", self.entrypoint.as_deref());
+        out.strings("args", &self.args);
+        out.bool("auto_image", self.auto_image);
+        let detect_order: Vec<String> = self.detect_order.iter().map(|k| k.to_string()).collect();
+        out.strings("detect_order", &detect_order);
+        out.display("detach_keys", self.detach_keys.as_deref());
+        out.strings("storage_opts", &self.storage_opts);
+        out.display("log_driver", self.log_driver.as_deref());
+        out.strings("log_opts", &self.log_opts);
+        out.display("umask", self.umask.as_deref());
+        out.integer("cmd_timeout", self.cmd_timeout);
+        out.strings("cap_drop", &self.cap_drop);
+        out.display("apparmor", self.apparmor.as_deref());
+        out.bool("hostname_from_project", self.hostname_from_project);
+        out.strings("expose", &self.expose);
+        out.display("ipc", self.ipc.as_deref());
+        out.display("editor", self.editor);
+        out.integer("max_open_containers", self.max_open_containers);
+        out.display("bind_propagation", self.bind_propagation);
+        out.display("setup", self.setup.as_deref());
+        out.bool("mount_target_owner", self.mount_target_owner);
+        out.display("cpuset_cpus", self.cpuset_cpus.as_deref());
+        out.display("cpuset_mems", self.cpuset_mems.as_deref());
+        out.bool("persist_cache", self.persist_cache);
+        out.display("cache_path", self.cache_path.as_deref());
+        out.strings("writable_subdirs", &self.writable_subdirs);
+        out.display("container_user_home", self.container_user_home.as_deref());
+        out.0
+    }
+
+    /// Write `.safecrate.toml` into the project directory.
+    ///
+    /// Refuses to overwrite an existing file unless `force` is set. Returns the path written.
+    pub fn save(&self, dir: &Path, force: bool) -> Result<PathBuf> {
+        let path = dir.join(CONFIG_FILE_NAME);
+        if path.exists() && !force {
+            return Err(anyhow!(
+                "{} already exists. Use --force to overwrite it.",
+                path.display()
+            ));
+        }
+        self.check_log_driver()?;
+        self.check_isolation()?;
+        fs::write(&path, self.to_toml())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Layer `overrides` on top of this config; set fields in `overrides` win.
+    pub fn merge(self, overrides: Config) -> Config {
+        // `cmd`, `args` and `editor` are alternative forms of the command, so they override
+        // together.
+        let (cmd, args, editor) = if overrides.cmd.is_some()
+            || !overrides.args.is_empty()
+            || overrides.editor.is_some()
+        {
+            (overrides.cmd, overrides.args, overrides.editor)
+        } else {
+            (self.cmd, self.args, self.editor)
+        };
+        // Log options belong to a driver, so they only carry over together.
+        let (log_driver, log_opts) =
+            if overrides.log_driver.is_some() || !overrides.log_opts.is_empty() {
+                (overrides.log_driver, overrides.log_opts)
+            } else {
+                (self.log_driver, self.log_opts)
+            };
+        Config {
+            subdir: overrides.subdir.or(self.subdir),
+            copy_in: overrides.copy_in.or(self.copy_in),
+            image: overrides.image.or(self.image),
+            pull: overrides.pull.or(self.pull),
+            cmd,
+            keep_container: overrides.keep_container.or(self.keep_container),
+            keep_on_failure: overrides.keep_on_failure.or(self.keep_on_failure),
+            network: overrides.network.or(self.network),
+            network_mode: overrides.network_mode.or(self.network_mode),
+            ip: overrides.ip.or(self.ip),
+            mac_address: overrides.mac_address.or(self.mac_address),
+            tmpfs: or_list(overrides.tmpfs, self.tmpfs),
+            sysctls: or_list(overrides.sysctls, self.sysctls),
+            stop_timeout: overrides.stop_timeout.or(self.stop_timeout),
+            memory: overrides.memory.or(self.memory),
+            oom_score_adj: overrides.oom_score_adj.or(self.oom_score_adj),
+            isolation: overrides.isolation.or(self.isolation),
+            oci_runtime: overrides.oci_runtime.or(self.oci_runtime),
+            annotations: or_list(overrides.annotations, self.annotations),
+            health_cmd: overrides.health_cmd.or(self.health_cmd),
+            health_interval: overrides.health_interval.or(self.health_interval),
+            health_retries: overrides.health_retries.or(self.health_retries),
+            shm_size: overrides.shm_size.or(self.shm_size),
+            user: overrides.user.or(self.user),
+            group_add: or_list(overrides.group_add, self.group_add),
+            cpus: overrides.cpus.or(self.cpus),
+            cpu_shares: overrides.cpu_shares.or(self.cpu_shares),
+            cpu_period: overrides.cpu_period.or(self.cpu_period),
+            cpu_quota: overrides.cpu_quota.or(self.cpu_quota),
+            read_only_rootfs: overrides.read_only_rootfs.or(self.read_only_rootfs),
+            tmpfs_exec: overrides.tmpfs_exec.or(self.tmpfs_exec),
+            entrypoint: overrides.entrypoint.or(self.entrypoint),
+            args,
+            auto_image: overrides.auto_image.or(self.auto_image),
+            detect_order: or_list(overrides.detect_order, self.detect_order),
+            detach_keys: overrides.detach_keys.or(self.detach_keys),
+            storage_opts: or_list(overrides.storage_opts, self.storage_opts),
+            log_driver,
+            log_opts,
+            umask: overrides.umask.or(self.umask),
+            cmd_timeout: overrides.cmd_timeout.or(self.cmd_timeout),
+            cap_drop: or_list(overrides.cap_drop, self.cap_drop),
+            apparmor: overrides.apparmor.or(self.apparmor),
+            hostname_from_project: overrides
+                .hostname_from_project
+                .or(self.hostname_from_project),
+            expose: or_list(overrides.expose, self.expose),
+            ipc: overrides.ipc.or(self.ipc),
+            editor,
+            max_open_containers: overrides.max_open_containers.or(self.max_open_containers),
+            bind_propagation: overrides.bind_propagation.or(self.bind_propagation),
+            setup: overrides.setup.or(self.setup),
+            mount_target_owner: overrides.mount_target_owner.or(self.mount_target_owner),
+            cpuset_cpus: overrides.cpuset_cpus.or(self.cpuset_cpus),
+            cpuset_mems: overrides.cpuset_mems.or(self.cpuset_mems),
+            persist_cache: overrides.persist_cache.or(self.persist_cache),
+            cache_path: overrides.cache_path.or(self.cache_path),
+            writable_subdirs: or_list(overrides.writable_subdirs, self.writable_subdirs),
+            container_user_home: overrides.container_user_home.or(self.container_user_home),
+        }
+    }
+
+    /// Build a sandbox for `dir` with these options applied.
+    pub fn sandbox(&self, dir: impl Into<PathBuf>) -> Sandbox {
+        let mut sandbox = Sandbox::new(dir);
+        if let Some(subdir) = &self.subdir {
+            sandbox = sandbox.subdir(subdir);
+        }
+        if let Some(copy_in) = self.copy_in {
+            sandbox = sandbox.copy_in(copy_in);
+        }
+        if let Some(image) = &self.image {
+            sandbox = sandbox.image(image);
+        }
+        if let Some(policy) = self.pull {
+            sandbox = sandbox.pull(policy);
+        }
+        if let Some(cmd) = &self.cmd {
+            sandbox = sandbox.cmd(cmd);
+        }
+        if let Some(keep) = self.keep_container {
+            sandbox = sandbox.keep_container(keep);
+        }
+        if let Some(keep) = self.keep_on_failure {
+            sandbox = sandbox.keep_on_failure(keep);
+        }
+        if let Some(network) = self.network {
+            sandbox = sandbox.network(network);
+        }
+        if let Some(mode) = &self.network_mode {
+            sandbox = sandbox.network_mode(mode);
+        }
+        if let Some(ip) = &self.ip {
+            sandbox = sandbox.ip(ip);
+        }
+        if let Some(mac) = &self.mac_address {
+            sandbox = sandbox.mac_address(mac);
+        }
+        for spec in &self.tmpfs {
+            sandbox = sandbox.tmpfs(spec);
+        }
+        for spec in &self.sysctls {
+            sandbox = sandbox.sysctl(spec);
+        }
+        if let Some(seconds) = self.stop_timeout {
+            sandbox = sandbox.stop_timeout(seconds);
+        }
+        if let Some(memory) = &self.memory {
+            sandbox = sandbox.memory(memory);
+        }
+        if let Some(value) = self.oom_score_adj {
+            sandbox = sandbox.oom_score_adj(value);
+        }
+        if let Some(isolation) = self.isolation {
+            sandbox = sandbox.isolation(isolation);
+        }
+        if let Some(runtime) = &self.oci_runtime {
+            sandbox = sandbox.oci_runtime(runtime);
+        }
+        for spec in &self.annotations {
+            sandbox = sandbox.annotation(spec);
+        }
+        if let Some(cmd) = &self.health_cmd {
+            sandbox = sandbox.health_cmd(cmd);
+        }
+        if let Some(interval) = &self.health_interval {
+            sandbox = sandbox.health_interval(interval);
+        }
+        if let Some(retries) = self.health_retries {
+            sandbox = sandbox.health_retries(retries);
+        }
+        if let Some(value) = &self.shm_size {
+            sandbox = sandbox.shm_size(value);
+        }
+        if let Some(value) = &self.user {
+            sandbox = sandbox.user(value);
+        }
+        for value in &self.group_add {
+            sandbox = sandbox.group_add(value);
+        }
+        if let Some(value) = &self.cpus {
+            sandbox = sandbox.cpus(value);
+        }
+        if let Some(value) = self.cpu_shares {
+            sandbox = sandbox.cpu_shares(value);
+        }
+        if let Some(value) = self.cpu_period {
+            sandbox = sandbox.cpu_period(value);
+        }
+        if let Some(value) = self.cpu_quota {
+            sandbox = sandbox.cpu_quota(value);
+        }
+        if let Some(value) = self.read_only_rootfs {
+            sandbox = sandbox.read_only_rootfs(value);
+        }
+        if let Some(value) = self.tmpfs_exec {
+            sandbox = sandbox.tmpfs_exec(value);
+        }
+        if let Some(value) = &self.entrypoint {
+            sandbox = sandbox.entrypoint(value);
+        }
+        if !self.args.is_empty() {
+            sandbox = sandbox.args(&self.args);
+        }
+        if let Some(value) = self.auto_image {
+            sandbox = sandbox.auto_image(value);
+        }
+        if !self.detect_order.is_empty() {
+            sandbox = sandbox.detect_order(self.detect_order.iter().copied());
+        }
+        if let Some(value) = &self.detach_keys {
+            sandbox = sandbox.detach_keys(value);
+        }
+        for value in &self.storage_opts {
+            sandbox = sandbox.storage_opt(value);
+        }
+        if let Some(value) = &self.log_driver {
+            sandbox = sandbox.log_driver(value);
+        }
+        for value in &self.log_opts {
+            sandbox = sandbox.log_opt(value);
+        }
+        if let Some(value) = &self.umask {
+            sandbox = sandbox.umask(value);
+        }
+        if let Some(value) = self.cmd_timeout {
+            sandbox = sandbox.cmd_timeout(value);
+        }
+        for value in &self.cap_drop {
+            sandbox = sandbox.cap_drop(value);
+        }
+        if let Some(value) = &self.apparmor {
+            sandbox = sandbox.apparmor(value);
+        }
+        if let Some(value) = self.hostname_from_project {
+            sandbox = sandbox.hostname_from_project(value);
+        }
+        for value in &self.expose {
+            sandbox = sandbox.expose(value);
+        }
+        if let Some(value) = &self.ipc {
+            sandbox = sandbox.ipc(value);
+        }
+        if let Some(value) = self.editor {
+            sandbox = sandbox.editor(value);
+        }
+        if let Some(value) = self.max_open_containers {
+            sandbox = sandbox.max_open_containers(value);
+        }
+        if let Some(value) = self.bind_propagation {
+            sandbox = sandbox.bind_propagation(value);
+        }
+        if let Some(value) = &self.setup {
+            sandbox = sandbox.setup(value);
+        }
+        if let Some(value) = self.mount_target_owner {
+            sandbox = sandbox.mount_target_owner(value);
+        }
+        if let Some(value) = &self.cpuset_cpus {
+            sandbox = sandbox.cpuset_cpus(value);
+        }
+        if let Some(value) = &self.cpuset_mems {
+            sandbox = sandbox.cpuset_mems(value);
+        }
+        if let Some(value) = self.persist_cache {
+            sandbox = sandbox.persist_cache(value);
+        }
+        if let Some(value) = &self.cache_path {
+            sandbox = sandbox.cache_path(value);
+        }
+        for value in &self.writable_subdirs {
+            sandbox = sandbox.writable_subdir(value);
+        }
+        if let Some(value) = &self.container_user_home {
+            sandbox = sandbox.container_user_home(value);
+        }
+        sandbox
+    }
+}
+
+fn or_list<T>(overrides: Vec<T>, base: Vec<T>) -> Vec<T> {
+    if overrides.is_empty() {
+        base
+    } else {
+        overrides
+    }
+}
+
+/// Serializes set fields as `key = value` lines.
+#[derive(Default)]
+struct Writer(String);
+
+impl Writer {
+    fn value(&mut self, key: &str, value: Value) {
+        self.0.push_str(&format!("{} = {}\n", key, value));
+    }
+
+    fn display(&mut self, key: &str, value: Option<impl fmt::Display>) {
+        if let Some(value) = value {
+            self.value(key, Value::String(value.to_string()));
+        }
+    }
+
+    fn bool(&mut self, key: &str, value: Option<bool>) {
+        if let Some(value) = value {
+            self.value(key, Value::Bool(value));
+        }
+    }
+
+    fn integer(&mut self, key: &str, value: Option<impl Into<i64>>) {
+        if let Some(value) = value {
+            self.value(key, Value::Integer(value.into()));
+        }
+    }
+
+    fn strings(&mut self, key: &str, values: &[String]) {
+        if !values.is_empty() {
+            let items = values.iter().cloned().map(Value::String).collect();
+            self.value(key, Value::Array(items));
+        }
+    }
+}
+
+fn take_string(table: &mut BTreeMap<String, Value>, key: &str) -> Result<Option<String>> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(other) => Err(type_error(key, "string", &other)),
+    }
+}
+
+fn take_bool(table: &mut BTreeMap<String, Value>, key: &str) -> Result<Option<bool>> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some(Value::Bool(b)) => Ok(Some(b)),
+        Some(other) => Err(type_error(key, "boolean", &other)),
+    }
+}
+
+fn take_integer<T: TryFrom<i64>>(
+    table: &mut BTreeMap<String, Value>,
+    key: &str,
+) -> Result<Option<T>> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some(Value::Integer(n)) => T::try_from(n)
+            .map(Some)
+            .map_err(|_| anyhow!("'{}' is out of range: {}", key, n)),
+        Some(other) => Err(type_error(key, "integer", &other)),
+    }
+}
+
+fn take_strings(table: &mut BTreeMap<String, Value>, key: &str) -> Result<Vec<String>> {
+    match table.remove(key) {
+        None => Ok(Vec::new()),
+        Some(Value::Array(items)) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s),
+                other => Err(type_error(key, "array of strings", &other)),
+            })
+            .collect(),
+        Some(other) => Err(type_error(key, "array of strings", &other)),
+    }
+}
+
+fn type_error(key: &str, expected: &str, found: &Value) -> anyhow::Error {
+    anyhow!(
+        "'{}' must be a {}, found {}",
+        key,
+        expected,
+        found.type_name()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::project_dir;
+
+    #[test]
+    fn parse_and_serialize_round_trip() {
+        let config = Config {
+            subdir: Some(PathBuf::from("crates/foo")),
+            copy_in: Some(true),
+            image: Some("rust:1.88".to_string()),
+            pull: Some(PullPolicy::Always),
+            cmd: Some("cargo test".to_string()),
+            keep_container: Some(true),
+            keep_on_failure: Some(false),
+            network: Some(true),
+            network_mode: Some("testnet".to_string()),
+            ip: Some("172.30.0.10".to_string()),
+            mac_address: Some("02:42:ac:1e:00:0a".to_string()),
+            tmpfs: vec!["/scratch:size=64m".to_string()],
+            sysctls: vec!["net.ipv4.ping_group_range=0 1000".to_string()],
+            stop_timeout: Some(5),
+            memory: Some("2g".to_string()),
+            oom_score_adj: Some(10),
+            isolation: Some(Isolation::Gvisor),
+            oci_runtime: None,
+            annotations: Vec::new(),
+            health_cmd: Some("true".to_string()),
+            health_interval: Some("10s".to_string()),
+            health_retries: Some(2),
+            shm_size: Some("1g".to_string()),
+            user: Some("1000:1000".to_string()),
+            group_add: vec!["998".to_string()],
+            cpus: None,
+            cpu_shares: Some(512),
+            cpu_period: Some(50_000),
+            cpu_quota: Some(100_000),
+            read_only_rootfs: Some(true),
+            tmpfs_exec: Some(false),
+            entrypoint: Some("/usr/bin/tool".to_string()),
+            args: vec!["--verbose".to_string()],
+            auto_image: Some(true),
+            detect_order: vec![ProjectKind::Python, ProjectKind::Rust],
+            detach_keys: Some("ctrl-x,x".to_string()),
+            storage_opts: vec!["size=10G".to_string()],
+            log_driver: Some("json-file".to_string()),
+            log_opts: vec!["max-size=10m".to_string()],
+            umask: Some("077".to_string()),
+            cmd_timeout: Some(600),
+            cap_drop: vec!["ALL".into()],
+            apparmor: Some("safecrate".into()),
+            hostname_from_project: Some(true),
+            expose: vec!["8080".into()],
+            ipc: Some("none".into()),
+            editor: Some(Editor::Helix),
+            max_open_containers: Some(8),
+            bind_propagation: Some(BindPropagation::Rslave),
+            setup: Some("git config --global --add safe.directory /workspace".to_string()),
+            mount_target_owner: Some(true),
+            cpuset_cpus: Some("0-3,8".to_string()),
+            cpuset_mems: Some("0".to_string()),
+            persist_cache: Some(true),
+            cache_path: Some("/cache".to_string()),
+            writable_subdirs: vec!["output".to_string()],
+            container_user_home: Some("/home/builder".to_string()),
+        };
+
+        assert_eq!(Config::parse(&config.to_toml()).unwrap(), config);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_wrong_types() {
+        assert!(Config::parse("netwrok = false").is_err());
+        assert!(Config::parse("network = \"no\"").is_err());
+        assert!(Config::parse("tmpfs = \"/scratch\"").is_err());
+        assert!(Config::parse("stop_timeout = -1").is_err());
+        assert!(Config::parse("sandbox = \"vm\"").is_err());
+    }
+
+    #[test]
+    fn overrides_win_when_set() {
+        let base = Config {
+            cmd: Some("bash".to_string()),
+            network: Some(false),
+            tmpfs: vec!["/a".to_string()],
+            ..Config::default()
+        };
+        let overrides = Config {
+            cmd: Some("cargo build".to_string()),
+            keep_container: Some(true),
+            ..Config::default()
+        };
+
+        let merged = base.merge(overrides);
+        assert_eq!(merged.cmd.as_deref(), Some("cargo build"));
+        assert_eq!(merged.keep_container, Some(true));
+        assert_eq!(merged.network, Some(false));
+        assert_eq!(merged.tmpfs, vec!["/a".to_string()]);
+    }
+
+    #[test]
+    fn image_cant_smuggle_docker_flags() {
+        // With `args` naming the real image, this would run `docker run --privileged ...`.
+        let payload = "image = \"--privileged\"\nargs = [\"safecrate_default\", \"bash\"]\n";
+        assert!(Config::parse(payload).is_err());
+
+        let dir = crate::testutil::project_dir("config-image-flag");
+        let config = Config {
+            image: Some("--privileged".to_string()),
+            args: vec!["safecrate_default".to_string(), "bash".to_string()],
+            ..Config::default()
+        };
+        assert!(config.sandbox(&dir).docker_args().is_err());
+    }
+
+    #[test]
+    fn config_only_selects_local_log_drivers() {
+        assert!(Config::parse("log_driver = \"local\"\nlog_opts = [\"max-size=10m\"]").is_ok());
+
+        let err = Config::parse("log_driver = \"syslog\"").unwrap_err();
+        assert!(err.to_string().contains("--log-driver syslog"));
+        assert!(Config::parse("log_opts = [\"syslog-address=udp://1.2.3.4:514\"]").is_err());
+    }
+
+    #[test]
+    fn config_cannot_weaken_isolation() {
+        assert!(Config::parse("apparmor = \"safecrate\"").is_ok());
+        let err = Config::parse("apparmor = \"unconfined\"").unwrap_err();
+        assert!(err.to_string().contains("--apparmor unconfined"));
+
+        assert!(Config::parse("ipc = \"none\"").is_ok());
+        let err = Config::parse("ipc = \"host\"").unwrap_err();
+        assert!(err.to_string().contains("--ipc host"));
+
+        assert!(Config::parse("network_mode = \"testnet\"").is_ok());
+        let err = Config::parse("network_mode = \"host\"").unwrap_err();
+        assert!(err.to_string().contains("--network-mode host"));
+        assert!(Config::parse("network_mode = \"container:db\"").is_err());
+
+        assert!(Config::parse("bind_propagation = \"rslave\"").is_ok());
+        let err = Config::parse("bind_propagation = \"rshared\"").unwrap_err();
+        assert!(err.to_string().contains("--bind-propagation rshared"));
+
+        assert!(Config::parse("oom_score_adj = 1000").is_ok());
+        let err = Config::parse("oom_score_adj = -1000").unwrap_err();
+        assert!(err.to_string().contains("--oom-score-adj -1000"));
+
+        assert!(Config::parse("sandbox = \"gvisor\"").is_ok());
+        let err = Config::parse("oci_runtime = \"runc\"").unwrap_err();
+        assert!(err.to_string().contains("--runtime-oci"));
+        let err = Config::parse("annotations = [\"run.oci.keep_original_groups=1\"]").unwrap_err();
+        assert!(err.to_string().contains("--annotation"));
+    }
+
+    #[test]
+    fn trailing_args_replace_configured_cmd() {
+        let base = Config {
+            cmd: Some("cargo test".to_string()),
+            ..Config::default()
+        };
+        let overrides = Config {
+            args: vec!["make".to_string()],
+            ..Config::default()
+        };
+
+        let merged = base.merge(overrides);
+        assert_eq!(merged.cmd, None);
+        assert_eq!(merged.args, vec!["make".to_string()]);
+    }
+
+    #[test]
+    fn save_refuses_to_overwrite_without_force() {
+        let dir = project_dir("config-save");
+        let _ = fs::remove_file(dir.join(CONFIG_FILE_NAME));
+        let config = Config {
+            network: Some(false),
+            ..Config::default()
+        };
+
+        config.save(&dir, false).unwrap();
+        assert!(config.save(&dir, false).is_err());
+        config.save(&dir, true).unwrap();
+        assert_eq!(Config::load(&dir).unwrap(), config);
+    }
+
+    #[test]
+    fn spec_adds_a_project_dir() {
+        let spec = Spec::parse(
+            "dir = \"app\"\ncmd = \"cargo test\"\nmemory = \"2g\"\n",
+            Path::new("/specs"),
+        )
+        .unwrap();
+        assert_eq!(spec.dir, Some(PathBuf::from("/specs/app")));
+        assert_eq!(spec.config.cmd.as_deref(), Some("cargo test"));
+        assert_eq!(spec.config.memory.as_deref(), Some("2g"));
+
+        let spec = Spec::parse("dir = \"/src/app\"\n", Path::new("/specs")).unwrap();
+        assert_eq!(spec.dir, Some(PathBuf::from("/src/app")));
+        assert!(Spec::parse("dri = \"app\"\n", Path::new("")).is_err());
+        assert!(Config::parse("dir = \"app\"\n").is_err());
+    }
+
+    #[test]
+    fn load_without_file_is_default() {
+        let dir = project_dir("config-missing");
+        assert_eq!(Config::load(&dir).unwrap(), Config::default());
+    }
+}
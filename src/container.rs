@@ -0,0 +1,1034 @@
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::events::{emit, Event};
+use crate::git::{GitRevision, GIT_BRANCH_LABEL, GIT_COMMIT_LABEL};
+use crate::origin::ORIGIN_LABEL;
+use crate::runtime::{args, run_checked, ContainerRuntime};
+use crate::validate;
+use crate::{CONTAINER_SUFFIX, DEFAULT_DETACH_KEYS};
+
+/// Resume a previously created container for the given directory.
+///
+/// `detach_keys` overrides [`DEFAULT_DETACH_KEYS`] for detaching from it again.
+pub fn resume(runtime: &dyn ContainerRuntime, dir: &Path, detach_keys: Option<&str>) -> Result<()> {
+    let container_name = container_name(dir)?;
+
+    if !container_exists(runtime, &container_name)? {
+        return Err(anyhow!(
+            "No existing container to resume. Run `safecrate open` first with --keep-container."
+        ));
+    }
+
+    let detach_keys = detach_keys.unwrap_or(DEFAULT_DETACH_KEYS);
+    validate::detach_keys(detach_keys)?;
+    run_checked(
+        runtime,
+        &args(&[
+            "start",
+            "-ai",
+            "--detach-keys",
+            detach_keys,
+            &container_name,
+        ]),
+        "Failed to resume container",
+    )
+}
+
+/// Run `cmd` in the project's kept container instead of re-attaching to its own command:
+/// the container is started in the background if needed, then `cmd` runs through `sh -c`
+/// in `/workspace` with a terminal attached.
+///
+/// The container's own command runs again too when it was stopped, and `cmd` ends early
+/// if that exits.
+pub fn resume_cmd(
+    runtime: &dyn ContainerRuntime,
+    dir: &Path,
+    detach_keys: Option<&str>,
+    cmd: &str,
+) -> Result<()> {
+    let container_name = container_name(dir)?;
+    validate::cmd(cmd)?;
+    let detach_keys = detach_keys.unwrap_or(DEFAULT_DETACH_KEYS);
+    validate::detach_keys(detach_keys)?;
+
+    match container_state(runtime, &container_name)?.as_deref() {
+        None => {
+            return Err(anyhow!(
+                "No existing container to resume. Run `safecrate open` first with --keep-container."
+            ))
+        }
+        Some("running") => {}
+        Some("paused") => {
+            return Err(anyhow!(
+                "Container {} is frozen; run `safecrate unfreeze {}` first",
+                container_name,
+                dir.display()
+            ))
+        }
+        Some(_) => run_checked(
+            runtime,
+            &args(&["start", &container_name]),
+            "Failed to start container",
+        )?,
+    }
+    run_checked(
+        runtime,
+        &args(&[
+            "exec",
+            "-it",
+            "--detach-keys",
+            detach_keys,
+            "-w",
+            "/workspace",
+            &container_name,
+            "sh",
+            "-c",
+            cmd,
+        ]),
+        "Failed to run the command in the container",
+    )
+}
+
+/// Print CPU, memory, network and process usage of the project's running container.
+///
+/// With `watch`, the figures refresh until interrupted.
+pub fn top(runtime: &dyn ContainerRuntime, dir: &Path, watch: bool) -> Result<()> {
+    let container_name = container_in_state(runtime, dir, "running")?;
+    let mut stats = args(&["stats"]);
+    if !watch {
+        stats.push("--no-stream".into());
+    }
+    stats.extend(args(&[
+        "--format",
+        "table {{.Name}}\t{{.CPUPerc}}\t{{.MemUsage}}\t{{.MemPerc}}\t{{.NetIO}}\t{{.PIDs}}",
+        &container_name,
+    ]));
+    run_checked(runtime, &stats, "Failed to read container stats")
+}
+
+/// Freeze every process in the project's running container, for inspection before deciding
+/// whether to kill it. Returns the container's name.
+pub fn freeze(runtime: &dyn ContainerRuntime, dir: &Path) -> Result<String> {
+    let container_name = container_in_state(runtime, dir, "running")?;
+    run_checked(
+        runtime,
+        &args(&["pause", &container_name]),
+        "Failed to freeze container",
+    )?;
+    Ok(container_name)
+}
+
+/// Let the processes of a container frozen by [`freeze`] run again. Returns its name.
+pub fn unfreeze(runtime: &dyn ContainerRuntime, dir: &Path) -> Result<String> {
+    let container_name = container_in_state(runtime, dir, "paused")?;
+    run_checked(
+        runtime,
+        &args(&["unpause", &container_name]),
+        "Failed to unfreeze container",
+    )?;
+    Ok(container_name)
+}
+
+/// Send `signal` (see [`validate::signal`]) to the main process of the project's running
+/// container. Returns the container's name.
+pub fn kill(runtime: &dyn ContainerRuntime, dir: &Path, signal: &str) -> Result<String> {
+    let signal = validate::signal(signal)?;
+    let container_name = container_in_state(runtime, dir, "running")?;
+    run_checked(
+        runtime,
+        &args(&["kill", "--signal", &signal, &container_name]),
+        "Failed to signal container",
+    )?;
+    Ok(container_name)
+}
+
+/// Name of the project's container, which must be in Docker state `expected`.
+fn container_in_state(
+    runtime: &dyn ContainerRuntime,
+    dir: &Path,
+    expected: &str,
+) -> Result<String> {
+    let container_name = container_name(dir)?;
+    match container_state(runtime, &container_name)?.as_deref() {
+        Some(state) if state == expected => Ok(container_name),
+        Some(state) => Err(anyhow!(
+            "Container {} is {}, not {}",
+            container_name,
+            state,
+            expected
+        )),
+        None => Err(anyhow!(
+            "No container for this project. Run `safecrate open` first."
+        )),
+    }
+}
+
+/// Whether a container with exactly this name exists, running or not.
+pub(crate) fn container_exists(runtime: &dyn ContainerRuntime, name: &str) -> Result<bool> {
+    // The name filter matches substrings, so compare the listed names exactly.
+    let output = runtime.output(&args(&[
+        "ps",
+        "-a",
+        "--filter",
+        &format!("name={}", name),
+        "--format",
+        "{{.Names}}",
+    ]))?;
+    Ok(output.stdout.lines().any(|line| line.trim() == name))
+}
+
+/// Docker's state for the named container (e.g. `running`), or `None` if it doesn't exist.
+pub(crate) fn container_state(
+    runtime: &dyn ContainerRuntime,
+    name: &str,
+) -> Result<Option<String>> {
+    let output = runtime.output(&args(&[
+        "inspect",
+        "--type",
+        "container",
+        "--format",
+        "{{.State.Status}}",
+        name,
+    ]))?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(output.stdout.trim().to_string()))
+}
+
+/// Remove a previously created container for the given directory.
+///
+/// With a `stop_timeout`, the container is first stopped gracefully, giving it that many
+/// seconds to exit before SIGKILL. Returns the name of the removed container.
+pub fn remove(
+    runtime: &dyn ContainerRuntime,
+    dir: &Path,
+    force: bool,
+    stop_timeout: Option<u32>,
+) -> Result<String> {
+    let container_name = container_name(dir)?;
+
+    if let Some(seconds) = stop_timeout {
+        run_checked(
+            runtime,
+            &args(&["stop", "-t", &seconds.to_string(), &container_name]),
+            "Failed to stop container",
+        )?;
+    }
+
+    let mut rm_args = vec!["rm"];
+    if force {
+        rm_args.push("-f");
+    }
+    rm_args.push(&container_name);
+
+    run_checked(runtime, &args(&rm_args), "Failed to remove container")?;
+    emit(Event::ContainerRemoved {
+        name: container_name.clone(),
+    });
+
+    Ok(container_name)
+}
+
+/// Label tying containers of one multi-step workflow together; see [`remove_run`].
+pub const RUN_ID_LABEL: &str = "safecrate.run-id";
+
+/// Remove every container labelled with `run_id`, returning their names.
+///
+/// Without `force`, running containers are left alone and reported as an error.
+pub fn remove_run(
+    runtime: &dyn ContainerRuntime,
+    run_id: &str,
+    force: bool,
+) -> Result<Vec<String>> {
+    validate::run_id(run_id)?;
+    let output = runtime.output(&args(&[
+        "ps",
+        "-a",
+        "--filter",
+        &format!("label={}={}", RUN_ID_LABEL, run_id),
+        "--format",
+        "{{.Names}}",
+    ]))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to list containers: {}",
+            output.stderr.trim()
+        ));
+    }
+    let names: Vec<String> = output.stdout.lines().map(str::to_string).collect();
+    if names.is_empty() {
+        return Ok(names);
+    }
+
+    let mut rm_args = args(&["rm"]);
+    if force {
+        rm_args.push("-f".into());
+    }
+    rm_args.extend(names.iter().cloned());
+    run_checked(runtime, &rm_args, "Failed to remove containers")?;
+    for name in &names {
+        emit(Event::ContainerRemoved { name: name.clone() });
+    }
+    Ok(names)
+}
+
+/// A container named with the default `{project}_isolated` template, and its name under
+/// the current [`name_template`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    pub from: String,
+    pub to: String,
+    /// A container already has the new name, so this one keeps its old one.
+    pub conflict: bool,
+}
+
+impl fmt::Display for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.from, self.to)
+    }
+}
+
+/// Rename containers from the default `{project}_isolated` names to the current
+/// [`name_template`], so commands find them again after switching templates. With
+/// `dry_run`, only list what would be renamed.
+///
+/// Only containers with safecrate's [`ORIGIN_LABEL`] are renamed; others that merely
+/// share the naming scheme aren't safecrate's to touch. Docker can't change a container's
+/// labels after creating it, so migrated containers keep theirs; `safecrate recreate`
+/// gives one the current labels.
+pub fn migrate(runtime: &dyn ContainerRuntime, dry_run: bool) -> Result<Vec<Migration>> {
+    migrate_to(runtime, name_template(), dry_run)
+}
+
+fn migrate_to(
+    runtime: &dyn ContainerRuntime,
+    template: &NameTemplate,
+    dry_run: bool,
+) -> Result<Vec<Migration>> {
+    let legacy = NameTemplate::default();
+    if *template == legacy {
+        return Ok(Vec::new());
+    }
+    let output = runtime.output(&args(&[
+        "ps",
+        "-a",
+        "--format",
+        &format!("{{{{.Names}}}}\t{{{{.Label \"{}\"}}}}", ORIGIN_LABEL),
+    ]))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to list containers: {}",
+            output.stderr.trim()
+        ));
+    }
+    let containers: Vec<(&str, bool)> = output
+        .stdout
+        .lines()
+        .map(|line| {
+            let (name, origin) = line.split_once('\t').unwrap_or((line, ""));
+            (name.trim(), !origin.trim().is_empty())
+        })
+        .collect();
+    let names: Vec<&str> = containers.iter().map(|(name, _)| *name).collect();
+
+    let mut migrations = Vec::new();
+    for &(name, created_by_safecrate) in &containers {
+        // Names that also fit the current template are found already, and may be new.
+        if !created_by_safecrate || !legacy.matches(name) || template.matches(name) {
+            continue;
+        }
+        let project = &name[..name.len() - legacy.suffix.len()];
+        let to = template.render(project);
+        let conflict = names.contains(&to.as_str());
+        if !conflict && !dry_run {
+            run_checked(
+                runtime,
+                &args(&["rename", name, &to]),
+                "Failed to rename container",
+            )?;
+        }
+        migrations.push(Migration {
+            from: name.to_string(),
+            to,
+            conflict,
+        });
+    }
+    Ok(migrations)
+}
+
+/// Lifecycle state of a project's container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerStatus {
+    pub name: String,
+    /// Docker's state, e.g. `running` or `exited`.
+    pub state: String,
+    /// Health check result (`starting`, `healthy`, `unhealthy`), if a health check is defined.
+    pub health: Option<String>,
+}
+
+impl std::fmt::Display for ContainerStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.name, self.state)?;
+        if let Some(health) = &self.health {
+            write!(f, " ({})", health)?;
+        }
+        Ok(())
+    }
+}
+
+/// Report the state and health of the container for the given directory.
+pub fn status(runtime: &dyn ContainerRuntime, dir: &Path) -> Result<ContainerStatus> {
+    let container_name = container_name(dir)?;
+
+    let output = runtime.output(&args(&[
+        "inspect",
+        "--type",
+        "container",
+        "--format",
+        "{{.State.Status}} {{if .State.Health}}{{.State.Health.Status}}{{end}}",
+        &container_name,
+    ]))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "No container {} found for {}.",
+            container_name,
+            dir.display()
+        ));
+    }
+
+    let mut fields = output.stdout.split_whitespace();
+    let state = fields.next().unwrap_or("unknown").to_string();
+    let health = fields.next().map(str::to_string);
+
+    Ok(ContainerStatus {
+        name: container_name,
+        state,
+        health,
+    })
+}
+
+/// A safecrate container, as shown by `safecrate list`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerSummary {
+    pub name: String,
+    /// Docker's state, e.g. `running` or `exited`.
+    pub state: String,
+    /// Creation time as reported by Docker, e.g. `2024-05-01 10:20:30 +0200 CEST`.
+    pub created: String,
+    /// The Git revision the project was at, from the container's labels.
+    pub revision: Option<GitRevision>,
+}
+
+impl std::fmt::Display for ContainerSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:<32} {:<10} {}", self.name, self.state, self.created)?;
+        if let Some(revision) = &self.revision {
+            write!(f, "  {}", revision.short_commit())?;
+            if let Some(branch) = &revision.branch {
+                write!(f, " ({})", branch)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// List safecrate containers, optionally only those created within `since` of now.
+///
+/// Containers whose creation time can't be parsed are left out when filtering.
+pub fn list(
+    runtime: &dyn ContainerRuntime,
+    since: Option<Duration>,
+) -> Result<Vec<ContainerSummary>> {
+    let template = name_template();
+    // Docker matches the filter anywhere in the name, so it only narrows the list down.
+    let filter = if template.prefix.len() > template.suffix.len() {
+        &template.prefix
+    } else {
+        &template.suffix
+    };
+    let output = runtime.output(&args(&[
+        "ps",
+        "-a",
+        "--filter",
+        &format!("name={}", filter),
+        "--format",
+        &format!(
+            "{{{{.Names}}}}\t{{{{.State}}}}\t{{{{.CreatedAt}}}}\t{{{{.Label \"{}\"}}}}\t{{{{.Label \"{}\"}}}}",
+            GIT_COMMIT_LABEL, GIT_BRANCH_LABEL
+        ),
+    ]))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to list containers: {}",
+            output.stderr.trim()
+        ));
+    }
+
+    let cutoff = since.map(|since| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        now.as_secs() as i64 - since.as_secs() as i64
+    });
+
+    Ok(output
+        .stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(5, '\t');
+            let name = fields.next()?.trim();
+            if !template.matches(name) {
+                return None;
+            }
+            let state = fields.next().unwrap_or("unknown").trim().to_string();
+            let created = fields.next().unwrap_or_default().trim().to_string();
+            let revision = GitRevision::from_labels(
+                fields.next().unwrap_or_default(),
+                fields.next().unwrap_or_default(),
+            );
+            Some(ContainerSummary {
+                name: name.to_string(),
+                state,
+                created,
+                revision,
+            })
+        })
+        .filter(|summary| match cutoff {
+            Some(cutoff) => parse_created(&summary.created).is_some_and(|t| t >= cutoff),
+            None => true,
+        })
+        .collect())
+}
+
+/// Parse Docker's `CreatedAt` (`YYYY-MM-DD HH:MM:SS +HHMM ZONE`) into Unix seconds.
+fn parse_created(created: &str) -> Option<i64> {
+    let mut fields = created.split_whitespace();
+    let date: Vec<i64> = parse_numbers(fields.next()?, '-')?;
+    let time: Vec<i64> = parse_numbers(fields.next()?, ':')?;
+    let (&[year, month, day], &[hour, minute, second]) = (date.as_slice(), time.as_slice()) else {
+        return None;
+    };
+    let offset = fields.next()?;
+    let sign = match offset.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let hours: i64 = offset.get(1..3)?.parse().ok()?;
+    let minutes: i64 = offset.get(3..5)?.parse().ok()?;
+
+    let days = days_from_civil(year, month, day);
+    Some(
+        days * 86_400 + hour * 3_600 + minute * 60 + second - sign * (hours * 3_600 + minutes * 60),
+    )
+}
+
+fn parse_numbers(s: &str, separator: char) -> Option<Vec<i64>> {
+    s.split(separator).map(|n| n.parse().ok()).collect()
+}
+
+/// Days since the Unix epoch for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Placeholder in a [`NameTemplate`] for the project directory's basename.
+const PROJECT_PLACEHOLDER: &str = "{project}";
+
+/// How container names are formed from the project's basename, e.g. `sc-{project}`.
+///
+/// Holds exactly one `{project}`; the rest may only use characters Docker allows in names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    prefix: String,
+    suffix: String,
+}
+
+impl Default for NameTemplate {
+    /// `{project}_isolated`.
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            suffix: format!("_{}", CONTAINER_SUFFIX),
+        }
+    }
+}
+
+impl NameTemplate {
+    /// The container name for a project.
+    pub fn render(&self, project: &str) -> String {
+        format!("{}{}{}", self.prefix, project, self.suffix)
+    }
+
+    /// Whether `name` could have come from this template.
+    pub fn matches(&self, name: &str) -> bool {
+        name.len() > self.prefix.len() + self.suffix.len()
+            && name.starts_with(&self.prefix)
+            && name.ends_with(&self.suffix)
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((prefix, suffix)) = s.split_once(PROJECT_PLACEHOLDER) else {
+            return Err(anyhow!(
+                "Name template '{}' must contain {}",
+                s,
+                PROJECT_PLACEHOLDER
+            ));
+        };
+        let literal = format!("{}{}", prefix, suffix);
+        if let Some(c) = literal
+            .chars()
+            .find(|c| !c.is_ascii_alphanumeric() && !"_.-".contains(*c))
+        {
+            return Err(anyhow!(
+                "Name template '{}' contains '{}'; besides {} container names may only use letters, digits, _, . and -",
+                s,
+                c,
+                PROJECT_PLACEHOLDER
+            ));
+        }
+        Ok(Self {
+            prefix: prefix.to_string(),
+            suffix: suffix.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for NameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.prefix, PROJECT_PLACEHOLDER, self.suffix)
+    }
+}
+
+static NAME_TEMPLATE: OnceLock<NameTemplate> = OnceLock::new();
+
+/// Name containers with `template` instead of the default `{project}_isolated`.
+///
+/// Only the first call has an effect, so make it before any container is named.
+pub fn set_name_template(template: NameTemplate) {
+    let _ = NAME_TEMPLATE.set(template);
+}
+
+/// The template containers are named with.
+pub fn name_template() -> &'static NameTemplate {
+    NAME_TEMPLATE.get_or_init(NameTemplate::default)
+}
+
+/// The basename of the project directory.
+fn project_name(dir: &Path) -> Result<String> {
+    let abs_dir = std::fs::canonicalize(dir)?;
+    abs_dir
+        .file_name()
+        .and_then(|s| s.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Invalid directory name: {}", dir.display()))
+}
+
+/// A new name for the project's container, so a fresh one can take its name while it stays
+/// around, e.g. `app-stopped-1700000000_isolated`. Still matches [`name_template`], so
+/// `list` shows it.
+pub(crate) fn set_aside_name(dir: &Path) -> Result<String> {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Ok(name_template().render(&format!("{}-stopped-{}", project_name(dir)?, stamp)))
+}
+
+/// Get the container name from a directory path, using [`name_template`].
+pub fn container_name(dir: &Path) -> Result<String> {
+    Ok(name_template().render(&project_name(dir)?))
+}
+
+/// Label on a [`cache_volume_name`] volume holding the project directory it belongs to.
+pub const CACHE_PROJECT_LABEL: &str = "safecrate.project";
+
+/// Name of the project's persistent cache volume, e.g. `safecrate_cache_app_1f2e3d4c5b6a7988`.
+///
+/// The suffix is an FNV-1a hash of the canonical path, so projects sharing a basename get
+/// separate caches and the name stays the same across safecrate versions.
+pub fn cache_volume_name(dir: &Path) -> Result<String> {
+    let abs_dir = std::fs::canonicalize(dir)?;
+    let hash = abs_dir
+        .to_string_lossy()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    let project: String = abs_dir
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || "_.-".contains(*c))
+        .take(40)
+        .collect();
+    Ok(format!("safecrate_cache_{}_{:016x}", project, hash))
+}
+
+/// The project's basename as a hostname: lowercase letters, digits and inner `-`, at
+/// most 63 characters, or `safecrate` if nothing is left.
+pub(crate) fn project_hostname(dir: &Path) -> Result<String> {
+    let project_name = project_name(dir)?;
+    let mut hostname: String = project_name
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9') => c,
+            _ => '-',
+        })
+        .collect();
+    hostname.truncate(63);
+    let hostname = hostname.trim_matches('-');
+    Ok(if hostname.is_empty() {
+        "safecrate".to_string()
+    } else {
+        hostname.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{MockRuntime, Output};
+    use crate::testutil::project_dir;
+
+    #[test]
+    fn container_name_uses_basename() {
+        let dir = project_dir("name-basename");
+        assert_eq!(container_name(&dir).unwrap(), "name-basename_isolated");
+    }
+
+    #[test]
+    fn name_templates_wrap_the_project() {
+        let template: NameTemplate = "sc-{project}-sandbox".parse().unwrap();
+        assert_eq!(template.render("app"), "sc-app-sandbox");
+        assert!(template.matches("sc-app-sandbox"));
+        assert!(!template.matches("sc--sandbox"));
+        assert!(!template.matches("app_isolated"));
+        assert!("{project}-{project}".parse::<NameTemplate>().is_err());
+        assert_eq!(template.to_string(), "sc-{project}-sandbox");
+        assert_eq!(NameTemplate::default().to_string(), "{project}_isolated");
+        assert!("sandbox".parse::<NameTemplate>().is_err());
+        assert!("sc/{project}".parse::<NameTemplate>().is_err());
+    }
+
+    #[test]
+    fn cache_volumes_are_per_path() {
+        let a = project_dir("cache-a");
+        let name = cache_volume_name(&a).unwrap();
+        assert!(name.starts_with("safecrate_cache_cache-a_"));
+        assert_eq!(name.len(), "safecrate_cache_cache-a_".len() + 16);
+        assert_eq!(cache_volume_name(&a).unwrap(), name);
+
+        let nested = a.join("cache-a");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert_ne!(cache_volume_name(&nested).unwrap(), name);
+    }
+
+    #[test]
+    fn top_requires_running_container() {
+        let dir = project_dir("top-running");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("running\n"));
+        top(&runtime, &dir, false).unwrap();
+        let calls = runtime.calls();
+        assert_eq!(calls[1][..2], ["stats", "--no-stream"]);
+        assert_eq!(calls[1].last().unwrap(), "top-running_isolated");
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("exited\n"));
+        let err = top(&runtime, &dir, true).unwrap_err();
+        assert!(err.to_string().contains("is exited, not running"));
+        assert_eq!(runtime.calls().len(), 1);
+    }
+
+    #[test]
+    fn freeze_and_unfreeze_check_state() {
+        let dir = project_dir("freeze");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("running\n"));
+        assert_eq!(freeze(&runtime, &dir).unwrap(), "freeze_isolated");
+        assert_eq!(runtime.calls()[1], ["pause", "freeze_isolated"]);
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("running\n"));
+        let err = unfreeze(&runtime, &dir).unwrap_err();
+        assert!(err.to_string().contains("is running, not paused"));
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("paused\n"));
+        unfreeze(&runtime, &dir).unwrap();
+        assert_eq!(runtime.calls()[1], ["unpause", "freeze_isolated"]);
+    }
+
+    #[test]
+    fn kill_validates_signal() {
+        let dir = project_dir("kill");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("running\n"));
+        kill(&runtime, &dir, "int").unwrap();
+        assert_eq!(
+            runtime.calls()[1],
+            ["kill", "--signal", "SIGINT", "kill_isolated"]
+        );
+
+        let runtime = MockRuntime::new();
+        assert!(kill(&runtime, &dir, "SIGNOPE").is_err());
+        assert!(runtime.calls().is_empty());
+    }
+
+    #[test]
+    fn project_hostname_is_sanitized() {
+        let dir = project_dir("Host_Name.v2");
+        assert_eq!(project_hostname(&dir).unwrap(), "host-name-v2");
+
+        let dir = project_dir("__");
+        assert_eq!(project_hostname(&dir).unwrap(), "safecrate");
+
+        let dir = project_dir(&"a".repeat(70));
+        assert_eq!(project_hostname(&dir).unwrap().len(), 63);
+    }
+
+    #[test]
+    fn resume_starts_existing_container() {
+        let dir = project_dir("resume-existing");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("resume-existing_isolated\n"));
+
+        resume(&runtime, &dir, None).unwrap();
+
+        let calls = runtime.calls();
+        assert_eq!(
+            calls[0],
+            args(&[
+                "ps",
+                "-a",
+                "--filter",
+                "name=resume-existing_isolated",
+                "--format",
+                "{{.Names}}"
+            ])
+        );
+        assert_eq!(
+            calls[1],
+            args(&[
+                "start",
+                "-ai",
+                "--detach-keys",
+                "ctrl-_,ctrl-q",
+                "resume-existing_isolated"
+            ])
+        );
+    }
+
+    #[test]
+    fn resume_cmd_starts_container_then_execs() {
+        let dir = project_dir("resume-cmd");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("exited\n"));
+
+        resume_cmd(&runtime, &dir, Some("ctrl-x,x"), "cargo test").unwrap();
+
+        let calls = runtime.calls();
+        assert_eq!(calls[1], args(&["start", "resume-cmd_isolated"]));
+        assert_eq!(
+            calls[2],
+            args(&[
+                "exec",
+                "-it",
+                "--detach-keys",
+                "ctrl-x,x",
+                "-w",
+                "/workspace",
+                "resume-cmd_isolated",
+                "sh",
+                "-c",
+                "cargo test"
+            ])
+        );
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("running\n"));
+        resume_cmd(&runtime, &dir, None, "cargo test").unwrap();
+        assert_eq!(runtime.calls()[1][0], "exec");
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::failure(1, "No such container"));
+        assert!(resume_cmd(&runtime, &dir, None, "cargo test").is_err());
+        assert_eq!(runtime.calls().len(), 1);
+    }
+
+    #[test]
+    fn resume_ignores_containers_with_similar_names() {
+        let dir = project_dir("resume-similar");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("other-resume-similar_isolated\n"));
+
+        assert!(resume(&runtime, &dir, None).is_err());
+    }
+
+    #[test]
+    fn resume_errors_without_container() {
+        let dir = project_dir("resume-missing");
+        let runtime = MockRuntime::new();
+
+        let err = resume(&runtime, &dir, None).unwrap_err();
+        assert!(err.to_string().contains("No existing container"));
+        assert_eq!(runtime.calls().len(), 1);
+    }
+
+    #[test]
+    fn status_reports_health() {
+        let dir = project_dir("status-health");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("running healthy\n"));
+        runtime.respond(Output::success("exited \n"));
+
+        let healthy = status(&runtime, &dir).unwrap();
+        assert_eq!(
+            healthy.to_string(),
+            "status-health_isolated: running (healthy)"
+        );
+
+        let exited = status(&runtime, &dir).unwrap();
+        assert_eq!(exited.state, "exited");
+        assert_eq!(exited.health, None);
+    }
+
+    #[test]
+    fn remove_passes_force() {
+        let dir = project_dir("remove-force");
+        let runtime = MockRuntime::new();
+
+        remove(&runtime, &dir, false, None).unwrap();
+        remove(&runtime, &dir, true, None).unwrap();
+
+        assert_eq!(
+            runtime.calls(),
+            vec![
+                args(&["rm", "remove-force_isolated"]),
+                args(&["rm", "-f", "remove-force_isolated"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn remove_reports_docker_failure() {
+        let dir = project_dir("remove-failure");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::failure(1, "No such container"));
+
+        let err = remove(&runtime, &dir, false, None).unwrap_err();
+        assert!(err.to_string().contains("Failed to remove container"));
+    }
+
+    #[test]
+    fn remove_stops_gracefully_with_timeout() {
+        let dir = project_dir("remove-stop-timeout");
+        let runtime = MockRuntime::new();
+
+        remove(&runtime, &dir, true, Some(2)).unwrap();
+
+        assert_eq!(
+            runtime.calls(),
+            vec![
+                args(&["stop", "-t", "2", "remove-stop-timeout_isolated"]),
+                args(&["rm", "-f", "remove-stop-timeout_isolated"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_docker_created_at() {
+        assert_eq!(parse_created("1970-01-01 00:00:00 +0000 UTC"), Some(0));
+        assert_eq!(
+            parse_created("2024-05-01 12:20:30 +0200 CEST"),
+            Some(1_714_558_830)
+        );
+        assert_eq!(parse_created("yesterday"), None);
+    }
+
+    #[test]
+    fn list_filters_by_suffix_and_age() {
+        let runtime = MockRuntime::new();
+        let listing = "old_isolated\texited\t2000-01-01 00:00:00 +0000 UTC\t\t\n\
+                       new_isolated\trunning\t2999-01-01 00:00:00 +0000 UTC\t\
+                       0123456789abcdef0123456789abcdef01234567\tmain\n\
+                       unrelated\trunning\t2999-01-01 00:00:00 +0000 UTC\n";
+        runtime.respond(Output::success(listing));
+        runtime.respond(Output::success(listing));
+
+        let all = list(&runtime, None).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].state, "exited");
+
+        let recent = list(&runtime, Some(Duration::from_secs(7_200))).unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].name, "new_isolated");
+        assert!(recent[0].to_string().ends_with("  0123456789ab (main)"));
+        assert_eq!(all[0].revision, None);
+    }
+
+    #[test]
+    fn migrates_default_names_to_the_template() {
+        let template: NameTemplate = "sc-{project}".parse().unwrap();
+        let listing = "app_isolated\tsafecrate open app\n\
+                       lib_isolated\tsafecrate open lib\n\
+                       sc-lib\tsafecrate open lib\n\
+                       sc-new_isolated\tsafecrate open new\n\
+                       foo_isolated\t\n\
+                       unrelated\t\n";
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(listing));
+        runtime.respond(Output::success(listing));
+
+        let planned = migrate_to(&runtime, &template, true).unwrap();
+        assert_eq!(
+            planned.iter().map(|m| m.to_string()).collect::<Vec<_>>(),
+            ["app_isolated -> sc-app", "lib_isolated -> sc-lib"]
+        );
+        assert!(planned[1].conflict);
+        assert_eq!(runtime.calls().len(), 1);
+
+        migrate_to(&runtime, &template, false).unwrap();
+        assert_eq!(runtime.calls()[2], ["rename", "app_isolated", "sc-app"]);
+        // foo_isolated has no origin label, so it isn't safecrate's to rename.
+        assert_eq!(runtime.calls().len(), 3);
+
+        assert!(migrate_to(&runtime, &NameTemplate::default(), false)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn removes_containers_of_a_run() {
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("build_isolated\ntest_isolated\n"));
+        let removed = remove_run(&runtime, "nightly-7", true).unwrap();
+        assert_eq!(removed, ["build_isolated", "test_isolated"]);
+        let calls = runtime.calls();
+        assert_eq!(calls[0][3], "label=safecrate.run-id=nightly-7");
+        assert_eq!(calls[1], ["rm", "-f", "build_isolated", "test_isolated"]);
+
+        assert!(remove_run(&runtime, "nothing-left", false)
+            .unwrap()
+            .is_empty());
+        assert_eq!(runtime.calls().len(), 3);
+        assert!(remove_run(&runtime, "a b", false).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! Staging a filtered copy of the project for copy-in (`--no-mount`) sandboxes.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ignore::IgnoreRules;
+
+/// A temporary directory holding the files to copy into the container.
+#[derive(Debug)]
+pub(crate) struct Staging {
+    parent: PathBuf,
+    path: PathBuf,
+}
+
+impl Staging {
+    /// Copy `source` into a fresh temporary directory, skipping ignored paths.
+    ///
+    /// Symlinks are recreated as symlinks rather than followed, so a link pointing
+    /// outside the project doesn't pull host files into the container.
+    pub(crate) fn create(source: &Path, rules: &IgnoreRules, name: &str) -> Result<Self> {
+        // The files go one level down, so `docker cp` doesn't give `/workspace` the
+        // private parent's 0700 mode.
+        let parent = private_temp_dir("safecrate-stage")?;
+        let path = parent.join(name);
+        let staging = Self { parent, path };
+        fs::create_dir(&staging.path)?;
+        copy_dir(source, &staging.path, "", rules)
+            .with_context(|| format!("Failed to stage {} for copying", source.display()))?;
+        Ok(staging)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.parent);
+    }
+}
+
+/// Create a new directory under the system temp dir that only the current user can
+/// enter, named `<prefix>-<random>`.
+///
+/// The temp dir is shared with other local users, so a predictable name could be
+/// created first, or swapped for a symlink, by someone else. The directory is never
+/// reused: creating it fails if the path already exists.
+pub fn private_temp_dir(prefix: &str) -> Result<PathBuf> {
+    use std::hash::{BuildHasher, Hasher};
+    let state = std::collections::hash_map::RandomState::new();
+    for attempt in 0u32.. {
+        let mut hasher = state.build_hasher();
+        hasher.write_u32(std::process::id());
+        hasher.write_u32(attempt);
+        let path = std::env::temp_dir().join(format!("{}-{:016x}", prefix, hasher.finish()));
+        match private_dir_builder().create(&path) {
+            Ok(()) => return Ok(path),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists && attempt < 16 => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to create {}", path.display()))
+            }
+        }
+    }
+    unreachable!()
+}
+
+#[cfg(unix)]
+fn private_dir_builder() -> fs::DirBuilder {
+    use std::os::unix::fs::DirBuilderExt;
+    let mut builder = fs::DirBuilder::new();
+    builder.mode(0o700);
+    builder
+}
+
+#[cfg(not(unix))]
+fn private_dir_builder() -> fs::DirBuilder {
+    fs::DirBuilder::new()
+}
+
+pub(crate) fn copy_dir(from: &Path, to: &Path, relative: &str, rules: &IgnoreRules) -> Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let name = file_name.to_string_lossy();
+        let child = if relative.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", relative, name)
+        };
+        let file_type = entry.file_type()?;
+        if rules.is_ignored(&child, file_type.is_dir()) {
+            continue;
+        }
+
+        let target = to.join(&file_name);
+        if file_type.is_symlink() {
+            copy_symlink(&entry.path(), &target)?;
+        } else if file_type.is_dir() {
+            fs::create_dir(&target)?;
+            copy_dir(&entry.path(), &target, &child, rules)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(link: &Path, target: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(link)?, target)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(_link: &Path, _target: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::project_dir;
+
+    #[test]
+    fn staging_skips_ignored_paths() {
+        let dir = project_dir("copy-in-stage");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("target/debug")).unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(dir.join("target/debug/app"), "binary").unwrap();
+        fs::write(dir.join("notes.log"), "log").unwrap();
+
+        let rules = IgnoreRules::parse("target/\n*.log\n");
+        let staging = Staging::create(&dir, &rules, "copy-in-stage").unwrap();
+
+        assert!(staging.path().join("src/main.rs").exists());
+        assert!(!staging.path().join("target").exists());
+        assert!(!staging.path().join("notes.log").exists());
+
+        let path = staging.path().to_path_buf();
+        assert_eq!(path.file_name().unwrap(), "copy-in-stage");
+        drop(staging);
+        assert!(!path.parent().unwrap().exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn temp_dirs_are_private_and_fresh() {
+        use std::os::unix::fs::PermissionsExt;
+        let first = private_temp_dir("safecrate-test-private").unwrap();
+        let second = private_temp_dir("safecrate-test-private").unwrap();
+        assert_ne!(first, second);
+        let mode = fs::metadata(&first).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        fs::remove_dir(first).unwrap();
+        fs::remove_dir(second).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn staging_preserves_symlinks_without_following() {
+        let dir = project_dir("copy-in-symlink");
+        let link = dir.join("escape");
+        let _ = fs::remove_file(&link);
+        std::os::unix::fs::symlink("/etc", &link).unwrap();
+
+        let staging = Staging::create(&dir, &IgnoreRules::default(), "copy-in-symlink").unwrap();
+
+        let copied = staging.path().join("escape");
+        assert!(fs::symlink_metadata(&copied)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_link(copied).unwrap(), Path::new("/etc"));
+    }
+}
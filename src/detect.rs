@@ -0,0 +1,87 @@
+//! Project type detection for `--auto-image`.
+
+use anyhow::{anyhow, Error, Result};
+use std::path::Path;
+use std::str::FromStr;
+
+/// A kind of project recognised by its marker file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectKind {
+    Rust,
+    Node,
+    Python,
+}
+
+/// Order in which project kinds are tried when none is configured.
+pub const DEFAULT_DETECT_ORDER: [ProjectKind; 3] =
+    [ProjectKind::Rust, ProjectKind::Node, ProjectKind::Python];
+
+impl ProjectKind {
+    /// File at the project root that identifies this kind of project.
+    pub fn marker(self) -> &'static str {
+        match self {
+            ProjectKind::Rust => "Cargo.toml",
+            ProjectKind::Node => "package.json",
+            ProjectKind::Python => "pyproject.toml",
+        }
+    }
+
+    /// Image used for this kind of project, e.g. `safecrate_rust`.
+    pub fn image(self) -> String {
+        format!("safecrate_{}", self)
+    }
+}
+
+impl FromStr for ProjectKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rust" => Ok(ProjectKind::Rust),
+            "node" => Ok(ProjectKind::Node),
+            "python" => Ok(ProjectKind::Python),
+            _ => Err(anyhow!(
+                "Invalid project kind '{}': expected rust, node or python",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ProjectKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ProjectKind::Rust => "rust",
+            ProjectKind::Node => "node",
+            ProjectKind::Python => "python",
+        })
+    }
+}
+
+/// The first kind in `order` whose marker file exists in `dir`.
+pub fn detect(dir: &Path, order: &[ProjectKind]) -> Option<ProjectKind> {
+    order
+        .iter()
+        .copied()
+        .find(|kind| dir.join(kind.marker()).is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::project_dir;
+
+    #[test]
+    fn detection_follows_order() {
+        let dir = project_dir("detect-order");
+        std::fs::write(dir.join("Cargo.toml"), "").unwrap();
+        std::fs::write(dir.join("pyproject.toml"), "").unwrap();
+
+        assert_eq!(detect(&dir, &DEFAULT_DETECT_ORDER), Some(ProjectKind::Rust));
+        assert_eq!(
+            detect(&dir, &[ProjectKind::Python, ProjectKind::Rust]),
+            Some(ProjectKind::Python)
+        );
+        assert_eq!(detect(&dir, &[ProjectKind::Node]), None);
+    }
+}
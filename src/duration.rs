@@ -0,0 +1,63 @@
+use anyhow::{anyhow, Error, Result};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A duration written as a number with an `s`, `m`, `h` or `d` suffix, e.g. `90s` or `2h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HumanDuration(pub Duration);
+
+impl FromStr for HumanDuration {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid duration '{}': expected e.g. 30s, 15m, 2h or 7d", s);
+        let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let (number, unit) = s.split_at(split);
+        let number: u64 = number.parse().map_err(|_| invalid())?;
+        let seconds = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let total = number.checked_mul(seconds).ok_or_else(invalid)?;
+        Ok(HumanDuration(Duration::from_secs(total)))
+    }
+}
+
+impl std::fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = self.0.as_secs();
+        for (unit, size) in [("d", 86_400), ("h", 3_600), ("m", 60)] {
+            if secs != 0 && secs.is_multiple_of(size) {
+                return write!(f, "{}{}", secs / size, unit);
+            }
+        }
+        write!(f, "{}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_suffixes() {
+        let parse = |s: &str| s.parse::<HumanDuration>().map(|d| d.0.as_secs());
+        assert_eq!(parse("45s").unwrap(), 45);
+        assert_eq!(parse("15m").unwrap(), 900);
+        assert_eq!(parse("2h").unwrap(), 7_200);
+        assert_eq!(parse("7d").unwrap(), 604_800);
+        assert!(parse("2").is_err());
+        assert!(parse("h").is_err());
+        assert!(parse("2w").is_err());
+        assert!(parse("-1h").is_err());
+    }
+
+    #[test]
+    fn displays_largest_whole_unit() {
+        assert_eq!(HumanDuration(Duration::from_secs(7_200)).to_string(), "2h");
+        assert_eq!(HumanDuration(Duration::from_secs(90)).to_string(), "90s");
+    }
+}
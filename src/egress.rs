@@ -0,0 +1,254 @@
+//! Spotting the first packet a sandbox sends out, in its network capture, to freeze it
+//! there (`open --freeze-on-network`).
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::runtime::{args, run_checked, ContainerRuntime};
+use crate::style::Marker;
+
+/// How often the capture file is read for new packets.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Linux "cooked" captures, which `tcpdump -i any` writes.
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+/// Packet type of a packet the capturing host sent.
+const PACKET_OUTGOING: u8 = 4;
+
+/// A packet the sandbox sent: its protocol and where to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Egress {
+    /// `TCP`, `UDP`, `ICMP` or the IP protocol number.
+    pub protocol: String,
+    pub destination: IpAddr,
+    /// For TCP and UDP.
+    pub port: Option<u16>,
+}
+
+impl fmt::Display for Egress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.port {
+            Some(port) => write!(
+                f,
+                "{} to {}",
+                self.protocol,
+                SocketAddr::new(self.destination, port)
+            ),
+            None => write!(f, "{} to {}", self.protocol, self.destination),
+        }
+    }
+}
+
+/// The first outgoing IP packet in a pcap file written so far, skipping loopback and
+/// multicast traffic such as IPv6 neighbour discovery. A record still being written is
+/// ignored until it's complete.
+pub fn first_egress(pcap: &[u8]) -> Option<Egress> {
+    let magic: [u8; 4] = pcap.get(..4)?.try_into().ok()?;
+    // Microsecond and nanosecond timestamps, in either byte order.
+    let read: fn([u8; 4]) -> u32 = match u32::from_le_bytes(magic) {
+        0xa1b2_c3d4 | 0xa1b2_3c4d => u32::from_le_bytes,
+        0xd4c3_b2a1 | 0x4d3c_b2a1 => u32::from_be_bytes,
+        _ => return None,
+    };
+    let u32_at = |bytes: &[u8], at: usize| Some(read(bytes.get(at..at + 4)?.try_into().ok()?));
+    // The upper bits of the link type describe frame check sequences.
+    let linktype = u32_at(pcap, 20)? & 0xffff;
+
+    let mut records = pcap.get(24..)?;
+    while records.len() >= 16 {
+        let length = u32_at(records, 8)? as usize;
+        let packet = records.get(16..16 + length)?;
+        if let Some(egress) = outgoing(linktype, packet) {
+            return Some(egress);
+        }
+        records = &records[16 + length..];
+    }
+    None
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+/// The packet as an [`Egress`], if it's an outgoing IP packet that counts.
+fn outgoing(linktype: u32, packet: &[u8]) -> Option<Egress> {
+    let (packet_type, ethertype, payload) = match linktype {
+        LINKTYPE_LINUX_SLL => (u16_at(packet, 0)?, u16_at(packet, 14)?, packet.get(16..)?),
+        LINKTYPE_LINUX_SLL2 => (
+            *packet.get(10)? as u16,
+            u16_at(packet, 0)?,
+            packet.get(20..)?,
+        ),
+        _ => return None,
+    };
+    if packet_type != PACKET_OUTGOING as u16 {
+        return None;
+    }
+    let (protocol, destination, transport) = match ethertype {
+        0x0800 if payload.first()? >> 4 == 4 => {
+            let header_length = usize::from(payload[0] & 0x0f) * 4;
+            let destination: [u8; 4] = payload.get(16..20)?.try_into().ok()?;
+            (
+                *payload.get(9)?,
+                IpAddr::V4(Ipv4Addr::from(destination)),
+                payload.get(header_length..),
+            )
+        }
+        0x86dd => {
+            let destination: [u8; 16] = payload.get(24..40)?.try_into().ok()?;
+            (
+                *payload.get(6)?,
+                IpAddr::V6(Ipv6Addr::from(destination)),
+                payload.get(40..),
+            )
+        }
+        _ => return None,
+    };
+    if destination.is_loopback() || destination.is_multicast() {
+        return None;
+    }
+    let (protocol, port) = match protocol {
+        6 => ("TCP".to_string(), transport.and_then(|t| u16_at(t, 2))),
+        17 => ("UDP".to_string(), transport.and_then(|t| u16_at(t, 2))),
+        1 | 58 => ("ICMP".to_string(), None),
+        number => (format!("IP protocol {}", number), None),
+    };
+    Some(Egress {
+        protocol,
+        destination,
+        port,
+    })
+}
+
+/// Run `run`, freezing `container` as soon as the capture at `pcap` shows it sending a
+/// packet out. The packet itself has already left by then.
+pub(crate) fn freeze_on_egress<T>(
+    runtime: &dyn ContainerRuntime,
+    container: &str,
+    dir: &Path,
+    pcap: &Path,
+    run: impl FnOnce() -> T,
+) -> T {
+    let done = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            while !done.load(Ordering::Relaxed) {
+                let egress = std::fs::read(pcap)
+                    .ok()
+                    .and_then(|bytes| first_egress(&bytes));
+                if let Some(egress) = egress {
+                    freeze(runtime, container, dir, &egress);
+                    return;
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        });
+        let result = run();
+        done.store(true, Ordering::Relaxed);
+        result
+    })
+}
+
+fn freeze(runtime: &dyn ContainerRuntime, container: &str, dir: &Path, egress: &Egress) {
+    match run_checked(
+        runtime,
+        &args(&["pause", container]),
+        "Failed to freeze container",
+    ) {
+        Ok(()) => eprintln!(
+            "\n{} WARNING: {} sent its first packet out ({}) and is frozen.\n\
+             \tInspect it, then `safecrate unfreeze {dir}` to let it go on or `safecrate remove --force {dir}` to stop it.",
+            Marker::Warning,
+            container,
+            egress,
+            dir = dir.display()
+        ),
+        Err(err) => eprintln!(
+            "\n{} WARNING: {} sent its first packet out ({}), but freezing it failed: {}",
+            Marker::Warning,
+            container,
+            egress,
+            err
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::MockRuntime;
+    use crate::testutil::project_dir;
+
+    /// A little-endian pcap of SLL2 packets, each `(packet type, IPv4 packet)`.
+    fn pcap(packets: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let mut pcap = Vec::new();
+        for field in [
+            0xa1b2_c3d4u32,
+            0x0004_0002,
+            0,
+            0,
+            65535,
+            LINKTYPE_LINUX_SLL2,
+        ] {
+            pcap.extend(field.to_le_bytes());
+        }
+        for (packet_type, ip) in packets {
+            let mut packet = vec![0x08, 0x00, 0, 0, 0, 0, 0, 1, 0, 1, *packet_type, 6];
+            packet.extend([0; 8]);
+            packet.extend(ip);
+            for field in [0, 0, packet.len() as u32, packet.len() as u32] {
+                pcap.extend(field.to_le_bytes());
+            }
+            pcap.extend(packet);
+        }
+        pcap
+    }
+
+    /// An IPv4 TCP header to `destination`:443.
+    fn tcp_to(destination: [u8; 4]) -> Vec<u8> {
+        let mut ip = vec![0x45, 0, 0, 40, 0, 0, 0, 0, 64, 6, 0, 0, 172, 17, 0, 2];
+        ip.extend(destination);
+        ip.extend([0xc3, 0x50, 0x01, 0xbb]);
+        ip
+    }
+
+    #[test]
+    fn finds_first_outgoing_packet() {
+        let capture = pcap(&[
+            (0, tcp_to([172, 17, 0, 2])),
+            (PACKET_OUTGOING, tcp_to([127, 0, 0, 11])),
+            (PACKET_OUTGOING, tcp_to([224, 0, 0, 22])),
+            (PACKET_OUTGOING, tcp_to([93, 184, 216, 34])),
+        ]);
+        let egress = first_egress(&capture).unwrap();
+        assert_eq!(egress.to_string(), "TCP to 93.184.216.34:443");
+
+        // Not written completely yet.
+        assert_eq!(first_egress(&capture[..capture.len() - 1]), None);
+        assert_eq!(first_egress(&capture[..24]), None);
+        assert_eq!(first_egress(b"not a capture"), None);
+    }
+
+    #[test]
+    fn freezes_container_on_egress() {
+        let dir = project_dir("egress-freeze");
+        let capture = dir.join("capture.pcap");
+        std::fs::write(&capture, pcap(&[])).unwrap();
+
+        let runtime = MockRuntime::new();
+        freeze_on_egress(&runtime, "egress-freeze_isolated", &dir, &capture, || {
+            std::fs::write(&capture, pcap(&[(PACKET_OUTGOING, tcp_to([1, 1, 1, 1]))])).unwrap();
+            while runtime.calls().is_empty() {
+                std::thread::yield_now();
+            }
+        });
+        assert_eq!(
+            runtime.calls(),
+            [args(&["pause", "egress-freeze_isolated"])]
+        );
+    }
+}
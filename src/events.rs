@@ -0,0 +1,135 @@
+//! JSON-lines lifecycle events on stdout, for programs that wrap safecrate (`--events`).
+
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::sync::OnceLock;
+
+/// Something that happened to an image or container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    BuildStarted {
+        image: String,
+    },
+    BuildFinished {
+        image: String,
+        success: bool,
+    },
+    /// The container is about to start.
+    ContainerCreated {
+        name: String,
+    },
+    /// The command exited, with `None` for a signal.
+    CommandExited {
+        name: String,
+        code: Option<i32>,
+    },
+    ContainerRemoved {
+        name: String,
+    },
+}
+
+impl Event {
+    /// The event as one line of JSON, e.g. `{"event":"container_removed","name":"app_isolated"}`.
+    pub fn to_json(&self) -> String {
+        let (event, fields) = match self {
+            Event::BuildStarted { image } => ("build_started", vec![("image", json_string(image))]),
+            Event::BuildFinished { image, success } => (
+                "build_finished",
+                vec![
+                    ("image", json_string(image)),
+                    ("success", success.to_string()),
+                ],
+            ),
+            Event::ContainerCreated { name } => {
+                ("container_created", vec![("name", json_string(name))])
+            }
+            Event::CommandExited { name, code } => (
+                "command_exited",
+                vec![
+                    ("name", json_string(name)),
+                    ("code", code.map_or("null".into(), |code| code.to_string())),
+                ],
+            ),
+            Event::ContainerRemoved { name } => {
+                ("container_removed", vec![("name", json_string(name))])
+            }
+        };
+        let mut json = format!("{{\"event\":\"{}\"", event);
+        for (key, value) in fields {
+            write!(json, ",\"{}\":{}", key, value).unwrap();
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// `value` as a JSON string literal.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut json = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+/// Emit events on stdout, moving human-readable output (and the container's) to stderr.
+///
+/// Only the first call has an effect, so make it before printing anything.
+pub fn set_events(enabled: bool) {
+    let _ = ENABLED.set(enabled);
+}
+
+/// Whether events are emitted.
+pub fn events_enabled() -> bool {
+    *ENABLED.get_or_init(|| false)
+}
+
+/// Print `event` if events are enabled.
+pub(crate) fn emit(event: Event) {
+    if events_enabled() {
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(stdout, "{}", event.to_json());
+        let _ = stdout.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_one_object_per_event() {
+        assert_eq!(
+            Event::CommandExited {
+                name: "app_isolated".into(),
+                code: Some(101),
+            }
+            .to_json(),
+            r#"{"event":"command_exited","name":"app_isolated","code":101}"#
+        );
+        assert_eq!(
+            Event::CommandExited {
+                name: "app_isolated".into(),
+                code: None,
+            }
+            .to_json(),
+            r#"{"event":"command_exited","name":"app_isolated","code":null}"#
+        );
+        assert_eq!(
+            Event::BuildStarted {
+                image: "a\"b\\c\n\u{1}".into(),
+            }
+            .to_json(),
+            r#"{"event":"build_started","image":"a\"b\\c\n\u0001"}"#
+        );
+    }
+}
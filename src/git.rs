@@ -0,0 +1,154 @@
+//! The Git revision a project is checked out at, for labelling its container with
+//! (`open --container-label-from-git`).
+//!
+//! `.git` is read directly instead of running `git`: the untrusted repository's own
+//! config could make `git` run commands on the host, e.g. through `core.fsmonitor`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Label holding the commit the project was checked out at.
+pub const GIT_COMMIT_LABEL: &str = "safecrate.git.commit";
+/// Label holding the branch the project was on, unless its HEAD was detached.
+pub const GIT_BRANCH_LABEL: &str = "safecrate.git.branch";
+
+/// Where a checkout's HEAD points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitRevision {
+    pub commit: String,
+    pub branch: Option<String>,
+}
+
+impl GitRevision {
+    /// The revision of the repository containing `dir`, or `None` outside a repository
+    /// or when HEAD can't be resolved (e.g. before the first commit).
+    pub fn of(dir: &Path) -> Option<Self> {
+        // Absolute, so `.` in a subdirectory still has the repository among its ancestors.
+        let dir = fs::canonicalize(dir).ok()?;
+        let git_dir = dir.ancestors().find_map(git_dir)?;
+        let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+        let head = head.trim();
+        let Some(reference) = head.strip_prefix("ref: ") else {
+            return is_object_id(head).then(|| Self {
+                commit: head.to_string(),
+                branch: None,
+            });
+        };
+        // Linked worktrees keep their branches in the main repository.
+        let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+            Ok(common) => git_dir.join(common.trim()),
+            Err(_) => git_dir.clone(),
+        };
+        let commit = [&git_dir, &common_dir]
+            .into_iter()
+            .find_map(|dir| fs::read_to_string(dir.join(reference)).ok())
+            .map(|commit| commit.trim().to_string())
+            .or_else(|| packed_ref(&common_dir, reference))
+            .filter(|commit| is_object_id(commit))?;
+        Some(Self {
+            commit,
+            branch: Some(
+                reference
+                    .strip_prefix("refs/heads/")
+                    .unwrap_or(reference)
+                    .to_string(),
+            ),
+        })
+    }
+
+    /// The revision recorded in a container's labels, `<no value>` or empty where missing.
+    pub(crate) fn from_labels(commit: &str, branch: &str) -> Option<Self> {
+        let commit = commit.trim();
+        let branch = branch.trim();
+        is_object_id(commit).then(|| Self {
+            commit: commit.to_string(),
+            branch: Some(branch)
+                .filter(|b| !b.is_empty() && *b != "<no value>")
+                .map(str::to_string),
+        })
+    }
+
+    /// The first 12 characters of the commit, as in `git log --abbrev=12`.
+    pub fn short_commit(&self) -> &str {
+        &self.commit[..12]
+    }
+}
+
+/// The Git directory of `dir`, following a `.git` file as worktrees and submodules have.
+fn git_dir(dir: &Path) -> Option<PathBuf> {
+    let dot_git = dir.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let pointer = fs::read_to_string(&dot_git).ok()?;
+    let target = pointer.trim().strip_prefix("gitdir: ")?;
+    Some(dir.join(target))
+}
+
+fn packed_ref(git_dir: &Path, reference: &str) -> Option<String> {
+    let packed = fs::read_to_string(git_dir.join("packed-refs")).ok()?;
+    packed.lines().find_map(|line| {
+        let (commit, name) = line.split_once(' ')?;
+        (name == reference).then(|| commit.to_string())
+    })
+}
+
+/// A SHA-1 or SHA-256 object ID.
+fn is_object_id(id: &str) -> bool {
+    matches!(id.len(), 40 | 64) && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::project_dir;
+
+    const COMMIT: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    #[test]
+    fn reads_head_without_running_git() {
+        let dir = project_dir("git-revision");
+        let git = dir.join(".git");
+        fs::create_dir_all(git.join("refs/heads")).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+
+        fs::write(git.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        assert_eq!(GitRevision::of(&dir), None);
+        fs::write(git.join("refs/heads/main"), format!("{}\n", COMMIT)).unwrap();
+        let revision = GitRevision::of(&dir.join("src")).unwrap();
+        assert_eq!(revision.commit, COMMIT);
+        assert_eq!(revision.branch.as_deref(), Some("main"));
+        assert_eq!(revision.short_commit(), "0123456789ab");
+
+        fs::write(git.join("HEAD"), "ref: refs/heads/fix/cve\n").unwrap();
+        fs::write(
+            git.join("packed-refs"),
+            format!("# pack-refs with: peeled\n{} refs/heads/fix/cve\n", COMMIT),
+        )
+        .unwrap();
+        assert_eq!(
+            GitRevision::of(&dir).unwrap().branch.as_deref(),
+            Some("fix/cve")
+        );
+
+        fs::write(git.join("HEAD"), format!("{}\n", COMMIT)).unwrap();
+        assert_eq!(GitRevision::of(&dir).unwrap().branch, None);
+        fs::write(git.join("HEAD"), "$(touch /tmp/pwned)\n").unwrap();
+        assert_eq!(GitRevision::of(&dir), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn finds_the_repository_above_a_non_canonical_dir() {
+        let dir = project_dir("git-non-canonical");
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join(".git/HEAD"), format!("{}\n", COMMIT)).unwrap();
+
+        // Like `.` in src/, the path's own ancestors don't include the repository.
+        let link = project_dir("git-non-canonical-link").join("src");
+        let _ = fs::remove_file(&link);
+        std::os::unix::fs::symlink(dir.join("src"), &link).unwrap();
+        assert_eq!(GitRevision::of(&link).unwrap().commit, COMMIT);
+    }
+}
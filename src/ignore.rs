@@ -0,0 +1,186 @@
+//! Matching for `.safecrateignore` files, using a subset of gitignore syntax.
+//!
+//! Supported: comments, `!` negation, trailing `/` for directories only, patterns
+//! anchored by a leading or inner `/`, and the `*`, `?`, and `**` wildcards.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// File name of the ignore file, looked up in the project root.
+pub const IGNORE_FILE_NAME: &str = ".safecrateignore";
+
+#[derive(Debug, Clone)]
+struct Rule {
+    /// Pattern split on `/`.
+    segments: Vec<String>,
+    negated: bool,
+    dir_only: bool,
+    /// Match against the full relative path rather than any trailing component.
+    anchored: bool,
+}
+
+/// Parsed ignore rules; later rules take precedence, as in gitignore.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    /// Load `.safecrateignore` from the given directory, or no rules if there is none.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(IGNORE_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Self::parse(&content))
+    }
+
+    /// Parse rules from gitignore-style text.
+    pub fn parse(input: &str) -> Self {
+        let rules = input.lines().filter_map(Rule::parse).collect();
+        Self { rules }
+    }
+
+    /// Whether a path, relative to the project root with `/` separators, is ignored.
+    pub fn is_ignored(&self, relative: &str, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            if (!rule.dir_only || is_dir) && rule.matches(relative) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let anchored = pattern.trim_start_matches('/').contains('/') || pattern.starts_with('/');
+        let pattern = pattern.trim_start_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            segments: pattern.split('/').map(str::to_string).collect(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, relative: &str) -> bool {
+        let path: Vec<&str> = relative.split('/').collect();
+        if self.anchored {
+            match_segments(&self.segments, &path)
+        } else {
+            path.last()
+                .is_some_and(|name| glob(&self.segments[0], name))
+        }
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((name, path_rest)) => glob(first, name) && match_segments(rest, path_rest),
+            None => false,
+        },
+    }
+}
+
+/// Match a single path component against a pattern with `*` and `?`.
+fn glob(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unanchored_patterns_match_any_component() {
+        let rules = IgnoreRules::parse("target/\nnode_modules\n*.log\n");
+
+        assert!(rules.is_ignored("target", true));
+        assert!(rules.is_ignored("crates/foo/target", true));
+        assert!(!rules.is_ignored("target", false));
+        assert!(rules.is_ignored("web/node_modules", true));
+        assert!(rules.is_ignored("logs/build.log", false));
+        assert!(!rules.is_ignored("src/main.rs", false));
+    }
+
+    #[test]
+    fn anchored_patterns_and_double_star() {
+        let rules = IgnoreRules::parse("/data\ndocs/*.pdf\nassets/**/raw\n");
+
+        assert!(rules.is_ignored("data", true));
+        assert!(!rules.is_ignored("src/data", true));
+        assert!(rules.is_ignored("docs/spec.pdf", false));
+        assert!(!rules.is_ignored("docs/old/spec.pdf", false));
+        assert!(rules.is_ignored("assets/raw", true));
+        assert!(rules.is_ignored("assets/a/b/raw", true));
+    }
+
+    #[test]
+    fn later_negation_wins() {
+        let rules = IgnoreRules::parse("# comment\n*.bin\n!keep.bin\n");
+
+        assert!(rules.is_ignored("blob.bin", false));
+        assert!(!rules.is_ignored("keep.bin", false));
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(glob("*.rs", "main.rs"));
+        assert!(glob("a*b*c", "aXXbYYc"));
+        assert!(glob("?.txt", "a.txt"));
+        assert!(!glob("?.txt", "ab.txt"));
+        assert!(!glob("*.rs", "main.rsx"));
+    }
+}
@@ -0,0 +1,470 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::events::{emit, Event};
+use crate::progress::{parse_steps, BuildProgress};
+use crate::runtime::{args, run_checked, ContainerRuntime, Docker};
+use crate::DOCKER_IMAGE_NAME;
+
+/// Rust version of the default base image, `rust:<version>-slim`.
+pub const DEFAULT_TOOLCHAIN: &str = "1.88.0";
+
+/// Builder for the safecrate base image (by default Rust + Neovim).
+#[derive(Debug, Clone, Default)]
+pub struct Image {
+    dockerfile: Option<PathBuf>,
+    tag: Option<String>,
+    from: Option<String>,
+    toolchain: Option<String>,
+    digest: Option<String>,
+    packages: Vec<String>,
+    progress: BuildProgress,
+}
+
+impl Image {
+    /// Create a builder for the default embedded Dockerfile.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a custom Dockerfile instead of the embedded template.
+    pub fn dockerfile(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dockerfile = Some(path.into());
+        self
+    }
+
+    /// Tag the image with another name (default: [`DOCKER_IMAGE_NAME`]).
+    ///
+    /// Tagging e.g. `safecrate_node` makes the image available to `--auto-image`.
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    /// Build the embedded template on another Debian-based image, e.g. `rust:1.90-bookworm`.
+    pub fn from(mut self, image: impl Into<String>) -> Self {
+        self.from = Some(image.into());
+        self
+    }
+
+    /// Use another Rust version for the default base image (default: [`DEFAULT_TOOLCHAIN`]).
+    pub fn toolchain(mut self, version: impl Into<String>) -> Self {
+        self.toolchain = Some(version.into());
+        self
+    }
+
+    /// Pin the base image to a digest (`sha256:…`), so every build starts from the same
+    /// image whatever its tag points to now.
+    pub fn from_digest(mut self, digest: impl Into<String>) -> Self {
+        self.digest = Some(digest.into());
+        self
+    }
+
+    /// Problems worth telling the user about before building.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let tagged = self.toolchain.is_some() || self.from.as_deref().is_some_and(has_tag);
+        if self.digest.is_some() && tagged {
+            warnings.push(
+                "Both a base image tag and --from-digest are given; Docker ignores the tag and builds from the digest."
+                    .to_string(),
+            );
+        }
+        warnings
+    }
+
+    /// Install an extra Debian package in the embedded template, e.g. `ripgrep`.
+    pub fn package(mut self, name: impl Into<String>) -> Self {
+        self.packages.push(name.into());
+        self
+    }
+
+    /// How to show build progress (default: Docker's own, [`BuildProgress::Auto`]).
+    pub fn progress(mut self, progress: BuildProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Whether any option that only applies to the embedded template is set.
+    fn customizes_template(&self) -> bool {
+        self.from.is_some()
+            || self.toolchain.is_some()
+            || self.digest.is_some()
+            || !self.packages.is_empty()
+    }
+
+    /// The embedded template with its placeholders filled in.
+    fn render(&self) -> Result<String> {
+        let from = match (&self.from, &self.toolchain) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!(
+                    "A toolchain version picks the rust image's tag; it can't be combined with another base image"
+                ))
+            }
+            (Some(image), None) => image.clone(),
+            (None, toolchain) => {
+                let toolchain = toolchain.as_deref().unwrap_or(DEFAULT_TOOLCHAIN);
+                if !toolchain
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || ".-".contains(c))
+                {
+                    return Err(anyhow!(
+                        "Invalid toolchain '{}': expected a version such as 1.88.0",
+                        toolchain
+                    ));
+                }
+                format!("rust:{}-slim", toolchain)
+            }
+        };
+        if from.is_empty() || from.contains(char::is_whitespace) {
+            return Err(anyhow!("Invalid base image '{}'", from));
+        }
+        let from = match &self.digest {
+            Some(_) if from.contains('@') => {
+                return Err(anyhow!(
+                    "Base image '{}' is already pinned to a digest; drop --from-digest",
+                    from
+                ))
+            }
+            Some(digest) => {
+                validate_digest(digest)?;
+                format!("{}@{}", from, digest)
+            }
+            None => from,
+        };
+        for name in &self.packages {
+            validate_package(name)?;
+        }
+        let extra_packages = if self.packages.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "RUN apt-get update && apt-get install -y --no-install-recommends {} \\\n    \
+                 && rm -rf /var/lib/apt/lists/*\n",
+                self.packages.join(" ")
+            )
+        };
+        fill(
+            include_str!("Dockerfile.template"),
+            &[("from", from), ("extra_packages", extra_packages)],
+        )
+    }
+
+    /// Build the Docker image.
+    pub fn build(&self) -> Result<()> {
+        self.build_with(&Docker::new())
+    }
+
+    /// Build the image using the given container runtime.
+    pub fn build_with(&self, runtime: &dyn ContainerRuntime) -> Result<()> {
+        let dockerfile_path = if let Some(path) = &self.dockerfile {
+            if self.customizes_template() {
+                return Err(anyhow!(
+                    "Base image, digest, toolchain and extra packages only apply to the embedded \
+                     Dockerfile; set them in {} instead",
+                    path.display()
+                ));
+            }
+            path.clone()
+        } else {
+            let dockerfile_content = self.render()?;
+            let tmp_path = std::env::temp_dir().join("Dockerfile.safecrate");
+            fs::write(&tmp_path, dockerfile_content)
+                .context("Failed to write temporary Dockerfile")?;
+            tmp_path
+        };
+
+        let image = self.tag.as_deref().unwrap_or(DOCKER_IMAGE_NAME);
+        let mut build_args = args(&["build"]);
+        if self.progress != BuildProgress::Auto {
+            build_args.extend(args(&["--progress", "plain"]));
+        }
+        build_args.extend(args(&[
+            "-t",
+            image,
+            "-f",
+            dockerfile_path.to_str().unwrap(),
+            ".",
+        ]));
+
+        emit(Event::BuildStarted {
+            image: image.to_string(),
+        });
+        let result = if self.progress == BuildProgress::Json {
+            build_reporting_steps(runtime, &build_args)
+        } else {
+            run_checked(runtime, &build_args, "Docker build failed")
+        };
+        emit(Event::BuildFinished {
+            image: image.to_string(),
+            success: result.is_ok(),
+        });
+        result
+    }
+}
+
+/// Remove an image, failing with a clear message if a container still uses it.
+pub fn remove_image(runtime: &dyn ContainerRuntime, image: &str) -> Result<()> {
+    let output = runtime.output(&args(&["image", "rm", image]))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    // e.g. "conflict: unable to remove repository reference ... container 0123abcd is using
+    // its referenced image"
+    if output.stderr.contains("conflict") {
+        return Err(anyhow!(
+            "Image {} is still used by a container; remove the container, then `docker image rm {}`",
+            image,
+            image
+        ));
+    }
+    Err(anyhow!(
+        "Failed to remove image {}: {}",
+        image,
+        output.stderr.trim()
+    ))
+}
+
+/// Run the build with its log captured, printing each step as a line of JSON.
+fn build_reporting_steps(runtime: &dyn ContainerRuntime, build_args: &[String]) -> Result<()> {
+    let output = runtime.output(build_args)?;
+    // BuildKit writes its progress to stderr.
+    for step in parse_steps(&output.stderr) {
+        println!("{}", step.to_json());
+    }
+    if output.status.success() {
+        return Ok(());
+    }
+    let lines: Vec<&str> = output.stderr.lines().collect();
+    Err(anyhow!(
+        "Docker build failed:\n{}",
+        lines[lines.len().saturating_sub(15)..].join("\n")
+    ))
+}
+
+/// Whether an image reference names a tag, as in `rust:1.90-slim` (a `:` after any
+/// registry port).
+fn has_tag(image: &str) -> bool {
+    let name = image.split('@').next().unwrap_or(image);
+    name.rsplit('/').next().unwrap_or(name).contains(':')
+}
+
+fn validate_digest(digest: &str) -> Result<()> {
+    let valid = digest
+        .strip_prefix("sha256:")
+        .is_some_and(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid digest '{}': expected sha256: and 64 hex digits, as `docker images --digests` shows",
+            digest
+        ))
+    }
+}
+
+/// Replace each `{{name}}` in `template` with its value in `vars`.
+fn fill(template: &str, vars: &[(&str, String)]) -> Result<String> {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        filled.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| anyhow!("Unclosed {{{{ in Dockerfile template"))?;
+        let name = &after[..end];
+        let (_, value) = vars
+            .iter()
+            .find(|(var, _)| *var == name)
+            .ok_or_else(|| anyhow!("Unknown variable {{{{{}}}}} in Dockerfile template", name))?;
+        filled.push_str(value);
+        rest = &after[end + 2..];
+    }
+    filled.push_str(rest);
+    Ok(filled)
+}
+
+/// Check a Debian package name, so it can't smuggle anything else into the `RUN` line.
+fn validate_package(name: &str) -> Result<()> {
+    let valid = name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c));
+    if !valid {
+        return Err(anyhow!(
+            "Invalid package name '{}': expected a Debian package such as ripgrep",
+            name
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{MockRuntime, Output};
+
+    #[test]
+    fn build_uses_custom_dockerfile() {
+        let runtime = MockRuntime::new();
+
+        Image::new()
+            .dockerfile("/tmp/Custom.Dockerfile")
+            .build_with(&runtime)
+            .unwrap();
+
+        assert_eq!(
+            runtime.calls(),
+            vec![args(&[
+                "build",
+                "-t",
+                "safecrate_default",
+                "-f",
+                "/tmp/Custom.Dockerfile",
+                "."
+            ])]
+        );
+    }
+
+    #[test]
+    fn build_uses_custom_tag() {
+        let runtime = MockRuntime::new();
+
+        Image::new()
+            .tag("safecrate_node")
+            .build_with(&runtime)
+            .unwrap();
+
+        assert_eq!(runtime.calls()[0][2], "safecrate_node");
+    }
+
+    #[test]
+    fn build_writes_embedded_template() {
+        let runtime = MockRuntime::new();
+
+        Image::new().build_with(&runtime).unwrap();
+
+        let call = &runtime.calls()[0];
+        let written = fs::read_to_string(&call[4]).unwrap();
+        assert!(written.starts_with(&format!("FROM rust:{}-slim\n", DEFAULT_TOOLCHAIN)));
+        assert!(!written.contains("{{"));
+    }
+
+    #[test]
+    fn progress_is_forwarded_as_plain() {
+        let runtime = MockRuntime::new();
+        Image::new()
+            .progress(BuildProgress::Plain)
+            .dockerfile("/tmp/Custom.Dockerfile")
+            .build_with(&runtime)
+            .unwrap();
+        assert_eq!(runtime.calls()[0][..3], ["build", "--progress", "plain"]);
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::failure(
+            1,
+            "#3 [1/2] RUN make\n#3 ERROR: exit code: 2\n",
+        ));
+        let err = Image::new()
+            .progress(BuildProgress::Json)
+            .dockerfile("/tmp/Custom.Dockerfile")
+            .build_with(&runtime)
+            .unwrap_err();
+        assert!(err.to_string().ends_with("#3 ERROR: exit code: 2"));
+    }
+
+    #[test]
+    fn removing_an_image_in_use_explains_why() {
+        let runtime = MockRuntime::new();
+        remove_image(&runtime, "safecrate_ephemeral_1").unwrap();
+        assert_eq!(runtime.calls()[0], ["image", "rm", "safecrate_ephemeral_1"]);
+
+        runtime.respond(Output::failure(
+            1,
+            "Error response from daemon: conflict: unable to remove repository reference \
+             \"safecrate_ephemeral_1\" (must force) - container 0123abcd is using its referenced image",
+        ));
+        let err = remove_image(&runtime, "safecrate_ephemeral_1").unwrap_err();
+        assert!(err.to_string().contains("still used by a container"));
+    }
+
+    #[test]
+    fn template_variables_are_filled() {
+        let vars = [("a", "1".to_string()), ("b", String::new())];
+        assert_eq!(fill("x{{a}}y{{b}}z{{a}}", &vars).unwrap(), "x1yz1");
+        assert!(fill("{{c}}", &vars).is_err());
+        assert!(fill("{{a", &vars).is_err());
+
+        let rendered = Image::new().toolchain("1.90.0").render().unwrap();
+        assert!(rendered.starts_with("FROM rust:1.90.0-slim\n"));
+        let rendered = Image::new().from("rust:1.90-bookworm").render().unwrap();
+        assert!(rendered.starts_with("FROM rust:1.90-bookworm\n"));
+
+        assert!(Image::new().from("a").toolchain("1").render().is_err());
+        assert!(Image::new().toolchain("1.90 AS x").render().is_err());
+        assert!(Image::new().from("rust\nRUN evil").render().is_err());
+    }
+
+    #[test]
+    fn digest_pins_the_base_image() {
+        let digest = format!("sha256:{}", "ab12".repeat(16));
+        let rendered = Image::new()
+            .from("rust")
+            .from_digest(&digest)
+            .render()
+            .unwrap();
+        assert!(rendered.starts_with(&format!("FROM rust@{}\n", digest)));
+        assert!(Image::new()
+            .from("rust")
+            .from_digest(&digest)
+            .warnings()
+            .is_empty());
+
+        let image = Image::new()
+            .from("localhost:5000/rust:1.90")
+            .from_digest(&digest);
+        assert!(image
+            .render()
+            .unwrap()
+            .starts_with(&format!("FROM localhost:5000/rust:1.90@{}\n", digest)));
+        assert_eq!(image.warnings().len(), 1);
+        assert!(!has_tag("localhost:5000/rust"));
+        assert_eq!(
+            Image::new()
+                .toolchain("1.90")
+                .from_digest(&digest)
+                .warnings()
+                .len(),
+            1
+        );
+
+        assert!(Image::new().from_digest("sha256:abc").render().is_err());
+        assert!(Image::new()
+            .from(format!("rust@{}", digest))
+            .from_digest(&digest)
+            .render()
+            .is_err());
+    }
+
+    #[test]
+    fn extra_packages_get_their_own_layer() {
+        let rendered = Image::new()
+            .package("ripgrep")
+            .package("fd-find")
+            .render()
+            .unwrap();
+        assert!(rendered.contains(
+            "RUN apt-get update && apt-get install -y --no-install-recommends ripgrep fd-find \\\n"
+        ));
+
+        assert!(Image::new().package("rg; curl evil").render().is_err());
+        let runtime = MockRuntime::new();
+        assert!(Image::new()
+            .dockerfile("/tmp/Custom.Dockerfile")
+            .package("ripgrep")
+            .build_with(&runtime)
+            .is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! Curated summary of a container's configuration, from `docker inspect`.
+
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::path::Path;
+
+use crate::container::container_name;
+use crate::git::GitRevision;
+use crate::runtime::{args, ContainerRuntime};
+
+/// Separates top-level fields in the inspect template output.
+const FIELD_SEP: char = '\u{1e}';
+/// Separates items within a list field.
+const ITEM_SEP: char = '\u{1f}';
+/// Separates the parts of a single mount item.
+const PART_SEP: char = '\u{1d}';
+
+/// Go template for `docker inspect --format`, one field per [`FIELD_SEP`].
+const INSPECT_FORMAT: &str = concat!(
+    "{{.Name}}\u{1e}",
+    "{{.Config.Image}}\u{1e}",
+    "{{.State.Status}}\u{1e}",
+    "{{.HostConfig.NetworkMode}}\u{1e}",
+    "{{.HostConfig.Memory}}\u{1e}",
+    "{{.HostConfig.NanoCpus}}\u{1e}",
+    "{{.HostConfig.PidsLimit}}\u{1e}",
+    "{{range .Mounts}}{{.Type}}\u{1d}{{.Source}}\u{1d}{{.Destination}}\u{1d}{{.RW}}\u{1f}{{end}}\u{1e}",
+    "{{range .Config.Env}}{{.}}\u{1f}{{end}}\u{1e}",
+    "{{range .Config.Entrypoint}}{{.}}\u{1f}{{end}}\u{1e}",
+    "{{range .Config.Cmd}}{{.}}\u{1f}{{end}}\u{1e}",
+    "{{index .Config.Labels \"safecrate.git.commit\"}}\u{1e}",
+    "{{index .Config.Labels \"safecrate.git.branch\"}}",
+);
+
+/// A mount as reported by `docker inspect`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountInfo {
+    pub kind: String,
+    pub source: String,
+    pub destination: String,
+    pub read_write: bool,
+}
+
+/// The parts of a container's configuration that matter for debugging a sandbox.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerInfo {
+    pub name: String,
+    pub image: String,
+    pub status: String,
+    pub network_mode: String,
+    /// Memory limit in bytes, if any.
+    pub memory: Option<u64>,
+    /// CPU limit in units of 1e-9 CPUs, if any.
+    pub nano_cpus: Option<u64>,
+    pub pids_limit: Option<i64>,
+    pub mounts: Vec<MountInfo>,
+    pub env: Vec<String>,
+    pub entrypoint: Vec<String>,
+    pub cmd: Vec<String>,
+    /// The Git revision the project was at, from the container's labels.
+    pub revision: Option<GitRevision>,
+}
+
+/// Inspect the container for the given project directory.
+pub fn inspect(runtime: &dyn ContainerRuntime, dir: &Path) -> Result<ContainerInfo> {
+    let container_name = container_name(dir)?;
+
+    let output = runtime.output(&args(&[
+        "inspect",
+        "--type",
+        "container",
+        "--format",
+        INSPECT_FORMAT,
+        &container_name,
+    ]))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "No container {} found for {}. {}",
+            container_name,
+            dir.display(),
+            output.stderr.trim()
+        ));
+    }
+
+    ContainerInfo::parse(output.stdout.trim_end_matches('\n'))
+}
+
+impl ContainerInfo {
+    fn parse(output: &str) -> Result<Self> {
+        let fields: Vec<&str> = output.split(FIELD_SEP).collect();
+        let [name, image, status, network_mode, memory, nano_cpus, pids_limit, mounts, env, entrypoint, cmd, commit, branch] =
+            fields[..]
+        else {
+            return Err(anyhow!("Unexpected docker inspect output"));
+        };
+
+        let mounts = items(mounts)
+            .into_iter()
+            .map(|mount| {
+                let parts: Vec<&str> = mount.split(PART_SEP).collect();
+                match parts[..] {
+                    [kind, source, destination, rw] => Ok(MountInfo {
+                        kind: kind.to_string(),
+                        source: source.to_string(),
+                        destination: destination.to_string(),
+                        read_write: rw == "true",
+                    }),
+                    _ => Err(anyhow!(
+                        "Unexpected mount in docker inspect output: {}",
+                        mount
+                    )),
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            name: name.trim_start_matches('/').to_string(),
+            image: image.to_string(),
+            status: status.to_string(),
+            network_mode: network_mode.to_string(),
+            memory: limit(memory),
+            nano_cpus: limit(nano_cpus),
+            pids_limit: pids_limit.parse().ok().filter(|&n: &i64| n > 0),
+            mounts,
+            env: items(env),
+            entrypoint: items(entrypoint),
+            cmd: items(cmd),
+            revision: GitRevision::from_labels(commit, branch),
+        })
+    }
+}
+
+/// Parse a limit where `0` (or a missing value) means unlimited.
+fn limit(value: &str) -> Option<u64> {
+    value.parse().ok().filter(|&n| n > 0)
+}
+
+fn items(field: &str) -> Vec<String> {
+    field
+        .split(ITEM_SEP)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [(&str, u64); 3] = [("g", 1 << 30), ("m", 1 << 20), ("k", 1 << 10)];
+    for (unit, size) in UNITS {
+        if bytes >= size && bytes.is_multiple_of(size) {
+            return format!("{}{}", bytes / size, unit);
+        }
+    }
+    format!("{}b", bytes)
+}
+
+impl fmt::Display for ContainerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Container: {} ({})", self.name, self.status)?;
+        writeln!(f, "Image:     {}", self.image)?;
+        writeln!(f, "Network:   {}", self.network_mode)?;
+        if let Some(revision) = &self.revision {
+            match &revision.branch {
+                Some(branch) => writeln!(f, "Git:       {} ({})", revision.commit, branch)?,
+                None => writeln!(f, "Git:       {}", revision.commit)?,
+            }
+        }
+
+        writeln!(f, "Limits:")?;
+        let unlimited = || "unlimited".to_string();
+        writeln!(
+            f,
+            "\tmemory: {}",
+            self.memory.map(format_bytes).unwrap_or_else(unlimited)
+        )?;
+        writeln!(
+            f,
+            "\tcpus:   {}",
+            self.nano_cpus
+                .map(|n| format!("{}", n as f64 / 1e9))
+                .unwrap_or_else(unlimited)
+        )?;
+        writeln!(
+            f,
+            "\tpids:   {}",
+            self.pids_limit
+                .map(|n| n.to_string())
+                .unwrap_or_else(unlimited)
+        )?;
+
+        writeln!(f, "Mounts:")?;
+        for mount in &self.mounts {
+            let mode = if mount.read_write { "rw" } else { "ro" };
+            writeln!(
+                f,
+                "\t{} -> {} ({}, {})",
+                mount.source, mount.destination, mount.kind, mode
+            )?;
+        }
+
+        writeln!(f, "Env:")?;
+        for var in &self.env {
+            writeln!(f, "\t{}", var)?;
+        }
+
+        let command: Vec<&str> = self
+            .entrypoint
+            .iter()
+            .chain(&self.cmd)
+            .map(String::as_str)
+            .collect();
+        write!(f, "Command:   {}", command.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{MockRuntime, Output};
+    use crate::testutil::project_dir;
+
+    fn sample() -> String {
+        [
+            "/proj_isolated",
+            "safecrate_default",
+            "running",
+            "none",
+            "2147483648",
+            "0",
+            "<nil>",
+            "bind\u{1d}/home/me/my proj\u{1d}/workspace\u{1d}true\u{1f}volume\u{1d}/var/lib/docker/volumes/c\u{1d}/cache\u{1d}false\u{1f}",
+            "PATH=/usr/bin\u{1f}TERM=xterm\u{1f}",
+            "",
+            "sh\u{1f}-c\u{1f}nvim .\u{1f}",
+            "0123456789abcdef0123456789abcdef01234567",
+            "<no value>",
+        ]
+        .join("\u{1e}")
+    }
+
+    #[test]
+    fn parses_inspect_template_output() {
+        let dir = project_dir("inspect-sample");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(format!("{}\n", sample())));
+
+        let info = inspect(&runtime, &dir).unwrap();
+
+        assert_eq!(runtime.calls()[0][0], "inspect");
+        assert_eq!(
+            runtime.calls()[0].last().unwrap(),
+            "inspect-sample_isolated"
+        );
+        assert_eq!(info.name, "proj_isolated");
+        assert_eq!(info.network_mode, "none");
+        assert_eq!(info.memory, Some(2 << 30));
+        assert_eq!(info.nano_cpus, None);
+        assert_eq!(info.pids_limit, None);
+        assert_eq!(info.mounts.len(), 2);
+        assert_eq!(info.mounts[0].source, "/home/me/my proj");
+        assert!(!info.mounts[1].read_write);
+        assert_eq!(info.env, vec!["PATH=/usr/bin", "TERM=xterm"]);
+        assert_eq!(info.cmd, vec!["sh", "-c", "nvim ."]);
+
+        let summary = info.to_string();
+        assert!(summary.contains("memory: 2g"));
+        assert!(summary.contains("Git:       0123456789abcdef0123456789abcdef01234567\n"));
+        assert!(summary.contains("/home/me/my proj -> /workspace (bind, rw)"));
+        assert!(summary.ends_with("Command:   sh -c nvim ."));
+    }
+
+    #[test]
+    fn missing_container_is_an_error() {
+        let dir = project_dir("inspect-missing");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::failure(1, "Error: No such container"));
+
+        let err = inspect(&runtime, &dir).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("No container inspect-missing_isolated"));
+    }
+}
@@ -0,0 +1,103 @@
+//! Safecrate — safely open and build untrusted code in isolated Docker sandboxes.
+//!
+//! The `safecrate` binary is a thin CLI over this crate. The same operations can be
+//! driven programmatically:
+//!
+//! ```no_run
+//! use safecrate::Sandbox;
+//!
+//! Sandbox::new("/path/to/untrusted_code")
+//!     .cmd("cargo build")
+//!     .network(false)
+//!     .memory("2g")
+//!     .open()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Per-project defaults can be stored in a [`CONFIG_FILE_NAME`] file and loaded with
+//! [`Config::load`].
+//!
+//! All Docker invocations go through a [`ContainerRuntime`], so tests can swap in a
+//! [`MockRuntime`] and assert the exact arguments that would be passed to `docker`.
+
+/// `println!`, but to stderr while `--events` owns stdout.
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::events_enabled() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+mod batch;
+mod benchmark;
+mod clone;
+mod config;
+mod container;
+mod copy_in;
+mod detect;
+mod duration;
+mod egress;
+mod events;
+mod git;
+mod ignore;
+mod image;
+mod inspect;
+mod origin;
+mod progress;
+mod provenance;
+mod record;
+mod runtime;
+mod sandbox;
+mod scan;
+mod style;
+#[cfg(test)]
+mod testutil;
+mod toml;
+mod validate;
+mod version;
+mod watch;
+
+pub use batch::BatchReport;
+pub use benchmark::{benchmark, BenchmarkReport, ReportFormat, SAMPLE_INTERVAL};
+pub use clone::{clone_repo, repo_name};
+pub use config::{Config, Spec, CONFIG_FILE_NAME};
+pub use container::{
+    cache_volume_name, container_name, freeze, kill, list, migrate, name_template, remove,
+    remove_run, resume, resume_cmd, set_name_template, status, top, unfreeze, ContainerStatus,
+    ContainerSummary, Migration, NameTemplate, CACHE_PROJECT_LABEL, RUN_ID_LABEL,
+};
+pub use copy_in::private_temp_dir;
+pub use detect::{detect, ProjectKind, DEFAULT_DETECT_ORDER};
+pub use duration::HumanDuration;
+pub use egress::{first_egress, Egress};
+pub use events::{events_enabled, set_events, Event};
+pub use git::{GitRevision, GIT_BRANCH_LABEL, GIT_COMMIT_LABEL};
+pub use ignore::{IgnoreRules, IGNORE_FILE_NAME};
+pub use image::{remove_image, Image, DEFAULT_TOOLCHAIN};
+pub use inspect::{inspect, ContainerInfo, MountInfo};
+pub use origin::{origin, Origin, DOCKER_LABEL, ORIGIN_DIR_LABEL, ORIGIN_LABEL};
+pub use progress::{parse_steps, BuildProgress, BuildStep, StepStatus};
+pub use provenance::{Provenance, ProvenanceMode};
+pub use record::{record, unpack, Recording, RECORD_SPEC};
+pub use runtime::{ContainerRuntime, Docker, MockRuntime, Output, Status};
+pub use sandbox::{
+    BindPropagation, CommandFailed, Editor, Isolation, PullPolicy, RmPolicy, Sandbox,
+    DEFAULT_CACHE_PATH, DEFAULT_CMD, DEFAULT_DETACH_KEYS, DEFAULT_OOM_SCORE_ADJ, DEFAULT_USER_HOME,
+};
+pub use scan::{dir_size, scan, ByteSize, DirSize, Finding, FindingKind, LARGE_FILE_BYTES};
+pub use style::{color_enabled, green, red, set_color, ColorChoice, Marker};
+pub use version::{version, VersionInfo};
+pub use watch::{watch, WatchOptions, IGNORED_DIRS};
+
+/// Name of the base image built by `safecrate init`.
+pub const DOCKER_IMAGE_NAME: &str = "safecrate_default";
+
+/// AppArmor profile named `safecrate`, for hosts that load it with `apparmor_parser`.
+pub const APPARMOR_PROFILE: &str = include_str!("apparmor.profile");
+
+/// Suffix appended to the project name to form the container name.
+pub const CONTAINER_SUFFIX: &str = "isolated";
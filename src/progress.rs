@@ -0,0 +1,169 @@
+//! How `safecrate init` reports `docker build` progress, including a JSON summary of the
+//! BuildKit steps for CI logs.
+
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::events::json_string;
+
+/// Build progress output: Docker's own (`auto`), BuildKit's plain log, or JSON lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BuildProgress {
+    #[default]
+    Auto,
+    Plain,
+    /// One JSON object per build step, parsed from the plain log.
+    Json,
+}
+
+impl FromStr for BuildProgress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(BuildProgress::Auto),
+            "plain" => Ok(BuildProgress::Plain),
+            "json" => Ok(BuildProgress::Json),
+            _ => Err(anyhow!(
+                "Unknown progress output '{}': expected auto, plain, or json",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BuildProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BuildProgress::Auto => "auto",
+            BuildProgress::Plain => "plain",
+            BuildProgress::Json => "json",
+        })
+    }
+}
+
+/// How a build step ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepStatus {
+    /// Finished, after this many seconds.
+    Done(f64),
+    Cached,
+    Error,
+    /// Still running when the log ended.
+    Running,
+}
+
+/// One BuildKit step, e.g. `[2/6] RUN apt-get update`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildStep {
+    pub id: u32,
+    pub name: String,
+    pub status: StepStatus,
+}
+
+impl BuildStep {
+    /// The step as one line of JSON, e.g.
+    /// `{"step":5,"name":"[2/6] RUN make","status":"done","seconds":12.3}`.
+    pub fn to_json(&self) -> String {
+        let (status, seconds) = match self.status {
+            StepStatus::Done(seconds) => ("done", Some(seconds)),
+            StepStatus::Cached => ("cached", None),
+            StepStatus::Error => ("error", None),
+            StepStatus::Running => ("running", None),
+        };
+        let mut json = format!(
+            "{{\"step\":{},\"name\":{},\"status\":\"{}\"",
+            self.id,
+            json_string(&self.name),
+            status
+        );
+        if let Some(seconds) = seconds {
+            json.push_str(&format!(",\"seconds\":{}", seconds));
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// Steps in a `docker build --progress plain` log, in the order they started.
+///
+/// Lines look like `#5 [2/6] RUN make`, then output such as `#5 0.512 ...`, and finally
+/// `#5 DONE 12.3s`, `#5 CACHED` or `#5 ERROR: ...`.
+pub fn parse_steps(log: &str) -> Vec<BuildStep> {
+    let mut steps: Vec<BuildStep> = Vec::new();
+    for line in log.lines() {
+        let Some((id, rest)) = line.strip_prefix('#').and_then(|line| line.split_once(' ')) else {
+            continue;
+        };
+        let Ok(id) = id.parse::<u32>() else {
+            continue;
+        };
+        let status = if let Some(seconds) = rest.strip_prefix("DONE ") {
+            seconds
+                .trim_end_matches('s')
+                .parse()
+                .ok()
+                .map(StepStatus::Done)
+        } else if rest == "CACHED" {
+            Some(StepStatus::Cached)
+        } else if rest.starts_with("ERROR") {
+            Some(StepStatus::Error)
+        } else {
+            None
+        };
+        match steps.iter_mut().find(|step| step.id == id) {
+            Some(step) => {
+                if let Some(status) = status {
+                    step.status = status;
+                }
+            }
+            // The first line of a step names it; output lines start with a timestamp.
+            None if status.is_none() => steps.push(BuildStep {
+                id,
+                name: rest.to_string(),
+                status: StepStatus::Running,
+            }),
+            None => {}
+        }
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_buildkit_plain_log() {
+        let log = "\
+#1 [internal] load build definition from Dockerfile.safecrate
+#1 transferring dockerfile: 1.23kB done
+#1 DONE 0.0s
+
+#5 [2/6] RUN apt-get update && apt-get install -y \"curl\"
+#5 0.512 Get:1 http://deb.debian.org/debian bookworm InRelease
+#5 DONE 12.3s
+
+#6 [3/6] RUN git clone --depth 1 https://github.com/neovim/neovim /tmp/neovim
+#6 CACHED
+
+#7 [4/6] RUN make
+#7 1.002 make: *** [all] Error 2
+#7 ERROR: process \"/bin/sh -c make\" did not complete successfully: exit code: 2
+";
+        let steps = parse_steps(log);
+        assert_eq!(steps.len(), 4);
+        assert_eq!(steps[0].status, StepStatus::Done(0.0));
+        assert_eq!(steps[2].status, StepStatus::Cached);
+        assert_eq!(steps[3].status, StepStatus::Error);
+        assert_eq!(
+            steps[1].to_json(),
+            r#"{"step":5,"name":"[2/6] RUN apt-get update && apt-get install -y \"curl\"","status":"done","seconds":12.3}"#
+        );
+        assert_eq!(
+            "json".parse::<BuildProgress>().unwrap(),
+            BuildProgress::Json
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Checking the image's SLSA provenance attestations with `cosign` before it runs
+//! (`open --verify-image-provenance`).
+
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use crate::style::Marker;
+use crate::validate;
+
+/// What happens when an image's provenance doesn't verify.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProvenanceMode {
+    /// Print a warning and run the image anyway.
+    Warn,
+    /// Refuse to run the image.
+    #[default]
+    Enforce,
+}
+
+impl FromStr for ProvenanceMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "warn" => Ok(ProvenanceMode::Warn),
+            "enforce" => Ok(ProvenanceMode::Enforce),
+            _ => Err(anyhow!(
+                "Unknown provenance mode '{}': expected warn or enforce",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ProvenanceMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProvenanceMode::Warn => "warn",
+            ProvenanceMode::Enforce => "enforce",
+        })
+    }
+}
+
+/// Who must have attested the image's provenance, and what it has to say.
+///
+/// Attestations are signed with a key, or keylessly by an identity that an OIDC issuer
+/// vouched for; one of the two is required.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    mode: ProvenanceMode,
+    key: Option<String>,
+    identity: Option<String>,
+    issuer: Option<String>,
+    policy: Option<PathBuf>,
+}
+
+impl Provenance {
+    pub fn new(mode: ProvenanceMode) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+
+    /// Public key the attestations are signed with: a file, URL or KMS reference, as
+    /// `cosign --key` takes.
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Identity in the signing certificate of keyless attestations, e.g. a CI workflow URL.
+    pub fn identity(mut self, identity: impl Into<String>) -> Self {
+        self.identity = Some(identity.into());
+        self
+    }
+
+    /// OIDC issuer that vouched for [`Provenance::identity`].
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// CUE or Rego policy the provenance must also satisfy, e.g. to require a builder.
+    pub fn policy(mut self, path: impl Into<PathBuf>) -> Self {
+        self.policy = Some(path.into());
+        self
+    }
+
+    /// The `cosign` arguments verifying `image`.
+    pub fn cosign_args(&self, image: &str) -> Result<Vec<String>> {
+        validate::image(image)?;
+        let mut args = vec![
+            "verify-attestation".to_string(),
+            "--type".to_string(),
+            "slsaprovenance".to_string(),
+        ];
+        match (&self.key, &self.identity, &self.issuer) {
+            (Some(key), None, None) => args.extend(["--key".to_string(), key.clone()]),
+            (None, Some(identity), Some(issuer)) => args.extend([
+                "--certificate-identity".to_string(),
+                identity.clone(),
+                "--certificate-oidc-issuer".to_string(),
+                issuer.clone(),
+            ]),
+            _ => {
+                return Err(anyhow!(
+                    "--verify-image-provenance needs --provenance-key, or --provenance-identity and --provenance-issuer for keyless attestations"
+                ))
+            }
+        }
+        if let Some(policy) = &self.policy {
+            args.extend(["--policy".to_string(), policy.display().to_string()]);
+        }
+        args.push(image.to_string());
+        Ok(args)
+    }
+
+    /// Run `cosign` on `image`, failing in [`ProvenanceMode::Enforce`] and warning in
+    /// [`ProvenanceMode::Warn`] if it doesn't verify.
+    pub fn verify(&self, image: &str) -> Result<()> {
+        let args = self.cosign_args(image)?;
+        if !image.contains("@sha256:") {
+            eprintln!(
+                "{} WARNING: {} isn't pinned to a digest, so the tag could point to another image by the time Docker pulls it.",
+                Marker::Warning,
+                image
+            );
+        }
+        let problem = match Command::new("cosign")
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .output()
+        {
+            Ok(output) if output.status.success() => None,
+            Ok(output) => Some(format!(
+                "cosign couldn't verify its provenance: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(err) => Some(format!("cosign couldn't be run: {}", err)),
+        };
+        let Some(problem) = problem else {
+            say!("{} Verified the provenance of {}", Marker::Ok, image);
+            return Ok(());
+        };
+        match self.mode {
+            ProvenanceMode::Enforce => Err(anyhow!(
+                "Refusing to run {}: {}\n\tImages built locally, such as safecrate's own, have no attestations; \
+                 pass --verify-image-provenance warn to run it anyway.",
+                image,
+                problem
+            )),
+            ProvenanceMode::Warn => {
+                eprintln!("{} WARNING: Running {} anyway: {}", Marker::Warning, image, problem);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_cosign_arguments() {
+        let keyed = Provenance::new(ProvenanceMode::Enforce)
+            .key("cosign.pub")
+            .policy("slsa.cue");
+        assert_eq!(
+            keyed.cosign_args("ghcr.io/org/img@sha256:abc").unwrap(),
+            [
+                "verify-attestation",
+                "--type",
+                "slsaprovenance",
+                "--key",
+                "cosign.pub",
+                "--policy",
+                "slsa.cue",
+                "ghcr.io/org/img@sha256:abc"
+            ]
+        );
+
+        let keyless = Provenance::new(ProvenanceMode::Warn)
+            .identity("https://github.com/org/img/.github/workflows/release.yml@refs/heads/main")
+            .issuer("https://token.actions.githubusercontent.com");
+        let args = keyless.cosign_args("ghcr.io/org/img").unwrap();
+        assert_eq!(args[3], "--certificate-identity");
+        assert_eq!(args[5], "--certificate-oidc-issuer");
+
+        assert!(Provenance::new(ProvenanceMode::Enforce)
+            .cosign_args("img")
+            .is_err());
+        assert!(Provenance::new(ProvenanceMode::Enforce)
+            .key("cosign.pub")
+            .issuer("https://issuer")
+            .cosign_args("img")
+            .is_err());
+        assert_eq!(
+            "warn".parse::<ProvenanceMode>().unwrap(),
+            ProvenanceMode::Warn
+        );
+    }
+}
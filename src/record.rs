@@ -0,0 +1,278 @@
+//! Recording what `open` ran into an archive, and unpacking one to run it again
+//! (`open --record`, `safecrate replay`).
+
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::{Config, Spec};
+use crate::copy_in::{copy_dir, private_temp_dir};
+use crate::ignore::IgnoreRules;
+use crate::runtime::{args, ContainerRuntime};
+use crate::toml::Value;
+use crate::validate;
+use crate::DOCKER_IMAGE_NAME;
+
+/// The spec opening the recorded copy of the project, at the root of the archive.
+pub const RECORD_SPEC: &str = "safecrate-record.toml";
+/// The ID of the image the recorded run used, to spot a different one at replay.
+const IMAGE_ID_FILE: &str = "image-id";
+
+/// A recording unpacked by [`unpack`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recording {
+    /// Spec to open with `--spec`: the recorded config keys.
+    pub spec: PathBuf,
+    /// The unpacked copy of the project.
+    pub dir: PathBuf,
+    pub image: Option<String>,
+    image_id: Option<String>,
+}
+
+impl Recording {
+    /// A warning if the local image isn't the one the recorded run used.
+    pub fn check_image(&self, runtime: &dyn ContainerRuntime) -> Option<String> {
+        let (recorded, image) = (self.image_id.as_ref()?, self.image.as_ref()?);
+        match image_identity(runtime, image) {
+            Some((id, _)) if &id == recorded => None,
+            Some((id, _)) => Some(format!(
+                "{} is now {}, not the {} the recording used; the replay may behave differently.",
+                image, id, recorded
+            )),
+            None => Some(format!(
+                "{} isn't available locally, so it can't be checked against the {} the recording used.",
+                image, recorded
+            )),
+        }
+    }
+}
+
+/// Save the project at `dir` and the `config` opening it to the tar `archive`.
+///
+/// The archive holds a copy of the project without its ignored files, a spec with
+/// `config` pinned to the image's digest where Docker knows one, and the image's ID.
+/// Command-line-only flags aren't config keys, so they aren't recorded.
+pub fn record(
+    runtime: &dyn ContainerRuntime,
+    dir: &Path,
+    config: &Config,
+    archive: &Path,
+) -> Result<()> {
+    let root = private_temp_dir("safecrate-record")?;
+    let archive = std::path::absolute(archive)?;
+    let result = write_recording(runtime, dir, config, &root).and_then(|()| {
+        tar(&[
+            "-cf".as_ref(),
+            archive.as_os_str(),
+            "-C".as_ref(),
+            root.as_os_str(),
+            ".".as_ref(),
+        ])
+        .with_context(|| format!("Failed to write {}", archive.display()))
+    });
+    let _ = fs::remove_dir_all(&root);
+    result
+}
+
+fn write_recording(
+    runtime: &dyn ContainerRuntime,
+    dir: &Path,
+    config: &Config,
+    root: &Path,
+) -> Result<()> {
+    let source = fs::canonicalize(dir)?;
+    let name = source
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "project".to_string());
+    let copy = root.join(&name);
+    fs::create_dir(&copy)?;
+    copy_dir(&source, &copy, "", &IgnoreRules::load(&source)?)
+        .with_context(|| format!("Failed to copy {}", source.display()))?;
+
+    let mut config = config.clone();
+    if let Some((id, digest)) = image(&config).and_then(|image| image_identity(runtime, &image)) {
+        fs::write(root.join(IMAGE_ID_FILE), format!("{}\n", id))?;
+        if digest.is_some() {
+            config.image = digest;
+        }
+    }
+
+    // Quoted, so a newline in the path can't start a key of its own.
+    let spec = format!(
+        "# Recorded by safecrate {} from {}\ndir = {}\n{}",
+        env!("CARGO_PKG_VERSION"),
+        Value::String(source.display().to_string()),
+        Value::String(name),
+        config.to_toml()
+    );
+    fs::write(root.join(RECORD_SPEC), spec)?;
+    Ok(())
+}
+
+/// The image `config` runs, unless `--auto-image` picks it when opening.
+fn image(config: &Config) -> Option<String> {
+    match (&config.image, config.auto_image) {
+        (Some(image), _) => Some(image.clone()),
+        (None, Some(true)) => None,
+        (None, _) => Some(DOCKER_IMAGE_NAME.to_string()),
+    }
+}
+
+/// The image's ID, and its digest in a registry if it came from one.
+fn image_identity(runtime: &dyn ContainerRuntime, image: &str) -> Option<(String, Option<String>)> {
+    validate::image(image).ok()?;
+    let output = runtime
+        .output(&args(&[
+            "image",
+            "inspect",
+            "--format",
+            "{{.Id}}\t{{join .RepoDigests \",\"}}",
+            image,
+        ]))
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // Local builds have no digests, leaving the tab trimmed off.
+    let line = output.stdout.trim();
+    let (id, digests) = line.split_once('\t').unwrap_or((line, ""));
+    let digest = digests
+        .split(',')
+        .next()
+        .filter(|digest| !digest.is_empty());
+    Some((id.to_string(), digest.map(str::to_string)))
+}
+
+/// Unpack the tar `archive` written by [`record`] into the empty directory `into`.
+///
+/// The archive may come from anyone, so only its config keys are used, and its spec
+/// must open a directory inside `into`.
+pub fn unpack(archive: &Path, into: &Path) -> Result<Recording> {
+    tar(&[
+        "-xf".as_ref(),
+        archive.as_os_str(),
+        "-C".as_ref(),
+        into.as_os_str(),
+        "--no-same-owner".as_ref(),
+        "--no-same-permissions".as_ref(),
+    ])
+    .with_context(|| format!("Failed to unpack {}", archive.display()))?;
+    load(into).with_context(|| format!("{} isn't a safecrate recording", archive.display()))
+}
+
+fn load(root: &Path) -> Result<Recording> {
+    let spec = root.join(RECORD_SPEC);
+    let Spec { dir, config } = Spec::load(&spec)?;
+    let dir = dir.ok_or_else(|| anyhow!("{} has no `dir`", RECORD_SPEC))?;
+    let dir = fs::canonicalize(&dir)
+        .with_context(|| format!("Failed to find the recorded project {}", dir.display()))?;
+    let root = fs::canonicalize(root)?;
+    if dir == root || !dir.starts_with(&root) || !dir.is_dir() {
+        return Err(anyhow!(
+            "The recorded project must be a directory in the recording, not {}",
+            dir.display()
+        ));
+    }
+    let image_id = fs::read_to_string(root.join(IMAGE_ID_FILE))
+        .ok()
+        .map(|id| id.trim().to_string());
+    Ok(Recording {
+        spec,
+        dir,
+        image: image(&config),
+        image_id,
+    })
+}
+
+fn tar(args: &[&std::ffi::OsStr]) -> Result<()> {
+    let output = Command::new("tar")
+        .args(args)
+        .output()
+        .context("Failed to run tar")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "tar failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{MockRuntime, Output};
+    use crate::testutil::project_dir;
+
+    #[test]
+    fn records_and_unpacks_a_project() {
+        let dir = project_dir("record-project");
+        fs::write(dir.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(dir.join(".safecrateignore"), "secret.env\n").unwrap();
+        fs::write(dir.join("secret.env"), "TOKEN=1").unwrap();
+        let archive = dir.with_extension("tar");
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(
+            "sha256:1234\tghcr.io/org/img@sha256:abcd\n",
+        ));
+        let config = Config {
+            image: Some("ghcr.io/org/img:latest".to_string()),
+            cmd: Some("cargo test".to_string()),
+            ..Config::default()
+        };
+        record(&runtime, &dir, &config, &archive).unwrap();
+
+        let into = dir.with_extension("replay");
+        let _ = fs::remove_dir_all(&into);
+        fs::create_dir_all(&into).unwrap();
+        let recording = unpack(&archive, &into).unwrap();
+        assert_eq!(
+            recording.dir,
+            fs::canonicalize(into.join("record-project")).unwrap()
+        );
+        assert!(recording.dir.join("main.rs").exists());
+        assert!(!recording.dir.join("secret.env").exists());
+        assert_eq!(
+            recording.image.as_deref(),
+            Some("ghcr.io/org/img@sha256:abcd")
+        );
+        assert_eq!(
+            Spec::load(&recording.spec).unwrap().config.cmd.as_deref(),
+            Some("cargo test")
+        );
+
+        runtime.respond(Output::success("sha256:5678\t\n"));
+        let warning = recording.check_image(&runtime).unwrap();
+        assert!(warning.contains("not the sha256:1234"), "{}", warning);
+        runtime.respond(Output::success("sha256:1234\t\n"));
+        assert_eq!(recording.check_image(&runtime), None);
+    }
+
+    #[test]
+    fn project_paths_cant_add_keys_to_the_spec() {
+        let dir = project_dir("record-x\nnetwork = true");
+        let archive = project_dir("record-newline").join("session.tar");
+        record(&MockRuntime::new(), &dir, &Config::default(), &archive).unwrap();
+
+        let into = project_dir("record-newline").join("replay");
+        let _ = fs::remove_dir_all(&into);
+        fs::create_dir(&into).unwrap();
+        let recording = unpack(&archive, &into).unwrap();
+        assert_eq!(recording.dir.file_name(), dir.file_name());
+        assert_eq!(Spec::load(&recording.spec).unwrap().config.network, None);
+    }
+
+    #[test]
+    fn rejects_recordings_opening_outside_themselves() {
+        let root = project_dir("record-escape");
+        fs::write(root.join(RECORD_SPEC), "dir = \"..\"\n").unwrap();
+        assert!(load(&root).is_err());
+        fs::write(root.join(RECORD_SPEC), "dir = \"/\"\n").unwrap();
+        assert!(load(&root).is_err());
+        fs::write(root.join(RECORD_SPEC), "network = true\n").unwrap();
+        assert!(load(&root).is_err());
+    }
+}
@@ -0,0 +1,395 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Exit status of a container runtime invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status {
+    /// Exit code, or `None` if the process was terminated by a signal.
+    pub code: Option<i32>,
+}
+
+impl Status {
+    /// A status with the given exit code.
+    pub fn from_code(code: i32) -> Self {
+        Self { code: Some(code) }
+    }
+
+    /// Whether the command exited successfully.
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Captured result of a container runtime invocation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub status: Status,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Output {
+    /// A successful output with the given stdout.
+    pub fn success(stdout: impl Into<String>) -> Self {
+        Self {
+            status: Status::from_code(0),
+            stdout: stdout.into(),
+            stderr: String::new(),
+        }
+    }
+
+    /// A failed output with the given exit code and stderr.
+    pub fn failure(code: i32, stderr: impl Into<String>) -> Self {
+        Self {
+            status: Status::from_code(code),
+            stdout: String::new(),
+            stderr: stderr.into(),
+        }
+    }
+}
+
+/// Something that can execute container runtime commands, e.g. the `docker` CLI.
+///
+/// Runtimes are shared across threads, e.g. to keep a container attached in the
+/// background while `watch` runs commands in it.
+pub trait ContainerRuntime: Sync {
+    /// Run a command with stdio inherited from the current process.
+    fn run(&self, args: &[String]) -> Result<Status>;
+
+    /// Run a command and capture its stdout and stderr.
+    fn output(&self, args: &[String]) -> Result<Output>;
+
+    /// Like [`ContainerRuntime::run`], but give up on the command once `detach` returns
+    /// true, leaving whatever it started running. Returns `None` when it detached.
+    ///
+    /// `detach` is polled about once a second. Runtimes that can't let go of a command
+    /// just run it.
+    fn run_detachable(&self, args: &[String], detach: &dyn Fn() -> bool) -> Result<Option<Status>> {
+        let _ = detach;
+        self.run(args).map(Some)
+    }
+
+    /// Like [`ContainerRuntime::run`] without a terminal, also copying the command's
+    /// stdout and stderr into `log` as they come.
+    ///
+    /// Runtimes that can't stream copy the captured output once the command is done.
+    fn run_logged(&self, args: &[String], mut log: &File) -> Result<Status> {
+        let output = self.output(args)?;
+        print!("{}", output.stdout);
+        eprint!("{}", output.stderr);
+        log.write_all(output.stdout.as_bytes())?;
+        log.write_all(output.stderr.as_bytes())?;
+        Ok(output.status)
+    }
+}
+
+/// The `docker` CLI.
+#[derive(Debug, Clone, Default)]
+pub struct Docker {
+    context: Option<String>,
+    host: Option<String>,
+}
+
+impl Docker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run every command against the named `docker context` instead of the current one.
+    pub fn context(mut self, name: impl Into<String>) -> Self {
+        self.context = Some(name.into());
+        self
+    }
+
+    /// Run every command against this daemon (e.g. `ssh://user@box`) instead of `DOCKER_HOST`.
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// The daemon's address if it runs on another machine, where host paths don't exist.
+    ///
+    /// Without `--host` or `--context`, `DOCKER_HOST` decides, as it does for `docker`.
+    /// A remote named context isn't detected.
+    pub fn remote_host(&self) -> Option<String> {
+        let host = match (&self.host, &self.context) {
+            (Some(host), _) => Some(host.clone()),
+            (None, Some(_)) => None,
+            (None, None) => std::env::var("DOCKER_HOST").ok(),
+        };
+        host.filter(|host| is_remote_host(host))
+    }
+
+    /// Options selecting the daemon, given before every subcommand.
+    pub(crate) fn global_args(&self) -> Vec<String> {
+        let mut global = Vec::new();
+        if let Some(context) = &self.context {
+            global.extend(["--context".to_string(), context.clone()]);
+        }
+        if let Some(host) = &self.host {
+            global.extend(["-H".to_string(), host.clone()]);
+        }
+        global
+    }
+
+    fn command(&self, args: &[String]) -> Command {
+        let mut command = Command::new("docker");
+        command.args(self.global_args());
+        command.args(args);
+        command
+    }
+}
+
+impl ContainerRuntime for Docker {
+    fn run(&self, args: &[String]) -> Result<Status> {
+        let stdout = if crate::events_enabled() {
+            std::io::stderr().into()
+        } else {
+            Stdio::inherit()
+        };
+        let status = self
+            .command(args)
+            .stdout(stdout)
+            .stderr(Stdio::inherit())
+            .status()
+            .context("Failed to execute docker command. Is docker installed and running?")?;
+        Ok(Status {
+            code: status.code(),
+        })
+    }
+
+    /// Kills the `docker` client, which leaves a container with a terminal running, and
+    /// restores the terminal it had in raw mode.
+    fn run_detachable(&self, args: &[String], detach: &dyn Fn() -> bool) -> Result<Option<Status>> {
+        let stdout = if crate::events_enabled() {
+            std::io::stderr().into()
+        } else {
+            Stdio::inherit()
+        };
+        let mut child = self
+            .command(args)
+            .stdout(stdout)
+            .stderr(Stdio::inherit())
+            .spawn()
+            .context("Failed to execute docker command. Is docker installed and running?")?;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(Some(Status {
+                    code: status.code(),
+                }));
+            }
+            if detach() {
+                child.kill()?;
+                child.wait()?;
+                let _ = Command::new("stty").arg("sane").status();
+                return Ok(None);
+            }
+            std::thread::sleep(std::time::Duration::from_secs(1));
+        }
+    }
+
+    fn run_logged(&self, args: &[String], log: &File) -> Result<Status> {
+        let mut child = self
+            .command(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to execute docker command. Is docker installed and running?")?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let events = crate::events_enabled();
+        std::thread::scope(|scope| -> Result<()> {
+            let out = scope.spawn(|| {
+                if events {
+                    tee(stdout, std::io::stderr(), log)
+                } else {
+                    tee(stdout, std::io::stdout(), log)
+                }
+            });
+            let err = scope.spawn(|| tee(stderr, std::io::stderr(), log));
+            for copy in [out, err] {
+                copy.join().expect("output copy panicked")?;
+            }
+            Ok(())
+        })?;
+        let status = child.wait()?;
+        Ok(Status {
+            code: status.code(),
+        })
+    }
+
+    fn output(&self, args: &[String]) -> Result<Output> {
+        let output = self
+            .command(args)
+            .output()
+            .context("Failed to execute docker command. Is docker installed and running?")?;
+        Ok(Output {
+            status: Status {
+                code: output.status.code(),
+            },
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
+
+/// Copy `input` to `output` and `log` until it ends.
+fn tee(mut input: impl Read, mut output: impl Write, mut log: &File) -> Result<()> {
+    let mut buffer = [0; 8192];
+    loop {
+        let read = match input.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        output.write_all(&buffer[..read])?;
+        output.flush()?;
+        log.write_all(&buffer[..read])
+            .context("Failed to write the captured output")?;
+    }
+}
+
+/// A runtime that records invocations instead of executing them, for tests.
+///
+/// Every call succeeds with empty output unless a response was queued with
+/// [`MockRuntime::respond`].
+#[derive(Debug, Default)]
+pub struct MockRuntime {
+    calls: Mutex<Vec<Vec<String>>>,
+    responses: Mutex<VecDeque<Output>>,
+}
+
+impl MockRuntime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a response for the next `run` or `output` call.
+    pub fn respond(&self, output: Output) -> &Self {
+        self.responses.lock().unwrap().push_back(output);
+        self
+    }
+
+    /// All recorded argument vectors, in call order.
+    pub fn calls(&self) -> Vec<Vec<String>> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn next(&self, args: &[String]) -> Output {
+        self.calls.lock().unwrap().push(args.to_vec());
+        self.responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| Output::success(""))
+    }
+}
+
+impl ContainerRuntime for MockRuntime {
+    fn run(&self, args: &[String]) -> Result<Status> {
+        Ok(self.next(args).status)
+    }
+
+    fn output(&self, args: &[String]) -> Result<Output> {
+        Ok(self.next(args))
+    }
+}
+
+/// Run a command and turn a non-zero exit into an error with better context.
+pub(crate) fn run_checked(
+    runtime: &dyn ContainerRuntime,
+    args: &[String],
+    error_message: &str,
+) -> Result<()> {
+    let status = runtime.run(args)?;
+
+    if !status.success() {
+        return Err(anyhow!(
+            "{}. Docker command exited with non-zero status.",
+            error_message
+        ));
+    }
+
+    Ok(())
+}
+
+/// Convert string literals into an owned argument vector.
+pub(crate) fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|s| s.to_string()).collect()
+}
+
+/// Whether a Docker host address points to another machine: `ssh://` always does, `tcp://`
+/// unless it's the loopback address; `unix://` and `npipe://` never do.
+fn is_remote_host(host: &str) -> bool {
+    if host.starts_with("ssh://") {
+        return true;
+    }
+    let Some(address) = host.strip_prefix("tcp://") else {
+        return false;
+    };
+    let authority = address.split('/').next().unwrap_or_default();
+    let name = match authority.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    !matches!(name, "localhost" | "127.0.0.1" | "::1" | "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command_args(docker: &Docker) -> Vec<String> {
+        docker
+            .command(&args(&["ps", "-a"]))
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn context_is_inserted_before_subcommand() {
+        assert_eq!(command_args(&Docker::new()), args(&["ps", "-a"]));
+        assert_eq!(
+            command_args(&Docker::new().context("remote")),
+            args(&["--context", "remote", "ps", "-a"])
+        );
+        assert_eq!(
+            command_args(&Docker::new().host("ssh://box")),
+            args(&["-H", "ssh://box", "ps", "-a"])
+        );
+    }
+
+    #[test]
+    fn remote_hosts_are_detected() {
+        for host in [
+            "ssh://me@box",
+            "tcp://10.0.0.5:2376",
+            "tcp://[2001:db8::1]:2375",
+        ] {
+            assert!(is_remote_host(host), "{}", host);
+        }
+        for host in [
+            "unix:///var/run/docker.sock",
+            "npipe:////./pipe/docker_engine",
+            "tcp://localhost:2375",
+            "tcp://127.0.0.1:2375",
+            "tcp://[::1]:2375",
+        ] {
+            assert!(!is_remote_host(host), "{}", host);
+        }
+        assert_eq!(
+            Docker::new().host("tcp://10.0.0.5:2376").remote_host(),
+            Some("tcp://10.0.0.5:2376".into())
+        );
+        assert_eq!(
+            Docker::new().host("unix:///run/docker.sock").remote_host(),
+            None
+        );
+    }
+}
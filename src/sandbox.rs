@@ -0,0 +1,4427 @@
+use anyhow::{anyhow, Context, Result};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::container::{
+    cache_volume_name, container_exists, container_name, container_state, list, project_hostname,
+    set_aside_name, CACHE_PROJECT_LABEL, RUN_ID_LABEL,
+};
+use crate::copy_in::Staging;
+use crate::detect::{detect, ProjectKind, DEFAULT_DETECT_ORDER};
+use crate::egress::freeze_on_egress;
+use crate::events::{emit, Event};
+use crate::git::{GitRevision, GIT_BRANCH_LABEL, GIT_COMMIT_LABEL};
+use crate::ignore::IgnoreRules;
+use crate::origin::{join, quote, DOCKER_LABEL, ORIGIN_DIR_LABEL, ORIGIN_LABEL};
+use crate::provenance::Provenance;
+use crate::runtime::{args, run_checked, ContainerRuntime, Docker, Status};
+use crate::scan::escaping_symlinks;
+use crate::style::Marker;
+use crate::validate;
+use crate::{HumanDuration, DOCKER_IMAGE_NAME};
+
+/// Default command run inside the container.
+pub const DEFAULT_CMD: &str = "nvim .";
+
+/// Key sequence that detaches from an attached container, chosen over Docker's Ctrl-P Ctrl-Q
+/// because editors and shells bind those keys.
+pub const DEFAULT_DETACH_KEYS: &str = "ctrl-_,ctrl-q";
+
+/// OOM score adjustment applied when a memory limit is set, so the kernel prefers
+/// killing the sandbox over host processes under memory pressure.
+pub const DEFAULT_OOM_SCORE_ADJ: i32 = 500;
+
+/// Where [`Sandbox::git_config`] mounts the config: Git's system-wide file, read whatever
+/// the user and `HOME`.
+const GIT_CONFIG_TARGET: &str = "/etc/gitconfig";
+/// Where [`Sandbox::ssh_known_hosts`] mounts the file, read by ssh for every user.
+const SSH_KNOWN_HOSTS_TARGET: &str = "/etc/ssh/ssh_known_hosts";
+/// Where [`Sandbox::git_credentials`] mounts the credential store.
+const GIT_CREDENTIALS_TARGET: &str = "/run/safecrate/git-credentials";
+/// `GNUPGHOME` for [`Sandbox::gpg_agent`], holding the agent socket and public keyring.
+const GNUPG_HOME_TARGET: &str = "/run/safecrate/gnupg";
+/// Where [`Sandbox::shell_init`] mounts the script: sourced by login shells through
+/// `/etc/profile`, and by interactive POSIX shells through `ENV`.
+const SHELL_INIT_TARGET: &str = "/etc/profile.d/safecrate-shell-init.sh";
+/// Where [`Sandbox::trace_syscalls`] mounts the host file `strace` writes to.
+const SYSCALL_TRACE_TARGET: &str = "/run/safecrate/syscalls.trace";
+/// Where the [`Sandbox::network_capture`] sidecar writes the packets it captures.
+const NETWORK_CAPTURE_TARGET: &str = "/run/safecrate/capture.pcap";
+/// Memory and process limits of the capture sidecar; tcpdump needs little of either.
+const NETWORK_CAPTURE_MEMORY: &str = "256m";
+const NETWORK_CAPTURE_PIDS_LIMIT: &str = "16";
+
+/// `HOME` of a non-root user unless [`Sandbox::container_user_home`] says otherwise.
+pub const DEFAULT_USER_HOME: &str = "/home/safecrate";
+
+/// Where [`Sandbox::persist_cache`] mounts the cache volume unless told otherwise.
+pub const DEFAULT_CACHE_PATH: &str = "/root/.cache";
+
+/// How often [`Sandbox::readiness_wait`] retries its command.
+const READINESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Modes accepted by [`Sandbox::ipc`], besides `container:NAME`.
+const IPC_MODES: [&str; 4] = ["none", "private", "shareable", "host"];
+
+/// The sandboxed command exited unsuccessfully; returned by [`Sandbox::open_with`] so callers
+/// can pass the exit status on (`code` is `None` for a signal).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandFailed {
+    pub code: Option<i32>,
+}
+
+impl fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(
+                f,
+                "Failed to open container. Docker command exited with status {}.",
+                code
+            ),
+            None => write!(
+                f,
+                "Failed to open container. The command was killed by a signal."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
+/// How strongly the container is isolated from the host kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Isolation {
+    /// Docker's default runtime (runc).
+    #[default]
+    Default,
+    /// gVisor's `runsc`, which intercepts syscalls in a user-space kernel.
+    Gvisor,
+}
+
+impl Isolation {
+    /// OCI runtime that provides this isolation, if not the default.
+    pub fn oci_runtime(&self) -> Option<&'static str> {
+        match self {
+            Isolation::Default => None,
+            Isolation::Gvisor => Some("runsc"),
+        }
+    }
+}
+
+impl FromStr for Isolation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(Isolation::Default),
+            "gvisor" => Ok(Isolation::Gvisor),
+            _ => Err(anyhow!(
+                "Unknown sandbox '{}': expected 'default' or 'gvisor'",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Isolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Isolation::Default => write!(f, "default"),
+            Isolation::Gvisor => write!(f, "gvisor"),
+        }
+    }
+}
+
+/// When `docker run` pulls the image from a registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PullPolicy {
+    /// Always pull, even if the image exists locally.
+    Always,
+    /// Pull only if the image is not present locally (Docker's default).
+    #[default]
+    Missing,
+    /// Never pull; fail if the image is not present locally.
+    Never,
+}
+
+impl FromStr for PullPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "always" => Ok(PullPolicy::Always),
+            "missing" => Ok(PullPolicy::Missing),
+            "never" => Ok(PullPolicy::Never),
+            _ => Err(anyhow!(
+                "Unknown pull policy '{}': expected always, missing, or never",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PullPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PullPolicy::Always => write!(f, "always"),
+            PullPolicy::Missing => write!(f, "missing"),
+            PullPolicy::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// When the container is removed after its command exits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RmPolicy {
+    /// Always remove it, even if the command failed.
+    #[default]
+    Always,
+    /// Remove it only if the command succeeded, keeping it for `resume` otherwise.
+    OnSuccess,
+    /// Keep it.
+    Never,
+}
+
+impl FromStr for RmPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "always" => Ok(RmPolicy::Always),
+            "on-success" => Ok(RmPolicy::OnSuccess),
+            "never" => Ok(RmPolicy::Never),
+            _ => Err(anyhow!(
+                "Unknown removal policy '{}': expected always, on-success, or never",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RmPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RmPolicy::Always => write!(f, "always"),
+            RmPolicy::OnSuccess => write!(f, "on-success"),
+            RmPolicy::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// How mounts under the project directory propagate between the host and the container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BindPropagation {
+    /// Nothing propagates either way (Docker's default).
+    #[default]
+    Rprivate,
+    /// Mounts propagate both ways, so mounts made in the container appear on the host.
+    Rshared,
+    /// Host mounts, e.g. a network drive mounted later, propagate into the container.
+    Rslave,
+}
+
+impl FromStr for BindPropagation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rprivate" => Ok(BindPropagation::Rprivate),
+            "rshared" => Ok(BindPropagation::Rshared),
+            "rslave" => Ok(BindPropagation::Rslave),
+            _ => Err(anyhow!(
+                "Unknown bind propagation '{}': expected rprivate, rshared, or rslave",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BindPropagation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindPropagation::Rprivate => write!(f, "rprivate"),
+            BindPropagation::Rshared => write!(f, "rshared"),
+            BindPropagation::Rslave => write!(f, "rslave"),
+        }
+    }
+}
+
+/// Editor opened by the default command instead of Neovim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Editor {
+    Nvim,
+    Vim,
+    Nano,
+    /// VS Code's `code` command, e.g. from a code-server image.
+    Code,
+    Helix,
+}
+
+impl Editor {
+    /// Shell command that opens the workspace in this editor.
+    pub fn command(self) -> &'static str {
+        match self {
+            Editor::Nvim => "nvim .",
+            Editor::Vim => "vim .",
+            Editor::Nano => "nano",
+            Editor::Code => "code .",
+            Editor::Helix => "hx .",
+        }
+    }
+
+    /// The editor's executable.
+    fn binary(self) -> &'static str {
+        self.command().split(' ').next().unwrap_or_default()
+    }
+}
+
+impl FromStr for Editor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "nvim" => Ok(Editor::Nvim),
+            "vim" => Ok(Editor::Vim),
+            "nano" => Ok(Editor::Nano),
+            "code" => Ok(Editor::Code),
+            "helix" => Ok(Editor::Helix),
+            _ => Err(anyhow!(
+                "Unknown editor '{}': expected nvim, vim, nano, code, or helix",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Editor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Editor::Nvim => write!(f, "nvim"),
+            Editor::Vim => write!(f, "vim"),
+            Editor::Nano => write!(f, "nano"),
+            Editor::Code => write!(f, "code"),
+            Editor::Helix => write!(f, "helix"),
+        }
+    }
+}
+
+/// An additional bind mount into the container.
+#[derive(Debug, Clone)]
+struct Mount {
+    source: PathBuf,
+    target: String,
+    read_only: bool,
+}
+
+fn is_bind_spec(spec: &str) -> bool {
+    spec.split(',').any(|field| field == "type=bind")
+}
+
+/// A `--mount` spec binding the host file given with `flag` at `target`, for output
+/// files. Unlike `-v`, a bind `--mount` fails instead of creating a directory if the file
+/// is missing.
+fn output_file_mount(flag: &str, path: &Path, target: &str) -> Result<String> {
+    let source = std::path::absolute(path)?;
+    let source = source.to_string_lossy();
+    if source.contains(',') {
+        return Err(anyhow!(
+            "Invalid {} {}: the path can't contain a comma",
+            flag,
+            source
+        ));
+    }
+    Ok(format!("type=bind,source={},target={}", source, target))
+}
+
+/// Builder for an isolated container with a project directory mounted at `/workspace`.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    dir: PathBuf,
+    subdir: Option<PathBuf>,
+    copy_in: bool,
+    image: Option<String>,
+    auto_image: bool,
+    detect_order: Vec<ProjectKind>,
+    pull: Option<PullPolicy>,
+    editor: Option<Editor>,
+    cmd: Option<String>,
+    entrypoint: Option<String>,
+    args: Vec<String>,
+    interactive: bool,
+    stdin: bool,
+    tty_size: Option<(u16, u16)>,
+    detach_keys: Option<String>,
+    init: bool,
+    user: Option<String>,
+    mount_target_owner: bool,
+    group_add: Vec<String>,
+    umask: Option<String>,
+    setup: Option<String>,
+    env_passthrough: Vec<String>,
+    cap_add: Vec<String>,
+    cap_drop: Vec<String>,
+    apparmor: Option<String>,
+    hostname_from_project: bool,
+    origin: Option<(Vec<String>, PathBuf)>,
+    mount_specs: Vec<String>,
+    bind_propagation: Option<BindPropagation>,
+    run_id: Option<String>,
+    git_labels: bool,
+    git_config: bool,
+    git_credentials: bool,
+    shell_init: bool,
+    gpg_agent: bool,
+    max_open_containers: Option<u32>,
+    expose: Vec<String>,
+    writable_subdirs: Vec<PathBuf>,
+    ipc: Option<String>,
+    pid: Option<String>,
+    keep_container: bool,
+    keep_on_failure: bool,
+    cmd_timeout: Option<u32>,
+    replace: bool,
+    stop_other: bool,
+    reuse: bool,
+    detach: bool,
+    persist_cache: bool,
+    cache_path: Option<String>,
+    readiness_wait: Option<(String, Duration)>,
+    detach_on_idle: Option<Duration>,
+    capture_output: Option<PathBuf>,
+    trace_syscalls: Option<PathBuf>,
+    network_capture: Option<PathBuf>,
+    freeze_on_network: bool,
+    provenance: Option<Provenance>,
+    home: Option<String>,
+    allow_escaping_symlinks: bool,
+    network: Option<bool>,
+    network_mode: Option<String>,
+    ip: Option<String>,
+    mac_address: Option<String>,
+    memory: Option<String>,
+    mounts: Vec<Mount>,
+    tmpfs: Vec<String>,
+    read_only_rootfs: bool,
+    tmpfs_exec: bool,
+    sysctls: Vec<String>,
+    stop_timeout: Option<u32>,
+    oom_score_adj: Option<i32>,
+    shm_size: Option<String>,
+    cpus: Option<String>,
+    cpu_shares: Option<u32>,
+    cpu_period: Option<u32>,
+    cpu_quota: Option<u32>,
+    cpuset_cpus: Option<String>,
+    cpuset_mems: Option<String>,
+    isolation: Isolation,
+    oci_runtime: Option<String>,
+    annotations: Vec<String>,
+    storage_opts: Vec<String>,
+    log_driver: Option<String>,
+    log_opts: Vec<String>,
+    health_cmd: Option<String>,
+    health_interval: Option<String>,
+    health_retries: Option<u32>,
+}
+
+impl Sandbox {
+    /// Create a sandbox for the given project directory.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            subdir: None,
+            copy_in: false,
+            image: None,
+            auto_image: false,
+            detect_order: DEFAULT_DETECT_ORDER.to_vec(),
+            pull: None,
+            editor: None,
+            cmd: None,
+            entrypoint: None,
+            args: Vec::new(),
+            interactive: true,
+            stdin: false,
+            tty_size: None,
+            detach_keys: None,
+            init: false,
+            user: None,
+            mount_target_owner: false,
+            group_add: Vec::new(),
+            umask: None,
+            setup: None,
+            env_passthrough: Vec::new(),
+            cap_add: Vec::new(),
+            cap_drop: Vec::new(),
+            apparmor: None,
+            hostname_from_project: false,
+            origin: None,
+            mount_specs: Vec::new(),
+            bind_propagation: None,
+            run_id: None,
+            git_labels: true,
+            git_config: false,
+            git_credentials: false,
+            shell_init: false,
+            gpg_agent: false,
+            max_open_containers: None,
+            expose: Vec::new(),
+            writable_subdirs: Vec::new(),
+            ipc: None,
+            pid: None,
+            keep_container: false,
+            keep_on_failure: false,
+            cmd_timeout: None,
+            replace: false,
+            stop_other: false,
+            reuse: false,
+            detach: false,
+            persist_cache: false,
+            cache_path: None,
+            readiness_wait: None,
+            detach_on_idle: None,
+            capture_output: None,
+            trace_syscalls: None,
+            network_capture: None,
+            freeze_on_network: false,
+            provenance: None,
+            home: None,
+            allow_escaping_symlinks: false,
+            network: None,
+            network_mode: None,
+            ip: None,
+            mac_address: None,
+            memory: None,
+            mounts: Vec::new(),
+            tmpfs: Vec::new(),
+            read_only_rootfs: false,
+            tmpfs_exec: false,
+            sysctls: Vec::new(),
+            stop_timeout: None,
+            oom_score_adj: None,
+            shm_size: None,
+            cpus: None,
+            cpu_shares: None,
+            cpu_period: None,
+            cpu_quota: None,
+            cpuset_cpus: None,
+            cpuset_mems: None,
+            isolation: Isolation::Default,
+            oci_runtime: None,
+            annotations: Vec::new(),
+            storage_opts: Vec::new(),
+            log_driver: None,
+            log_opts: Vec::new(),
+            health_cmd: None,
+            health_interval: None,
+            health_retries: None,
+        }
+    }
+
+    /// Mount only this subdirectory of the project (relative to it) as `/workspace`.
+    ///
+    /// The container name is still derived from the project directory.
+    pub fn subdir(mut self, subdir: impl Into<PathBuf>) -> Self {
+        self.subdir = Some(subdir.into());
+        self
+    }
+
+    /// Mount the workspace read-only except for this directory inside it (repeatable).
+    ///
+    /// Relative to the workspace, so to [`Sandbox::subdir`] if one is set.
+    pub fn writable_subdir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.writable_subdirs.push(path.into());
+        self
+    }
+
+    /// Copy the project into the container instead of bind-mounting it.
+    ///
+    /// The container then can't modify the host tree at all. Paths matched by the
+    /// project's `.safecrateignore` are not copied.
+    pub fn copy_in(mut self, copy_in: bool) -> Self {
+        self.copy_in = copy_in;
+        self
+    }
+
+    /// Whether the project is copied in rather than bind-mounted.
+    pub fn is_copy_in(&self) -> bool {
+        self.copy_in
+    }
+
+    /// Image to run (default: [`DOCKER_IMAGE_NAME`], built by `safecrate init`).
+    pub fn image(mut self, image: impl Into<String>) -> Self {
+        self.image = Some(image.into());
+        self
+    }
+
+    /// Pick the image from the project's type (e.g. `safecrate_rust` for a `Cargo.toml`).
+    ///
+    /// Only applies without an explicit [`Sandbox::image`]. When no marker file matches, or
+    /// the matching image hasn't been built, the default image is used.
+    pub fn auto_image(mut self, auto: bool) -> Self {
+        self.auto_image = auto;
+        self
+    }
+
+    /// Order in which project kinds are checked by [`Sandbox::auto_image`].
+    pub fn detect_order(mut self, order: impl IntoIterator<Item = ProjectKind>) -> Self {
+        self.detect_order = order.into_iter().collect();
+        self
+    }
+
+    /// When to pull the image from its registry (Docker's default: [`PullPolicy::Missing`]).
+    pub fn pull(mut self, policy: PullPolicy) -> Self {
+        self.pull = Some(policy);
+        self
+    }
+
+    /// Shell command to run inside the container via `sh -c` (default: `nvim .`).
+    ///
+    /// Can't be combined with [`Sandbox::args`].
+    pub fn cmd(mut self, cmd: impl Into<String>) -> Self {
+        self.cmd = Some(cmd.into());
+        self
+    }
+
+    /// Open `editor` as the default command; [`Sandbox::cmd`] and [`Sandbox::args`] win over it.
+    pub fn editor(mut self, editor: Editor) -> Self {
+        self.editor = Some(editor);
+        self
+    }
+
+    /// Override the image's entrypoint.
+    ///
+    /// Without a [`Sandbox::cmd`] or [`Sandbox::args`] the entrypoint runs with no
+    /// arguments instead of the default command.
+    pub fn entrypoint(mut self, entrypoint: impl Into<String>) -> Self {
+        self.entrypoint = Some(entrypoint.into());
+        self
+    }
+
+    /// Arguments passed verbatim after the image, without a shell.
+    ///
+    /// They become the entrypoint's arguments, or the command itself for images without one.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The shell command run inside the container.
+    pub fn command(&self) -> &str {
+        self.cmd.as_deref().unwrap_or_else(|| self.default_cmd())
+    }
+
+    /// The command run when neither a command nor an entrypoint is given.
+    fn default_cmd(&self) -> &'static str {
+        self.editor.map_or(DEFAULT_CMD, Editor::command)
+    }
+
+    /// Whether the sandbox overrides the entrypoint or passes exec-form arguments.
+    pub(crate) fn has_exec_form(&self) -> bool {
+        self.entrypoint.is_some() || !self.args.is_empty()
+    }
+
+    /// Whether the command runs under `timeout`.
+    pub(crate) fn has_cmd_timeout(&self) -> bool {
+        self.cmd_timeout.is_some()
+    }
+
+    /// Name of the container this sandbox creates.
+    pub fn container_name(&self) -> Result<String> {
+        container_name(&self.dir)
+    }
+
+    /// Attach an interactive terminal (`-it`, the default).
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    /// Pipe safecrate's stdin into the command without a terminal (`-i`), e.g. to run a
+    /// script given on a pipe. Overrides [`Sandbox::interactive`].
+    pub fn stdin(mut self, stdin: bool) -> Self {
+        self.stdin = stdin;
+        self
+    }
+
+    /// Tell the command the terminal's size, in columns and lines, through `COLUMNS` and
+    /// `LINES`.
+    ///
+    /// Docker forwards later resizes itself, but a program that starts before the first one
+    /// arrives (common when exec'ing into a running container) draws at 80x24. Programs that
+    /// prefer these variables to the terminal's own size stop following resizes, so this is
+    /// opt-in. Only applies when a terminal is attached.
+    pub fn tty_size(mut self, columns: u16, lines: u16) -> Self {
+        self.tty_size = Some((columns, lines));
+        self
+    }
+
+    /// `-e COLUMNS=… -e LINES=…` for [`Sandbox::tty_size`].
+    fn tty_size_args(&self) -> Vec<String> {
+        match self.tty_size {
+            Some((columns, lines)) if self.tty() => vec![
+                "-e".into(),
+                format!("COLUMNS={}", columns),
+                "-e".into(),
+                format!("LINES={}", lines),
+            ],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether a terminal is attached, as opposed to only stdin or nothing.
+    fn tty(&self) -> bool {
+        self.interactive && !self.stdin && !self.detach
+    }
+
+    /// `-it` for a terminal, `-i` for stdin alone.
+    fn stdin_flag(&self) -> Option<&'static str> {
+        if self.tty() {
+            Some("-it")
+        } else if self.stdin {
+            Some("-i")
+        } else {
+            None
+        }
+    }
+
+    /// `docker start` flags attaching the output, and the input if there is any.
+    fn attach_flag(&self) -> &'static str {
+        if self.tty() || self.stdin {
+            "-ai"
+        } else {
+            "-a"
+        }
+    }
+
+    /// Key sequence that detaches from the container (default: [`DEFAULT_DETACH_KEYS`]).
+    ///
+    /// Given in Docker's format, e.g. `ctrl-x,x`.
+    pub fn detach_keys(mut self, keys: impl Into<String>) -> Self {
+        self.detach_keys = Some(keys.into());
+        self
+    }
+
+    /// Run a minimal init process as PID 1 that forwards signals and reaps zombies.
+    pub fn init(mut self, init: bool) -> Self {
+        self.init = init;
+        self
+    }
+
+    /// Run as this `USER[:GROUP]` instead of the image's default (usually root).
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    /// Run as the UID and GID owning the project directory, so a non-root command can write
+    /// to `/workspace` without changing ownership on the host. Replaces [`Sandbox::user`].
+    pub fn mount_target_owner(mut self, enabled: bool) -> Self {
+        self.mount_target_owner = enabled;
+        self
+    }
+
+    /// Set `HOME` to this absolute path, on a fresh tmpfs the user can write to unless it's
+    /// in `/workspace`.
+    ///
+    /// Without it, non-root users get [`DEFAULT_USER_HOME`] the same way, since tools that
+    /// write dotfiles fail in the `/` Docker gives users the image doesn't know. Root keeps
+    /// the image's `HOME`.
+    pub fn container_user_home(mut self, path: impl Into<String>) -> Self {
+        self.home = Some(path.into());
+        self
+    }
+
+    /// The `--user` value: the project owner's `UID:GID` with
+    /// [`Sandbox::mount_target_owner`], else the one set with [`Sandbox::user`].
+    fn effective_user(&self) -> Result<Option<String>> {
+        if !self.mount_target_owner {
+            return Ok(self.user.clone());
+        }
+        if self.user.is_some() {
+            return Err(anyhow!(
+                "--mount-target-owner picks the user itself; leave out --user"
+            ));
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let metadata = std::fs::metadata(self.workspace_source()?)?;
+            Ok(Some(format!("{}:{}", metadata.uid(), metadata.gid())))
+        }
+        #[cfg(not(unix))]
+        Err(anyhow!("--mount-target-owner needs Unix file ownership"))
+    }
+
+    /// Add a supplementary group by numeric GID, e.g. to access a mounted socket.
+    pub fn group_add(mut self, gid: impl Into<String>) -> Self {
+        self.group_add.push(gid.into());
+        self
+    }
+
+    /// Umask for processes in the container, e.g. `077` so created files aren't world-readable.
+    ///
+    /// Shell commands are prefixed with `umask`; exec-form commands rely on the image's
+    /// entrypoint honoring `SAFECRATE_UMASK`, as the `safecrate init` image does. Without this
+    /// the image's default applies, usually `022`.
+    pub fn umask(mut self, umask: impl Into<String>) -> Self {
+        self.umask = Some(umask.into());
+        self
+    }
+
+    /// Run `setup` through the shell inside the container before the command, e.g.
+    /// `git config --global --add safe.directory /workspace`; if it fails, the command
+    /// doesn't run and the container exits with its status.
+    pub fn setup(mut self, setup: impl Into<String>) -> Self {
+        self.setup = Some(setup.into());
+        self
+    }
+
+    /// Grant a Linux capability, e.g. `net_bind_service`; case and a `CAP_` prefix don't matter.
+    pub fn cap_add(mut self, capability: impl Into<String>) -> Self {
+        self.cap_add.push(capability.into());
+        self
+    }
+
+    /// Drop a Linux capability from Docker's default set, or `ALL` of them.
+    pub fn cap_drop(mut self, capability: impl Into<String>) -> Self {
+        self.cap_drop.push(capability.into());
+        self
+    }
+
+    /// Record the `safecrate` command line creating the container, and the directory it ran
+    /// in, in the container's labels; see [`crate::origin()`].
+    pub fn origin(mut self, command: Vec<String>, dir: impl Into<PathBuf>) -> Self {
+        self.origin = Some((command, dir.into()));
+        self
+    }
+
+    /// Label the container with a workflow run id, so `safecrate clean --run-id` can remove
+    /// all containers of that run together.
+    pub fn run_id(mut self, id: impl Into<String>) -> Self {
+        self.run_id = Some(id.into());
+        self
+    }
+
+    /// Label the container with the commit and branch the project's Git checkout is at, if
+    /// it is one (on by default).
+    pub fn git_labels(mut self, enabled: bool) -> Self {
+        self.git_labels = enabled;
+        self
+    }
+
+    /// Set the hostname to the project's sanitized basename, for a readable shell prompt.
+    pub fn hostname_from_project(mut self, enabled: bool) -> Self {
+        self.hostname_from_project = enabled;
+        self
+    }
+
+    /// Expose a port, range or `PORT/PROTOCOL` to other containers on the same network.
+    ///
+    /// Nothing is published on the host.
+    pub fn expose(mut self, spec: impl Into<String>) -> Self {
+        self.expose.push(spec.into());
+        self
+    }
+
+    /// IPC namespace: `none`, `private`, `shareable`, `host` or `container:NAME`.
+    ///
+    /// Without it Docker gives the container a private one.
+    pub fn ipc(mut self, mode: impl Into<String>) -> Self {
+        self.ipc = Some(mode.into());
+        self
+    }
+
+    /// PID namespace to join instead of the container's own: `host` or `container:NAME`.
+    pub fn pid(mut self, mode: impl Into<String>) -> Self {
+        self.pid = Some(mode.into());
+        self
+    }
+
+    /// Confine the container with this loaded AppArmor profile, or `unconfined` for none.
+    ///
+    /// Without it Docker applies its `docker-default` profile where AppArmor is enabled.
+    pub fn apparmor(mut self, profile: impl Into<String>) -> Self {
+        self.apparmor = Some(profile.into());
+        self
+    }
+
+    /// Forward the host's value of the environment variable `name`, if it is set.
+    pub fn env_passthrough(mut self, name: impl Into<String>) -> Self {
+        self.env_passthrough.push(name.into());
+        self
+    }
+
+    /// Do not remove the container after exit.
+    pub fn keep_container(mut self, keep: bool) -> Self {
+        self.keep_container = keep;
+        self
+    }
+
+    /// Keep the container only if the command fails, so it can be inspected with `resume`.
+    pub fn keep_on_failure(mut self, keep: bool) -> Self {
+        self.keep_on_failure = keep;
+        self
+    }
+
+    /// Set both [`Sandbox::keep_container`] and [`Sandbox::keep_on_failure`] from one policy.
+    pub fn rm_policy(self, policy: RmPolicy) -> Self {
+        self.keep_container(policy == RmPolicy::Never)
+            .keep_on_failure(policy == RmPolicy::OnSuccess)
+    }
+
+    /// Kill the command after `seconds` (SIGKILL 5s later if it ignores SIGTERM), keeping the
+    /// stopped container for inspection when that happens. Needs `timeout` in the image.
+    pub fn cmd_timeout(mut self, seconds: u32) -> Self {
+        self.cmd_timeout = Some(seconds);
+        self
+    }
+
+    /// Refuse to start a new container while `limit` safecrate containers are running, as a
+    /// guard against runaway automation. Reusing the project's own container is not limited.
+    pub fn max_open_containers(mut self, limit: u32) -> Self {
+        self.max_open_containers = Some(limit);
+        self
+    }
+
+    /// Remove an existing container for the project before creating a fresh one.
+    ///
+    /// Without this, [`Sandbox::open_with`] refuses to run if the container already exists.
+    pub fn replace(mut self, replace: bool) -> Self {
+        self.replace = replace;
+        self
+    }
+
+    /// Stop an existing container for the project and rename it out of the way before
+    /// creating a fresh one, instead of removing it like [`Sandbox::replace`].
+    ///
+    /// The old container keeps its files and shows up in `safecrate list` under its new name.
+    pub fn stop_other(mut self, stop_other: bool) -> Self {
+        self.stop_other = stop_other;
+        self
+    }
+
+    /// Get back into the project's container if there is one, instead of refusing to open.
+    ///
+    /// A running container gets the command exec'd into it, a stopped one is resumed, and
+    /// otherwise a fresh container is created and kept for next time. Ignored when replacing.
+    pub fn reuse(mut self, reuse: bool) -> Self {
+        self.reuse = reuse;
+        self
+    }
+
+    /// Mount the project's own named volume (see [`crate::cache_volume_name`]) at the cache
+    /// path, creating it on first use, so build caches survive throwaway containers.
+    pub fn persist_cache(mut self, enabled: bool) -> Self {
+        self.persist_cache = enabled;
+        self
+    }
+
+    /// Where [`Sandbox::persist_cache`] mounts the volume (default [`DEFAULT_CACHE_PATH`]).
+    pub fn cache_path(mut self, path: impl Into<String>) -> Self {
+        self.cache_path = Some(path.into());
+        self
+    }
+
+    /// Start the container in the background and return once it runs, e.g. for a server.
+    pub fn detach(mut self, detach: bool) -> Self {
+        self.detach = detach;
+        self
+    }
+
+    /// With [`Sandbox::detach`], run `cmd` in the container through `sh -c` every second
+    /// until it succeeds, failing if that takes longer than `timeout`.
+    pub fn readiness_wait(mut self, cmd: impl Into<String>, timeout: Duration) -> Self {
+        self.readiness_wait = Some((cmd.into(), timeout));
+        self
+    }
+
+    /// Let go of the terminal after `idle` without input, leaving the container running to
+    /// `safecrate resume` later. It is removed when the command exits, unless kept.
+    pub fn detach_on_idle(mut self, idle: Duration) -> Self {
+        self.detach_on_idle = Some(idle);
+        self
+    }
+
+    /// Also write the command's stdout and stderr to this host file, replacing it, as a
+    /// transcript of what the code printed.
+    ///
+    /// The output has to pass through safecrate, so this needs a run without a terminal,
+    /// e.g. `safecrate run` or [`Sandbox::stdin`].
+    pub fn capture_output(mut self, path: impl Into<PathBuf>) -> Self {
+        self.capture_output = Some(path.into());
+        self
+    }
+
+    /// Run the command under `strace -f`, writing every system call it and its children
+    /// make to this host file, replacing it.
+    ///
+    /// The image needs `strace`, and the container gets `CAP_SYS_PTRACE`, so this is
+    /// warned about.
+    pub fn trace_syscalls(mut self, path: impl Into<PathBuf>) -> Self {
+        self.trace_syscalls = Some(path.into());
+        self
+    }
+
+    /// Record the container's network traffic to this host pcap file, replacing it.
+    ///
+    /// `tcpdump` runs in a sidecar container from the same image, started first on the
+    /// sandbox's network; the sandbox joins its network namespace, so every packet is
+    /// seen. The image needs `tcpdump`, and networking has to be enabled.
+    pub fn network_capture(mut self, path: impl Into<PathBuf>) -> Self {
+        self.network_capture = Some(path.into());
+        self
+    }
+
+    /// Freeze the container, as `safecrate freeze` does, once the [`Sandbox::network_capture`]
+    /// shows it sending its first packet out, and warn about where it went.
+    pub fn freeze_on_network(mut self, enabled: bool) -> Self {
+        self.freeze_on_network = enabled;
+        self
+    }
+
+    /// Check the image's SLSA provenance with `cosign` on the host before running it.
+    pub fn verify_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+
+    /// Open even if the workspace contains symlinks pointing outside it, warning instead.
+    ///
+    /// The container resolves links in its own filesystem, but host-side tools such as
+    /// editors or post-run hooks follow them to the host.
+    pub fn allow_escaping_symlinks(mut self, allow: bool) -> Self {
+        self.allow_escaping_symlinks = allow;
+        self
+    }
+
+    /// Enable or disable networking (disabled by default).
+    ///
+    /// Disabled networking runs the container with `--network none`.
+    pub fn network(mut self, enabled: bool) -> Self {
+        self.network = Some(enabled);
+        self
+    }
+
+    /// Whether the container will have network access.
+    pub fn network_enabled(&self) -> bool {
+        match (&self.network_mode, self.network) {
+            (_, Some(false)) => false,
+            (Some(mode), _) => mode != "none",
+            (None, enabled) => enabled.unwrap_or(false),
+        }
+    }
+
+    /// Network to attach to, enabling networking unless it was explicitly disabled.
+    ///
+    /// With plain [`Sandbox::network`] the container joins `bridge`.
+    ///
+    /// Use a user-defined network (`docker network create`) for a static [`Sandbox::ip`].
+    pub fn network_mode(mut self, mode: impl Into<String>) -> Self {
+        self.network_mode = Some(mode.into());
+        self
+    }
+
+    /// Static IP address on the attached user-defined network.
+    pub fn ip(mut self, address: impl Into<String>) -> Self {
+        self.ip = Some(address.into());
+        self
+    }
+
+    /// Static MAC address for the container's network interface.
+    pub fn mac_address(mut self, address: impl Into<String>) -> Self {
+        self.mac_address = Some(address.into());
+        self
+    }
+
+    /// Memory limit in Docker's format, e.g. `512m` or `2g`.
+    ///
+    /// Unless [`Sandbox::oom_score_adj`] is set, this also applies
+    /// [`DEFAULT_OOM_SCORE_ADJ`].
+    pub fn memory(mut self, limit: impl Into<String>) -> Self {
+        self.memory = Some(limit.into());
+        self
+    }
+
+    /// OOM killer score adjustment (-1000..=1000); higher means killed sooner.
+    pub fn oom_score_adj(mut self, value: i32) -> Self {
+        self.oom_score_adj = Some(value);
+        self
+    }
+
+    /// Size of `/dev/shm` (Docker's default is `64m`), e.g. `1g`.
+    pub fn shm_size(mut self, size: impl Into<String>) -> Self {
+        self.shm_size = Some(size.into());
+        self
+    }
+
+    /// Hard limit on CPU usage, e.g. `1.5` for one and a half CPUs.
+    ///
+    /// Shorthand for a [`Sandbox::cpu_quota`] over the default period; can't be combined
+    /// with an explicit period or quota.
+    pub fn cpus(mut self, cpus: impl Into<String>) -> Self {
+        self.cpus = Some(cpus.into());
+        self
+    }
+
+    /// Relative CPU weight under contention (default 1024; 2..=262144).
+    ///
+    /// Only matters when CPUs are contended and never caps usage on an idle host.
+    pub fn cpu_shares(mut self, shares: u32) -> Self {
+        self.cpu_shares = Some(shares);
+        self
+    }
+
+    /// Length of the CFS scheduling period in microseconds (default 100000; 1000..=1000000).
+    pub fn cpu_period(mut self, micros: u32) -> Self {
+        self.cpu_period = Some(micros);
+        self
+    }
+
+    /// CPU time in microseconds the container may use per period (at least 1000).
+    ///
+    /// A quota of twice the period allows two full CPUs.
+    pub fn cpu_quota(mut self, micros: u32) -> Self {
+        self.cpu_quota = Some(micros);
+        self
+    }
+
+    /// Pin the container to these CPUs, e.g. `0-3` or `0,2,4`.
+    pub fn cpuset_cpus(mut self, cpus: impl Into<String>) -> Self {
+        self.cpuset_cpus = Some(cpus.into());
+        self
+    }
+
+    /// Restrict the container's memory to these NUMA nodes, e.g. `0`.
+    pub fn cpuset_mems(mut self, nodes: impl Into<String>) -> Self {
+        self.cpuset_mems = Some(nodes.into());
+        self
+    }
+
+    /// Bind mount an additional host path into the container.
+    pub fn mount(mut self, source: impl Into<PathBuf>, target: impl Into<String>) -> Self {
+        self.mounts.push(Mount {
+            source: source.into(),
+            target: target.into(),
+            read_only: false,
+        });
+        self
+    }
+
+    /// Bind mount an additional host path into the container, read-only.
+    pub fn mount_read_only(
+        mut self,
+        source: impl Into<PathBuf>,
+        target: impl Into<String>,
+    ) -> Self {
+        self.mounts.push(Mount {
+            source: source.into(),
+            target: target.into(),
+            read_only: true,
+        });
+        self
+    }
+
+    /// Add a mount in Docker's long `--mount` syntax, e.g.
+    /// `type=volume,source=cache,target=/cache` or `type=tmpfs,target=/scratch,tmpfs-size=64m`.
+    ///
+    /// The spec is checked for a type and target and otherwise forwarded as is.
+    pub fn mount_spec(mut self, spec: impl Into<String>) -> Self {
+        self.mount_specs.push(spec.into());
+        self
+    }
+
+    /// Bind propagation of the `/workspace` mount, for projects containing mount points.
+    ///
+    /// Unset, Docker's `rprivate` applies.
+    pub fn bind_propagation(mut self, propagation: BindPropagation) -> Self {
+        self.bind_propagation = Some(propagation);
+        self
+    }
+
+    /// Whether host paths besides the project are bind mounted.
+    pub fn has_extra_mounts(&self) -> bool {
+        !self.mounts.is_empty()
+            || self.trace_syscalls.is_some()
+            || self.network_capture.is_some()
+            || self.mount_specs.iter().any(|spec| is_bind_spec(spec))
+    }
+
+    /// Mount a Git config file read-only as the container's system-wide config.
+    pub fn git_config(mut self, path: impl Into<PathBuf>) -> Self {
+        self.git_config = true;
+        self.mount_read_only(path, GIT_CONFIG_TARGET)
+    }
+
+    /// Mount an ssh `known_hosts` file read-only as the container's system-wide one.
+    pub fn ssh_known_hosts(self, path: impl Into<PathBuf>) -> Self {
+        self.mount_read_only(path, SSH_KNOWN_HOSTS_TARGET)
+    }
+
+    /// Mount a `git credential-store` file read-only and point Git's credential helper at it.
+    ///
+    /// Anything running in the sandbox can read the credentials.
+    pub fn git_credentials(mut self, path: impl Into<PathBuf>) -> Self {
+        self.git_credentials = true;
+        self.mount_read_only(path, GIT_CREDENTIALS_TARGET)
+    }
+
+    /// Forward a GPG agent socket, ideally the restricted `agent-extra-socket`, so the
+    /// command can sign with your keys. `public_keys` is a keyring (e.g. `pubring.kbx`)
+    /// mounted read-only, which `gpg` needs to find the keys.
+    ///
+    /// Anything running in the sandbox can have the agent sign or decrypt while it runs.
+    pub fn gpg_agent(mut self, socket: impl Into<PathBuf>, public_keys: Option<PathBuf>) -> Self {
+        self.gpg_agent = true;
+        self = self.mount(socket, format!("{}/S.gpg-agent", GNUPG_HOME_TARGET));
+        match public_keys {
+            Some(keyring) => {
+                self.mount_read_only(keyring, format!("{}/pubring.kbx", GNUPG_HOME_TARGET))
+            }
+            None => self,
+        }
+    }
+
+    /// Mount a shell script read-only and have interactive shells source it, for your
+    /// aliases and functions.
+    ///
+    /// `sh` and other POSIX shells read it through `ENV`; bash only as a login shell (`bash -l`).
+    pub fn shell_init(mut self, path: impl Into<PathBuf>) -> Self {
+        self.shell_init = true;
+        self.mount_read_only(path, SHELL_INIT_TARGET)
+    }
+
+    /// Mount an in-memory tmpfs, given as `PATH[:options]` (e.g. `/scratch:size=64m`).
+    pub fn tmpfs(mut self, spec: impl Into<String>) -> Self {
+        self.tmpfs.push(spec.into());
+        self
+    }
+
+    /// Mount the container's root filesystem read-only, with a writable scratch tmpfs at `/tmp`.
+    ///
+    /// The workspace and any extra mounts keep their own permissions.
+    pub fn read_only_rootfs(mut self, read_only: bool) -> Self {
+        self.read_only_rootfs = read_only;
+        self
+    }
+
+    /// Allow executing files from the scratch `/tmp` of a read-only rootfs (`noexec` by default).
+    pub fn tmpfs_exec(mut self, exec: bool) -> Self {
+        self.tmpfs_exec = exec;
+        self
+    }
+
+    /// Set a namespaced kernel parameter, given as `KEY=VALUE`.
+    pub fn sysctl(mut self, spec: impl Into<String>) -> Self {
+        self.sysctls.push(spec.into());
+        self
+    }
+
+    /// Seconds Docker waits after SIGTERM before sending SIGKILL when stopping the container.
+    pub fn stop_timeout(mut self, seconds: u32) -> Self {
+        self.stop_timeout = Some(seconds);
+        self
+    }
+
+    /// Use a hardened runtime such as gVisor.
+    ///
+    /// [`Sandbox::open_with`] checks that the runtime is registered with the daemon first.
+    pub fn isolation(mut self, isolation: Isolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// OCI runtime to run the container with, e.g. `runsc` for gVisor.
+    pub fn oci_runtime(mut self, name: impl Into<String>) -> Self {
+        self.oci_runtime = Some(name.into());
+        self
+    }
+
+    /// Set a storage driver option, given as `KEY=VALUE` (e.g. `size=10G` to cap the writable layer).
+    ///
+    /// `size` needs overlay2 on xfs with `pquota`, or btrfs, zfs or devicemapper.
+    pub fn storage_opt(mut self, spec: impl Into<String>) -> Self {
+        self.storage_opts.push(spec.into());
+        self
+    }
+
+    /// Logging driver for the container's output, e.g. `json-file` or `none`.
+    pub fn log_driver(mut self, driver: impl Into<String>) -> Self {
+        self.log_driver = Some(driver.into());
+        self
+    }
+
+    /// Set a logging driver option, given as `KEY=VALUE` (e.g. `max-size=10m`).
+    pub fn log_opt(mut self, spec: impl Into<String>) -> Self {
+        self.log_opts.push(spec.into());
+        self
+    }
+
+    /// Add an OCI runtime annotation, given as `KEY=VALUE`.
+    pub fn annotation(mut self, spec: impl Into<String>) -> Self {
+        self.annotations.push(spec.into());
+        self
+    }
+
+    /// Command Docker runs inside the container to check its health.
+    pub fn health_cmd(mut self, cmd: impl Into<String>) -> Self {
+        self.health_cmd = Some(cmd.into());
+        self
+    }
+
+    /// Time between health checks, as a duration like `30s`.
+    pub fn health_interval(mut self, interval: impl Into<String>) -> Self {
+        self.health_interval = Some(interval.into());
+        self
+    }
+
+    /// Consecutive failed health checks before the container is reported unhealthy.
+    pub fn health_retries(mut self, retries: u32) -> Self {
+        self.health_retries = Some(retries);
+        self
+    }
+
+    /// Human-readable warnings about options that weaken isolation.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if !self.sysctls.is_empty() {
+            warnings.push(format!(
+                "Custom sysctls ({}) can weaken container isolation.",
+                self.sysctls.join(", ")
+            ));
+        }
+        if let Some(mode) = self
+            .ipc
+            .as_deref()
+            .filter(|mode| *mode != "none" && *mode != "private")
+        {
+            warnings.push(format!(
+                "--ipc {} shares the container's IPC namespace, weakening isolation.",
+                mode
+            ));
+        }
+        if let Some(mode) = self
+            .network_mode
+            .as_deref()
+            .filter(|mode| *mode == "host" || mode.starts_with("container:"))
+            .filter(|_| self.network != Some(false))
+        {
+            warnings.push(format!(
+                "--network-mode {} shares that network namespace, so the sandbox can reach services listening on its loopback.",
+                mode
+            ));
+        }
+        if let Some(mode) = &self.pid {
+            warnings.push(format!(
+                "--pid {} lets the sandbox see and signal processes outside it.",
+                mode
+            ));
+        }
+        if self.bind_propagation == Some(BindPropagation::Rshared) && !self.copy_in {
+            warnings.push(
+                "--bind-propagation rshared lets mounts made in the container appear on the host."
+                    .to_string(),
+            );
+        }
+        for spec in self.mount_specs.iter().filter(|spec| is_bind_spec(spec)) {
+            warnings.push(format!(
+                "--mount-spec {} exposes a host path to the untrusted code.",
+                spec
+            ));
+        }
+        if self.trace_syscalls.is_some() {
+            warnings.push(
+                "--trace-syscalls gives the container CAP_SYS_PTRACE, letting the untrusted code inspect and tamper with its other processes."
+                    .to_string(),
+            );
+        }
+        if self.git_config {
+            warnings.push(
+                "--git-config shows your Git config, and any tokens or paths in it, to the untrusted code."
+                    .to_string(),
+            );
+        }
+        if self.git_credentials {
+            warnings.push(
+                "--git-credentials lets the untrusted code read your stored Git credentials and use them wherever it can connect."
+                    .to_string(),
+            );
+        }
+        if self.gpg_agent {
+            warnings.push(
+                "--gpg lets the untrusted code sign and decrypt with your GPG keys for as long as the sandbox runs."
+                    .to_string(),
+            );
+        }
+        if matches!(self.oom_score_adj, Some(n) if n < 0) {
+            warnings.push(
+                "A negative --oom-score-adj makes the kernel prefer killing host processes over the sandbox."
+                    .to_string(),
+            );
+        }
+        warnings
+    }
+
+    /// Build the `docker` arguments used to run this sandbox.
+    pub fn docker_args(&self) -> Result<Vec<String>> {
+        let container_name = container_name(&self.dir)?;
+
+        let action = if self.copy_in { "create" } else { "run" };
+        let mut args: Vec<String> = vec![action.into()];
+        if self.detach {
+            self.check_detach()?;
+            // `create` doesn't start the container, so there's nothing to detach from.
+            if !self.copy_in {
+                args.push("-d".into());
+            }
+        } else {
+            self.check_detach_on_idle()?;
+            self.check_capture_output()?;
+            args.extend(self.stdin_flag().map(String::from));
+        }
+        // `create` doesn't attach; the keys are passed to `start` instead.
+        if !self.copy_in {
+            args.extend(self.detach_args()?);
+        }
+        if self.init {
+            args.push("--init".into());
+        }
+        if self.auto_remove() {
+            args.push("--rm".into());
+        }
+        args.extend(["--name".into(), container_name]);
+        // With a capture, the sidecar owns the network namespace and its hostname.
+        if self.hostname_from_project && self.network_capture.is_none() {
+            args.extend(["--hostname".into(), project_hostname(&self.dir)?]);
+        }
+
+        let user = self.effective_user()?;
+        if let Some(user) = &user {
+            validate::user(user)?;
+            args.extend(["--user".into(), user.clone()]);
+        }
+        self.push_home_args(&mut args, user.as_deref())?;
+        for gid in &self.group_add {
+            validate::gid(gid)?;
+            args.extend(["--group-add".into(), gid.clone()]);
+        }
+        if let Some(profile) = &self.apparmor {
+            validate::apparmor_profile(profile)?;
+            args.extend(["--security-opt".into(), format!("apparmor={}", profile)]);
+        }
+        for capability in &self.cap_drop {
+            args.extend([
+                "--cap-drop".into(),
+                validate::capability("--cap-drop", capability)?,
+            ]);
+        }
+        for capability in &self.cap_add {
+            args.extend([
+                "--cap-add".into(),
+                validate::capability("--cap-add", capability)?,
+            ]);
+        }
+        if self.trace_syscalls.is_some() {
+            args.extend(["--cap-add".into(), "SYS_PTRACE".into()]);
+        }
+        args.extend(self.tty_size_args());
+        if let Some(umask) = &self.umask {
+            validate::umask(umask)?;
+            args.extend(["-e".into(), format!("SAFECRATE_UMASK={}", umask)]);
+        }
+        if self.git_credentials {
+            // Extends the config Git reads without touching any mounted one.
+            let helper = format!("store --file={}", GIT_CREDENTIALS_TARGET);
+            for (name, value) in [
+                ("GIT_CONFIG_COUNT", "1"),
+                ("GIT_CONFIG_KEY_0", "credential.helper"),
+                ("GIT_CONFIG_VALUE_0", &helper),
+            ] {
+                args.extend(["-e".into(), format!("{}={}", name, value)]);
+            }
+        }
+        if self.shell_init {
+            args.extend(["-e".into(), format!("ENV={}", SHELL_INIT_TARGET)]);
+        }
+        if self.gpg_agent {
+            args.extend(["-e".into(), format!("GNUPGHOME={}", GNUPG_HOME_TARGET)]);
+        }
+        for name in &self.env_passthrough {
+            validate::env_name(name)?;
+            if let Ok(value) = std::env::var(name) {
+                args.extend(["-e".into(), format!("{}={}", name, value)]);
+            }
+        }
+
+        self.push_network_args(&mut args)?;
+        if let Some(mode) = &self.ipc {
+            validate::namespace_mode("--ipc", mode, &IPC_MODES)?;
+            args.extend(["--ipc".into(), mode.clone()]);
+        }
+        if let Some(mode) = &self.pid {
+            validate::namespace_mode("--pid", mode, &["host"])?;
+            args.extend(["--pid".into(), mode.clone()]);
+        }
+        for spec in &self.expose {
+            validate::expose(spec)?;
+            if self.network_capture.is_none() {
+                args.extend(["--expose".into(), spec.clone()]);
+            }
+        }
+
+        if let Some(runtime) = self.runtime_name()? {
+            args.extend(["--runtime".into(), runtime]);
+        }
+
+        for spec in &self.annotations {
+            validate::key_value("--annotation", spec)?;
+            args.extend(["--annotation".into(), spec.clone()]);
+        }
+
+        if let Some(seconds) = self.stop_timeout {
+            args.extend(["--stop-timeout".into(), seconds.to_string()]);
+        }
+
+        if let Some(memory) = &self.memory {
+            validate::size("--memory", memory)?;
+            args.extend(["--memory".into(), memory.clone()]);
+        }
+
+        let oom_score_adj = self
+            .oom_score_adj
+            .or(self.memory.as_ref().map(|_| DEFAULT_OOM_SCORE_ADJ));
+        if let Some(value) = oom_score_adj {
+            validate::oom_score_adj(value)?;
+            args.extend(["--oom-score-adj".into(), value.to_string()]);
+        }
+
+        self.push_cpu_args(&mut args)?;
+
+        if let Some(driver) = &self.log_driver {
+            args.extend(["--log-driver".into(), driver.clone()]);
+        }
+        for spec in &self.log_opts {
+            validate::key_value("--log-opt", spec)?;
+            args.extend(["--log-opt".into(), spec.clone()]);
+        }
+
+        for spec in &self.storage_opts {
+            validate::key_value("--storage-opt", spec)?;
+            args.extend(["--storage-opt".into(), spec.clone()]);
+        }
+
+        if let Some(size) = &self.shm_size {
+            validate::size("--shm-size", size)?;
+            args.extend(["--shm-size".into(), size.clone()]);
+        }
+
+        let abs_dir = self.workspace_source()?;
+        if !self.copy_in {
+            let mut options = Vec::new();
+            if !self.writable_subdirs.is_empty() {
+                options.push("ro".to_string());
+            }
+            options.extend(self.bind_propagation.map(|p| p.to_string()));
+            let mut volume_mapping = format!("{}:/workspace", abs_dir.display());
+            if !options.is_empty() {
+                volume_mapping.push_str(&format!(":{}", options.join(",")));
+            }
+            args.extend(["-v".into(), volume_mapping]);
+        }
+        self.push_writable_subdir_args(&mut args, &abs_dir)?;
+
+        for mount in &self.mounts {
+            let source = std::fs::canonicalize(&mount.source)?;
+            let mut mapping = format!("{}:{}", source.display(), mount.target);
+            if mount.read_only {
+                mapping.push_str(":ro");
+            }
+            args.extend(["-v".into(), mapping]);
+        }
+        if self.persist_cache {
+            let target = self.cache_path.as_deref().unwrap_or(DEFAULT_CACHE_PATH);
+            // The path comes from the untrusted config: a comma would let it append keys,
+            // e.g. `,type=bind,source=/`, which Docker applies over the earlier ones.
+            if !target.starts_with('/') || target.contains([',', '=']) {
+                return Err(anyhow!(
+                    "Invalid --cache-path '{}': expected an absolute path without ',' or '='",
+                    target
+                ));
+            }
+            let spec = format!(
+                "type=volume,source={},target={}",
+                cache_volume_name(&self.dir)?,
+                target
+            );
+            args.extend(["--mount".into(), spec]);
+        }
+        for spec in &self.mount_specs {
+            validate::mount_spec(spec)?;
+            args.extend(["--mount".into(), spec.clone()]);
+        }
+        if let Some(path) = &self.trace_syscalls {
+            args.extend([
+                "--mount".into(),
+                output_file_mount("--trace-syscalls", path, SYSCALL_TRACE_TARGET)?,
+            ]);
+        }
+
+        for spec in &self.tmpfs {
+            validate::tmpfs(spec)?;
+            args.extend(["--tmpfs".into(), spec.clone()]);
+        }
+        self.push_read_only_args(&mut args)?;
+
+        for spec in &self.sysctls {
+            validate::key_value("--sysctl", spec)?;
+            args.extend(["--sysctl".into(), spec.clone()]);
+        }
+
+        if let Some(cmd) = &self.health_cmd {
+            args.extend(["--health-cmd".into(), cmd.clone()]);
+        }
+        if let Some(interval) = &self.health_interval {
+            validate::go_duration("--health-interval", interval)?;
+            args.extend(["--health-interval".into(), interval.clone()]);
+        }
+        if let Some(retries) = self.health_retries {
+            args.extend(["--health-retries".into(), retries.to_string()]);
+        }
+
+        if let Some(id) = &self.run_id {
+            validate::run_id(id)?;
+            args.extend(["--label".into(), format!("{}={}", RUN_ID_LABEL, id)]);
+        }
+        if let Some(revision) = self
+            .git_labels
+            .then(|| GitRevision::of(&self.dir))
+            .flatten()
+        {
+            args.extend([
+                "--label".into(),
+                format!("{}={}", GIT_COMMIT_LABEL, revision.commit),
+            ]);
+            if let Some(branch) = revision.branch {
+                args.extend(["--label".into(), format!("{}={}", GIT_BRANCH_LABEL, branch)]);
+            }
+        }
+
+        let labels_at = args.len();
+        args.extend(["-w".into(), "/workspace".into()]);
+        if let Some(policy) = self.pull {
+            args.extend(["--pull".into(), policy.to_string()]);
+        }
+        if let Some(entrypoint) = &self.entrypoint {
+            args.extend(["--entrypoint".into(), entrypoint.clone()]);
+        }
+        args.push(self.image_ref()?.to_string());
+        args.extend(self.container_command()?);
+
+        if let Some((command, dir)) = &self.origin {
+            let docker_line = join(&[&["docker".to_string()], &args[..]].concat());
+            let labels = [
+                (ORIGIN_LABEL, join(command)),
+                (ORIGIN_DIR_LABEL, dir.display().to_string()),
+                (DOCKER_LABEL, docker_line),
+            ];
+            let labels = labels
+                .into_iter()
+                .flat_map(|(label, value)| ["--label".into(), format!("{}={}", label, value)]);
+            args.splice(labels_at..labels_at, labels);
+        }
+        Ok(args)
+    }
+
+    /// A standalone `sh` script running the same `docker run` as [`Sandbox::open_with`],
+    /// with every argument quoted, e.g. to commit or adapt.
+    ///
+    /// The image [`Sandbox::auto_image`] would detect is assumed to be built. Options that
+    /// need safecrate during the run, such as copying the project in, can't be scripted.
+    pub fn script(&self, docker: &Docker) -> Result<String> {
+        let unscriptable = [
+            (self.copy_in, "--no-mount"),
+            (self.reusing(), "--reuse"),
+            (self.detach_on_idle.is_some(), "--detach-on-idle"),
+            (self.readiness_wait.is_some(), "--readiness-wait"),
+            (self.capture_output.is_some(), "--capture-output"),
+            (self.network_capture.is_some(), "--network-capture"),
+        ]
+        .into_iter()
+        .find_map(|(set, flag)| set.then_some(flag));
+        if let Some(flag) = unscriptable {
+            return Err(anyhow!(
+                "{} needs safecrate while the container runs, so it can't be printed as a script",
+                flag
+            ));
+        }
+        let mut sandbox = self.clone();
+        sandbox.origin = None;
+        if sandbox.auto_image && sandbox.image.is_none() {
+            sandbox.image =
+                detect(&self.workspace_source()?, &self.detect_order).map(|kind| kind.image());
+        }
+        let args = sandbox.docker_args()?;
+        let image_at = args.len() - sandbox.container_command()?.len() - 1;
+        let docker = join(&[&["docker".to_string()], &docker.global_args()[..]].concat());
+
+        let mut script = format!(
+            "#!/bin/sh\n# Written by `safecrate open --print-command-only` for {}.\n",
+            quote(&self.dir.display().to_string())
+        );
+        if self.persist_cache {
+            let project = std::fs::canonicalize(&self.dir)?;
+            script.push_str(&format!(
+                "{} volume create --label {} {} >/dev/null || exit\n",
+                docker,
+                quote(&format!("{}={}", CACHE_PROJECT_LABEL, project.display())),
+                quote(&cache_volume_name(&self.dir)?)
+            ));
+        }
+        if let Some(path) = &self.trace_syscalls {
+            script.push_str(&format!(
+                ": > {} || exit\n",
+                quote(&path.display().to_string())
+            ));
+        }
+        script.push_str(&format!("exec {} {}", docker, quote(&args[0])));
+        // One option and its values per line, then the image and command.
+        for arg in &args[1..image_at] {
+            script.push_str(if arg.starts_with('-') { " \\\n  " } else { " " });
+            script.push_str(&quote(arg));
+        }
+        script.push_str(" \\\n  ");
+        script.push_str(&join(&args[image_at..]));
+        script.push('\n');
+        Ok(script)
+    }
+
+    /// Whether `--rm` removes the container as soon as it exits.
+    fn auto_remove(&self) -> bool {
+        !self.keep_container
+            && !self.keep_on_failure
+            && self.cmd_timeout.is_none()
+            && self.detach_on_idle.is_none()
+            && !self.reusing()
+    }
+
+    /// [`Sandbox::command_line`] under `strace` and `timeout`, if those are requested.
+    fn container_command(&self) -> Result<Vec<String>> {
+        let mut command = self.command_line()?;
+        if self.trace_syscalls.is_some() {
+            command = self.traced(command)?;
+        }
+        let Some(seconds) = self.cmd_timeout else {
+            return Ok(command);
+        };
+        if self.entrypoint.is_some() {
+            return Err(anyhow!(
+                "--cmd-timeout can't wrap a custom --entrypoint; use --cmd or trailing arguments"
+            ));
+        }
+        // --foreground keeps the command on the terminal; it's PID 1's child, so
+        // nothing it leaves behind outlives the container.
+        let mut timed = args(&["timeout", "--foreground", "--kill-after=5"]);
+        timed.push(seconds.to_string());
+        timed.extend(command);
+        Ok(timed)
+    }
+
+    /// `command` under `strace -f`, failing with a clear message if the image lacks it.
+    fn traced(&self, command: Vec<String>) -> Result<Vec<String>> {
+        if self.entrypoint.is_some() {
+            return Err(anyhow!(
+                "--trace-syscalls can't wrap a custom --entrypoint; use --cmd or trailing arguments"
+            ));
+        }
+        if self.reusing() {
+            return Err(anyhow!(
+                "--trace-syscalls needs a fresh container to trace the command; drop --reuse"
+            ));
+        }
+        let script = format!(
+            "command -v strace >/dev/null || {{ echo 'safecrate: --trace-syscalls needs strace in the image' >&2; exit 127; }}\n\
+             exec strace -f -o {} -- \"$@\"",
+            SYSCALL_TRACE_TARGET
+        );
+        let mut traced = vec!["sh".into(), "-c".into(), script, "strace".into()];
+        traced.extend(command);
+        Ok(traced)
+    }
+
+    /// What follows the image: trailing args as-is, else `--cmd` through a shell, else
+    /// nothing for a custom entrypoint, else the default command.
+    fn command_line(&self) -> Result<Vec<String>> {
+        let shell = |cmd: &str| vec!["sh".into(), "-c".into(), self.shell_script(cmd)];
+        if let Some(setup) = &self.setup {
+            validate::cmd(setup)?;
+            if self.entrypoint.is_some() && self.cmd.is_none() {
+                return Err(anyhow!(
+                    "--setup can't run before a custom --entrypoint; put it in --cmd instead"
+                ));
+            }
+        }
+        match (&self.cmd, &self.entrypoint) {
+            (Some(_), _) if !self.args.is_empty() => Err(anyhow!(
+                "--cmd can't be combined with trailing arguments; pass one or the other"
+            )),
+            (None, None)
+                if !self.args.is_empty() && (self.umask.is_some() || self.setup.is_some()) =>
+            {
+                let mut wrapped = shell("exec \"$@\"");
+                wrapped.push("sh".into());
+                wrapped.extend(self.args.iter().cloned());
+                Ok(wrapped)
+            }
+            _ if !self.args.is_empty() => Ok(self.args.clone()),
+            (Some(cmd), _) => {
+                validate::cmd(cmd)?;
+                Ok(shell(cmd))
+            }
+            (None, Some(_)) => Ok(Vec::new()),
+            (None, None) => Ok(shell(self.default_cmd())),
+        }
+    }
+
+    /// `cmd` as run by `sh -c`, with the umask and setup command applied first if set.
+    pub(crate) fn shell_script(&self, cmd: &str) -> String {
+        let mut script = match &self.setup {
+            // Braces and newlines keep `;`, `||` or a trailing comment in either command
+            // from getting past `|| exit`.
+            Some(setup) => format!("{{ {}\n}} || exit\n{}", setup, cmd),
+            None => cmd.to_string(),
+        };
+        if let Some(umask) = &self.umask {
+            script = format!("umask {} && {}", umask, script);
+        }
+        script
+    }
+
+    fn push_network_args(&self, args: &mut Vec<String>) -> Result<()> {
+        if self.freeze_on_network && self.network_capture.is_none() {
+            return Err(anyhow!(
+                "--freeze-on-network watches the --network-capture file, so it needs one"
+            ));
+        }
+        if self.network_capture.is_some() {
+            // Checks the sidecar's network options too.
+            self.capture_args()?;
+            args.extend([
+                "--network".into(),
+                format!("container:{}", self.capture_name()?),
+            ]);
+        } else {
+            args.extend(self.network_args()?);
+        }
+        Ok(())
+    }
+
+    /// `--network` and the options that go with it.
+    fn network_args(&self) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+        let mode = match (&self.network_mode, self.network) {
+            (Some(mode), Some(false)) if mode != "none" => {
+                return Err(anyhow!(
+                    "--network-mode {} conflicts with disabled networking",
+                    mode
+                ))
+            }
+            (_, Some(false)) | (None, None) => "none",
+            (Some(mode), _) => mode.as_str(),
+            (None, Some(true)) => "bridge",
+        };
+        args.extend(["--network".into(), mode.into()]);
+
+        if let Some(ip) = &self.ip {
+            validate::ip(ip)?;
+            if matches!(mode, "bridge" | "host" | "none") {
+                return Err(anyhow!(
+                    "--ip requires a user-defined network; create one with `docker network create --subnet ...` and pass --network-mode NAME"
+                ));
+            }
+            let flag = if ip.contains(':') { "--ip6" } else { "--ip" };
+            args.extend([flag.into(), ip.clone()]);
+        }
+
+        if let Some(mac) = &self.mac_address {
+            validate::mac_address(mac)?;
+            if mode == "none" {
+                return Err(anyhow!("--mac-address requires networking"));
+            }
+            args.extend(["--mac-address".into(), mac.clone()]);
+        }
+
+        Ok(args)
+    }
+
+    /// Name of the [`Sandbox::network_capture`] sidecar.
+    fn capture_name(&self) -> Result<String> {
+        Ok(format!("{}-capture", self.container_name()?))
+    }
+
+    /// `docker run` arguments for the [`Sandbox::network_capture`] sidecar.
+    fn capture_args(&self) -> Result<Vec<String>> {
+        let Some(path) = &self.network_capture else {
+            return Err(anyhow!("No --network-capture file set"));
+        };
+        if self.detach_on_idle.is_some() || self.reusing() {
+            return Err(anyhow!(
+                "--network-capture stops capturing when the command exits, so it can't be combined with --detach-on-idle or --reuse"
+            ));
+        }
+        let network = self.network_args()?;
+        if matches!(network[1].as_str(), "none" | "host") || network[1].starts_with("container:") {
+            return Err(anyhow!(
+                "--network-capture needs the container on its own network; enable it with --network or --network-mode NAME"
+            ));
+        }
+        let mut args = args(&["run", "-d", "--rm", "--name", &self.capture_name()?]);
+        args.extend(network);
+        if self.hostname_from_project {
+            args.extend(["--hostname".into(), project_hostname(&self.dir)?]);
+        }
+        for spec in &self.expose {
+            args.extend(["--expose".into(), spec.clone()]);
+        }
+        // The sidecar runs the project's image and owns the sandbox's network namespace,
+        // so it keeps only what tcpdump needs: NET_RAW to capture, and DAC_OVERRIDE for
+        // root to open the capture file the host user created. No NET_ADMIN, which would
+        // let it reconfigure the sandbox's network.
+        args.extend(
+            [
+                "--cap-drop",
+                "ALL",
+                "--cap-add",
+                "NET_RAW",
+                "--cap-add",
+                "DAC_OVERRIDE",
+                "--security-opt",
+                "no-new-privileges",
+                "--read-only",
+                "--memory",
+                NETWORK_CAPTURE_MEMORY,
+                "--pids-limit",
+                NETWORK_CAPTURE_PIDS_LIMIT,
+            ]
+            .map(String::from),
+        );
+        if let Some(profile) = &self.apparmor {
+            validate::apparmor_profile(profile)?;
+            args.extend(["--security-opt".into(), format!("apparmor={}", profile)]);
+        }
+        args.extend([
+            "--mount".into(),
+            output_file_mount("--network-capture", path, NETWORK_CAPTURE_TARGET)?,
+            "--entrypoint".into(),
+            "tcpdump".into(),
+            self.image_ref()?.to_string(),
+        ]);
+        // Packet-buffered, so stopping the sidecar loses nothing; -Z root keeps tcpdump
+        // from switching to a user that can't write the host file.
+        args.extend(
+            [
+                "-i",
+                "any",
+                "-U",
+                "-Z",
+                "root",
+                "-w",
+                NETWORK_CAPTURE_TARGET,
+            ]
+            .map(String::from),
+        );
+        Ok(args)
+    }
+
+    /// Start the [`Sandbox::network_capture`] sidecar, if requested, returning its name.
+    fn start_network_capture(&self, runtime: &dyn ContainerRuntime) -> Result<Option<String>> {
+        let Some(path) = &self.network_capture else {
+            return Ok(None);
+        };
+        std::fs::File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        let name = self.capture_name()?;
+        let output = runtime.output(&self.capture_args()?)?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to start the network capture {}; the image needs tcpdump, e.g. from `safecrate init --add-package tcpdump`: {}",
+                name,
+                output.stderr.trim()
+            ));
+        }
+        say!(
+            "{} Capturing network traffic to {}",
+            Marker::Step,
+            path.display()
+        );
+        Ok(Some(name))
+    }
+
+    /// Stop the capture sidecar, which flushes the pcap file and removes it.
+    fn stop_network_capture(&self, runtime: &dyn ContainerRuntime, name: &str) -> Result<()> {
+        run_checked(
+            runtime,
+            &args(&["stop", name]),
+            "Failed to stop the network capture",
+        )?;
+        if let Some(path) = &self.network_capture {
+            say!("{} Saved network capture to {}", Marker::Ok, path.display());
+        }
+        Ok(())
+    }
+
+    /// `HOME` and the tmpfs holding it, for [`Sandbox::container_user_home`].
+    fn push_home_args(&self, args: &mut Vec<String>, user: Option<&str>) -> Result<()> {
+        let (uid, gid) = match user {
+            Some(user) => user.split_once(':').unwrap_or((user, "")),
+            None => ("0", ""),
+        };
+        let home = match &self.home {
+            Some(home) => home.as_str(),
+            None if uid != "0" && uid != "root" => DEFAULT_USER_HOME,
+            None => return Ok(()),
+        };
+        if !home.starts_with('/') || home == "/" || home.contains([':', ',']) {
+            return Err(anyhow!(
+                "Invalid --container-user-home '{}': expected an absolute path other than /",
+                home
+            ));
+        }
+        args.extend(["-e".into(), format!("HOME={}", home)]);
+        if home == "/workspace" || home.starts_with("/workspace/") {
+            return Ok(());
+        }
+        let numeric = |id: &str| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit());
+        // Owned by the user when its IDs are known; a name is only resolved in the container.
+        let mut spec = format!("{}:exec", home);
+        if numeric(uid) {
+            spec.push_str(&format!(",mode=0700,uid={}", uid));
+            if numeric(gid) {
+                spec.push_str(&format!(",gid={}", gid));
+            }
+        } else {
+            spec.push_str(",mode=1777");
+        }
+        args.extend(["--tmpfs".into(), spec]);
+        Ok(())
+    }
+
+    /// Writable mounts over the read-only workspace, for [`Sandbox::writable_subdir`].
+    fn push_writable_subdir_args(&self, args: &mut Vec<String>, workspace: &Path) -> Result<()> {
+        if self.writable_subdirs.is_empty() {
+            return Ok(());
+        }
+        if self.copy_in {
+            return Err(anyhow!(
+                "--writable-subdir can't be combined with --no-mount: a copied-in workspace isn't on the host"
+            ));
+        }
+        for subdir in &self.writable_subdirs {
+            // Resolved so a symlink in the project can't point the mount at another host path.
+            let source = std::fs::canonicalize(workspace.join(subdir))
+                .map_err(|e| anyhow!("Invalid --writable-subdir {}: {}", subdir.display(), e))?;
+            let relative = match source.strip_prefix(workspace) {
+                Ok(relative) if source.is_dir() && !relative.as_os_str().is_empty() => relative,
+                _ => {
+                    return Err(anyhow!(
+                        "Invalid --writable-subdir {}: must be a directory inside {}",
+                        subdir.display(),
+                        workspace.display()
+                    ))
+                }
+            };
+            args.extend([
+                "-v".into(),
+                format!("{}:/workspace/{}", source.display(), relative.display()),
+            ]);
+        }
+        Ok(())
+    }
+
+    fn push_read_only_args(&self, args: &mut Vec<String>) -> Result<()> {
+        if !self.read_only_rootfs {
+            if self.tmpfs_exec {
+                return Err(anyhow!(
+                    "--read-only-tmpfs-exec requires --read-only-rootfs"
+                ));
+            }
+            return Ok(());
+        }
+        if self.copy_in {
+            return Err(anyhow!(
+                "--read-only-rootfs can't be combined with --no-mount: the workspace couldn't be copied in"
+            ));
+        }
+        args.push("--read-only".into());
+
+        // A user-supplied /tmp tmpfs takes precedence over the scratch default.
+        let has_tmp = self
+            .tmpfs
+            .iter()
+            .any(|spec| spec.split(':').next() == Some("/tmp"));
+        if !has_tmp {
+            let exec = if self.tmpfs_exec { "exec" } else { "noexec" };
+            args.extend(["--tmpfs".into(), format!("/tmp:rw,{},nosuid", exec)]);
+        }
+        Ok(())
+    }
+
+    fn push_cpu_args(&self, args: &mut Vec<String>) -> Result<()> {
+        if let Some(cpus) = &self.cpus {
+            validate::cpus(cpus)?;
+            if self.cpu_period.is_some() || self.cpu_quota.is_some() {
+                return Err(anyhow!(
+                    "--cpus can't be combined with --cpu-period or --cpu-quota; use one or the other"
+                ));
+            }
+            args.extend(["--cpus".into(), cpus.clone()]);
+        }
+        if let Some(shares) = self.cpu_shares {
+            validate::in_range("--cpu-shares", shares, 2, 262_144)?;
+            args.extend(["--cpu-shares".into(), shares.to_string()]);
+        }
+        if let Some(period) = self.cpu_period {
+            validate::in_range("--cpu-period", period, 1_000, 1_000_000)?;
+            args.extend(["--cpu-period".into(), period.to_string()]);
+        }
+        if let Some(quota) = self.cpu_quota {
+            validate::in_range("--cpu-quota", quota, 1_000, u32::MAX)?;
+            args.extend(["--cpu-quota".into(), quota.to_string()]);
+        }
+        if let Some(cpus) = &self.cpuset_cpus {
+            validate::cpuset("--cpuset-cpus", cpus)?;
+            args.extend(["--cpuset-cpus".into(), cpus.clone()]);
+        }
+        if let Some(nodes) = &self.cpuset_mems {
+            validate::cpuset("--cpuset-mems", nodes)?;
+            args.extend(["--cpuset-mems".into(), nodes.clone()]);
+        }
+        Ok(())
+    }
+
+    /// The OCI runtime implied by the isolation level and any explicit override.
+    fn runtime_name(&self) -> Result<Option<String>> {
+        match (self.isolation.oci_runtime(), &self.oci_runtime) {
+            (Some(implied), Some(explicit)) if implied != explicit => Err(anyhow!(
+                "--sandbox {} requires the '{}' runtime, but --runtime-oci {} was given",
+                self.isolation,
+                implied,
+                explicit
+            )),
+            (_, Some(explicit)) => Ok(Some(explicit.clone())),
+            (implied, None) => Ok(implied.map(str::to_string)),
+        }
+    }
+
+    /// Fail with installation guidance if a hardened runtime isn't registered with Docker.
+    fn check_isolation(&self, runtime: &dyn ContainerRuntime) -> Result<()> {
+        let Some(required) = self.isolation.oci_runtime() else {
+            return Ok(());
+        };
+
+        let output = runtime.output(&args(&[
+            "info",
+            "--format",
+            "{{range $name, $_ := .Runtimes}}{{$name}} {{end}}",
+        ]))?;
+        if output.status.success() && output.stdout.split_whitespace().any(|r| r == required) {
+            return Ok(());
+        }
+
+        Err(anyhow!(
+            "--sandbox {} needs the '{}' runtime, but Docker doesn't know about it.\n\
+             \tInstall gVisor and register it with `sudo runsc install && sudo systemctl restart docker`.\n\
+             \tSee https://gvisor.dev/docs/user_guide/install/",
+            self.isolation,
+            required
+        ))
+    }
+
+    /// Refuse to open a workspace with symlinks escaping it, unless explicitly allowed.
+    pub(crate) fn check_symlinks(&self) -> Result<()> {
+        let escaping = escaping_symlinks(&self.workspace_source()?)?;
+        if escaping.is_empty() {
+            return Ok(());
+        }
+
+        const SHOWN: usize = 10;
+        let mut list: String = escaping
+            .iter()
+            .take(SHOWN)
+            .map(|(link, target)| format!("\n\t{} -> {}", link.display(), target.display()))
+            .collect();
+        if escaping.len() > SHOWN {
+            list.push_str(&format!("\n\t... and {} more", escaping.len() - SHOWN));
+        }
+
+        if self.allow_escaping_symlinks {
+            say!(
+                "{} WARNING: {} symlink(s) point outside the project:{}",
+                Marker::Warning,
+                escaping.len(),
+                list
+            );
+            return Ok(());
+        }
+        Err(anyhow!(
+            "Refusing to open: {} symlink(s) point outside the project:{}\n\
+             \tRemove them, or re-run with --allow-escaping-symlinks to open anyway.",
+            escaping.len(),
+            list
+        ))
+    }
+
+    /// Warn when the storage driver is known not to support `--storage-opt`.
+    fn check_storage(&self, runtime: &dyn ContainerRuntime) -> Result<()> {
+        if self.storage_opts.is_empty() {
+            return Ok(());
+        }
+
+        let output = runtime.output(&args(&["info", "--format", "{{.Driver}}"]))?;
+        let driver = output.stdout.trim();
+        if output.status.success()
+            && !["overlay2", "btrfs", "zfs", "devicemapper", "windowsfilter"].contains(&driver)
+        {
+            say!(
+                "{} WARNING: the '{}' storage driver doesn't support --storage-opt; Docker will likely refuse to start the container.",
+                Marker::Warning,
+                driver
+            );
+        }
+        Ok(())
+    }
+
+    /// Warn when an AppArmor profile is requested but the host doesn't enforce AppArmor.
+    fn check_apparmor(&self, runtime: &dyn ContainerRuntime) -> Result<()> {
+        let Some(profile) = self.apparmor.as_deref().filter(|p| *p != "unconfined") else {
+            return Ok(());
+        };
+
+        let output = runtime.output(&args(&["info", "--format", "{{.SecurityOptions}}"]))?;
+        if output.status.success() && !output.stdout.contains("name=apparmor") {
+            say!(
+                "{} WARNING: AppArmor isn't enabled on the Docker host, so --apparmor {} has no effect.",
+                Marker::Warning,
+                profile
+            );
+        }
+        Ok(())
+    }
+
+    /// Fail if [`Sandbox::max_open_containers`] are already running, besides the project's own,
+    /// which `--replace` may be about to remove.
+    fn check_open_containers(&self, runtime: &dyn ContainerRuntime) -> Result<()> {
+        let Some(limit) = self.max_open_containers else {
+            return Ok(());
+        };
+        let container_name = self.container_name()?;
+        let running = list(runtime, None)?
+            .into_iter()
+            .filter(|container| container.state == "running" && container.name != container_name)
+            .count();
+        if running >= limit as usize {
+            return Err(anyhow!(
+                "Refusing to open: the limit of {} running safecrate containers (--max-open-containers) is reached.\n\
+                 \tSee them with `safecrate list` and remove some with `safecrate remove`.",
+                limit
+            ));
+        }
+        Ok(())
+    }
+
+    /// The image to run, checked so it can't pass for a `docker` flag.
+    fn image_ref(&self) -> Result<&str> {
+        let image = self.image.as_deref().unwrap_or(DOCKER_IMAGE_NAME);
+        validate::image(image)?;
+        Ok(image)
+    }
+
+    /// Warn when the chosen editor isn't installed in the image, checked without pulling it.
+    fn check_editor(&self, runtime: &dyn ContainerRuntime) -> Result<()> {
+        let Some(editor) = self.editor else {
+            return Ok(());
+        };
+        if self.cmd.is_some() || self.has_exec_form() {
+            return Ok(());
+        }
+
+        let image = self.image_ref()?;
+        let output = runtime.output(&args(&[
+            "run",
+            "--rm",
+            "--pull",
+            "never",
+            "--network",
+            "none",
+            "--entrypoint",
+            "sh",
+            image,
+            "-c",
+            &format!("command -v {}", editor.binary()),
+        ]))?;
+        // `command -v` exits 1 for a missing command; other failures are Docker's and
+        // surface when the container starts.
+        if output.status.code == Some(1) {
+            say!(
+                "{} WARNING: {} isn't installed in {}; add it with `safecrate init --add-package` or use another --editor.",
+                Marker::Warning,
+                editor.binary(),
+                image
+            );
+        }
+        Ok(())
+    }
+
+    /// Create the container, copy the filtered project into it, then start it attached.
+    fn run_copied(&self, runtime: &dyn ContainerRuntime, create_args: &[String]) -> Result<Status> {
+        let container_name = self.container_name()?;
+        let source = self.workspace_source()?;
+        let staging = Staging::create(&source, &IgnoreRules::load(&source)?, &container_name)?;
+
+        run_checked(runtime, create_args, "Failed to create container")?;
+        emit(Event::ContainerCreated {
+            name: container_name.clone(),
+        });
+
+        let mut copy_args = args(&["cp"]);
+        // Keep the staged files' owner, i.e. the user the container runs as.
+        if self.mount_target_owner {
+            copy_args.push("--archive".into());
+        }
+        copy_args.extend([
+            format!("{}/.", staging.path().display()),
+            format!("{}:/workspace", container_name),
+        ]);
+        if let Err(err) = run_checked(runtime, &copy_args, "Failed to copy project into container")
+        {
+            let _ = runtime.output(&args(&["rm", "-f", &container_name]));
+            return Err(err);
+        }
+        drop(staging);
+
+        let mut start = args(&["start"]);
+        if !self.detach {
+            start.push(self.attach_flag().into());
+        }
+        start.extend(self.detach_args()?);
+        start.push(container_name);
+        self.run_attached(runtime, &start)
+    }
+
+    /// Create the project's cache volume, labelled with the project it belongs to; a no-op if
+    /// it exists.
+    fn create_cache_volume(&self, runtime: &dyn ContainerRuntime) -> Result<()> {
+        let project = std::fs::canonicalize(&self.dir)?;
+        run_checked(
+            runtime,
+            &args(&[
+                "volume",
+                "create",
+                "--label",
+                &format!("{}={}", CACHE_PROJECT_LABEL, project.display()),
+                &cache_volume_name(&self.dir)?,
+            ]),
+            "Failed to create the cache volume",
+        )
+    }
+
+    /// Refuse options that wait for the command to exit, which a detached run doesn't.
+    fn check_detach(&self) -> Result<()> {
+        let conflict = [
+            (self.keep_on_failure, "--keep-on-failure"),
+            (self.cmd_timeout.is_some(), "--cmd-timeout"),
+            (self.reusing(), "--reuse"),
+            (self.stdin, "--cmd-stdin"),
+            (self.capture_output.is_some(), "--capture-output"),
+            (self.network_capture.is_some(), "--network-capture"),
+        ]
+        .into_iter()
+        .find_map(|(set, flag)| set.then_some(flag));
+        match conflict {
+            Some(flag) => Err(anyhow!(
+                "--detach can't be combined with {}, which waits for the command",
+                flag
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn check_capture_output(&self) -> Result<()> {
+        if self.capture_output.is_some() && (self.tty() || self.reusing()) {
+            return Err(anyhow!(
+                "--capture-output needs the output to pass through safecrate, which a terminal session doesn't; \
+                 use `safecrate run` or --cmd-stdin, without --reuse"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Run an attached `docker` command, copying its output to the capture file if set.
+    fn run_attached(&self, runtime: &dyn ContainerRuntime, args: &[String]) -> Result<Status> {
+        let Some(path) = &self.capture_output else {
+            return runtime.run(args);
+        };
+        let log = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        runtime.run_logged(args, &log)
+    }
+
+    fn check_detach_on_idle(&self) -> Result<()> {
+        if self.detach_on_idle.is_none() {
+            return Ok(());
+        }
+        if !self.tty() {
+            return Err(anyhow!(
+                "--detach-on-idle watches the terminal for input, so it needs an interactive session"
+            ));
+        }
+        if self.copy_in || self.reusing() {
+            return Err(anyhow!(
+                "--detach-on-idle can't be combined with {}",
+                if self.copy_in {
+                    "--no-mount"
+                } else {
+                    "--reuse"
+                }
+            ));
+        }
+        Ok(())
+    }
+
+    /// Poll the readiness command of a detached container until it succeeds or times out.
+    fn wait_until_ready(&self, runtime: &dyn ContainerRuntime, container_name: &str) -> Result<()> {
+        let Some((cmd, timeout)) = &self.readiness_wait else {
+            return Ok(());
+        };
+        validate::cmd(cmd)?;
+        say!(
+            "{} Waiting for {} to be ready: {}",
+            Marker::Step,
+            container_name,
+            cmd
+        );
+        let start = Instant::now();
+        loop {
+            let output = runtime.output(&args(&["exec", container_name, "sh", "-c", cmd]))?;
+            if output.status.success() {
+                say!("{} {} is ready", Marker::Ok, container_name);
+                return Ok(());
+            }
+            if container_state(runtime, container_name)?.as_deref() != Some("running") {
+                return Err(anyhow!(
+                    "Container {} stopped before it was ready. See `docker logs {}`.",
+                    container_name,
+                    container_name
+                ));
+            }
+            if start.elapsed() >= *timeout {
+                return Err(anyhow!(
+                    "Container {} wasn't ready within {}s: `{}` kept failing. It is still running; \
+                     stop it with `safecrate remove --force {}`.",
+                    container_name,
+                    timeout.as_secs(),
+                    cmd,
+                    self.dir.display()
+                ));
+            }
+            std::thread::sleep(READINESS_INTERVAL);
+        }
+    }
+
+    /// `--detach-keys` for commands that attach a terminal.
+    fn detach_args(&self) -> Result<Vec<String>> {
+        if !self.tty() {
+            return Ok(Vec::new());
+        }
+        let keys = self.detach_keys.as_deref().unwrap_or(DEFAULT_DETACH_KEYS);
+        validate::detach_keys(keys)?;
+        Ok(vec!["--detach-keys".into(), keys.into()])
+    }
+
+    fn already_exists(&self, container_name: &str) -> anyhow::Error {
+        anyhow!(
+            "A container for this project already exists ({}) — use `safecrate resume {dir}`, \
+             `safecrate remove {dir}`, or `safecrate open --replace {dir}`.",
+            container_name,
+            dir = self.dir.display()
+        )
+    }
+
+    /// This sandbox with its image chosen by [`Sandbox::auto_image`], if that applies.
+    pub(crate) fn with_detected_image(&self, runtime: &dyn ContainerRuntime) -> Result<Sandbox> {
+        let mut sandbox = self.clone();
+        if !self.auto_image || self.image.is_some() {
+            return Ok(sandbox);
+        }
+        sandbox.auto_image = false;
+
+        let Some(kind) = detect(&self.workspace_source()?, &self.detect_order) else {
+            return Ok(sandbox);
+        };
+        let image = kind.image();
+        let built = runtime
+            .output(&args(&["image", "inspect", "--format", "{{.Id}}", &image]))?
+            .status
+            .success();
+        if built {
+            say!(
+                "{} Detected a {} project; using {}",
+                Marker::Step,
+                kind,
+                image
+            );
+            sandbox.image = Some(image);
+        } else {
+            say!(
+                "{} Detected a {} project, but {} isn't built; using {}",
+                Marker::Step,
+                kind,
+                image,
+                DOCKER_IMAGE_NAME
+            );
+        }
+        Ok(sandbox)
+    }
+
+    fn reusing(&self) -> bool {
+        self.reuse && !self.replace
+    }
+
+    /// Exec into the project's running container or resume its stopped one, if it has one.
+    ///
+    /// Returns `None` when there is no container and a fresh one should be created.
+    fn reuse_existing(&self, runtime: &dyn ContainerRuntime) -> Result<Option<Status>> {
+        let container_name = self.container_name()?;
+        let Some(state) = container_state(runtime, &container_name)? else {
+            say!(
+                "{} No container for this project yet; creating {}",
+                Marker::Step,
+                container_name
+            );
+            return Ok(None);
+        };
+
+        let mut docker_args = if state == "running" {
+            say!(
+                "{} {} is running; starting the command in it",
+                Marker::Step,
+                container_name
+            );
+            let mut exec = vec!["exec".to_string()];
+            exec.extend(self.stdin_flag().map(String::from));
+            exec.extend(self.detach_args()?);
+            exec.extend(self.tty_size_args());
+            exec.extend(["-w".into(), "/workspace".into(), container_name]);
+            exec.extend(self.entrypoint.clone());
+            exec
+        } else {
+            say!(
+                "{} {} is {}; resuming it",
+                Marker::Step,
+                container_name,
+                state
+            );
+            let attach = self.attach_flag();
+            let mut start = args(&["start", attach]);
+            start.extend(self.detach_args()?);
+            start.push(container_name);
+            return runtime.run(&start).map(Some);
+        };
+        docker_args.extend(self.container_command()?);
+        runtime.run(&docker_args).map(Some)
+    }
+
+    /// Run the container attached until its command exits, or `None` if the session was
+    /// detached from for [`Sandbox::detach_on_idle`].
+    fn run_foreground(
+        &self,
+        runtime: &dyn ContainerRuntime,
+        docker_args: &[String],
+        container_name: &str,
+    ) -> Result<Option<Status>> {
+        if self.copy_in {
+            return self.run_copied(runtime, docker_args).map(Some);
+        }
+        emit(Event::ContainerCreated {
+            name: container_name.to_string(),
+        });
+        let Some(idle) = self.detach_on_idle else {
+            return self.run_attached(runtime, docker_args).map(Some);
+        };
+        let idle_for = || terminal_idle().is_some_and(|elapsed| elapsed >= idle);
+        let status = runtime.run_detachable(docker_args, &idle_for)?;
+        if status.is_none() {
+            say!(
+                "\n{} Detached from {} after {} without input; it keeps running.\n\
+                 \tRun `safecrate resume {dir}` to get back into it, or `safecrate remove --force {dir}` to stop it.",
+                Marker::Ok,
+                container_name,
+                HumanDuration(idle),
+                dir = self.dir.display()
+            );
+        }
+        Ok(status)
+    }
+
+    /// Fail if the project's container already exists, or remove it when replacing.
+    pub(crate) fn clear_existing(&self, runtime: &dyn ContainerRuntime) -> Result<()> {
+        let container_name = self.container_name()?;
+        if !container_exists(runtime, &container_name)? {
+            return Ok(());
+        }
+        if self.stop_other && !self.replace {
+            return self.set_aside(runtime, &container_name);
+        }
+        if !self.replace {
+            return Err(self.already_exists(&container_name));
+        }
+        run_checked(
+            runtime,
+            &args(&["rm", "-f", &container_name]),
+            "Failed to remove existing container",
+        )?;
+        emit(Event::ContainerRemoved {
+            name: container_name,
+        });
+        Ok(())
+    }
+
+    /// Stop the existing container and give it another name, for [`Sandbox::stop_other`].
+    fn set_aside(&self, runtime: &dyn ContainerRuntime, container_name: &str) -> Result<()> {
+        if container_state(runtime, container_name)?.as_deref() == Some("running") {
+            run_checked(
+                runtime,
+                &args(&["stop", container_name]),
+                "Failed to stop existing container",
+            )?;
+        }
+        // Containers started with `--rm` are gone once stopped.
+        if !container_exists(runtime, container_name)? {
+            say!("{} Stopped {}", Marker::Ok, container_name);
+            return Ok(());
+        }
+        let new_name = set_aside_name(&self.dir)?;
+        run_checked(
+            runtime,
+            &args(&["rename", container_name, &new_name]),
+            "Failed to rename existing container",
+        )?;
+        say!(
+            "{} Stopped {} and renamed it {}; `docker start -ai {}` gets back into it",
+            Marker::Ok,
+            container_name,
+            new_name,
+            new_name
+        );
+        Ok(())
+    }
+
+    /// Host directory mounted as `/workspace`: the project, or the requested subdirectory.
+    pub(crate) fn workspace_source(&self) -> Result<PathBuf> {
+        let abs_dir = std::fs::canonicalize(&self.dir)?;
+        let Some(subdir) = &self.subdir else {
+            return Ok(abs_dir);
+        };
+
+        let joined = abs_dir.join(subdir);
+        let abs_subdir = std::fs::canonicalize(&joined)
+            .map_err(|e| anyhow!("Invalid --subdir {}: {}", subdir.display(), e))?;
+        if !abs_subdir.starts_with(&abs_dir) || !abs_subdir.is_dir() {
+            return Err(anyhow!(
+                "Invalid --subdir {}: must be a directory inside {}",
+                subdir.display(),
+                abs_dir.display()
+            ));
+        }
+        Ok(abs_subdir)
+    }
+
+    /// Run the container with the project directory mounted.
+    pub fn open(&self) -> Result<()> {
+        self.open_with(&Docker::new())
+    }
+
+    /// Run the container using the given container runtime.
+    pub fn open_with(&self, runtime: &dyn ContainerRuntime) -> Result<()> {
+        if self.auto_image && self.image.is_none() {
+            return self.with_detected_image(runtime)?.open_with(runtime);
+        }
+        let docker_args = self.docker_args()?;
+        self.check_symlinks()?;
+        if let Some(provenance) = &self.provenance {
+            provenance.verify(self.image_ref()?)?;
+        }
+        if let Some(path) = &self.trace_syscalls {
+            std::fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+        }
+        if self.reusing() {
+            if let Some(status) = self.reuse_existing(runtime)? {
+                emit(Event::CommandExited {
+                    name: self.container_name()?,
+                    code: status.code,
+                });
+                return if status.success() {
+                    Ok(())
+                } else {
+                    Err(anyhow!(
+                        "Failed to reuse container. Docker command exited with non-zero status."
+                    ))
+                };
+            }
+        }
+        self.check_isolation(runtime)?;
+        self.check_storage(runtime)?;
+        self.check_apparmor(runtime)?;
+        self.check_editor(runtime)?;
+        self.check_open_containers(runtime)?;
+
+        self.clear_existing(runtime)?;
+
+        if self.persist_cache {
+            self.create_cache_volume(runtime)?;
+        }
+        let container_name = self.container_name()?;
+        if self.detach {
+            let status = if self.copy_in {
+                self.run_copied(runtime, &docker_args)?
+            } else {
+                emit(Event::ContainerCreated {
+                    name: container_name.clone(),
+                });
+                // Docker prints the container ID.
+                let output = runtime.output(&docker_args)?;
+                if !output.status.success() {
+                    eprint!("{}", output.stderr);
+                }
+                output.status
+            };
+            if !status.success() {
+                return Err(anyhow!("Failed to start container {}", container_name));
+            }
+            say!(
+                "{} Started {} in the background",
+                Marker::Ok,
+                container_name
+            );
+            return self.wait_until_ready(runtime, &container_name);
+        }
+        let capture = self.start_network_capture(runtime)?;
+        let run = || self.run_foreground(runtime, &docker_args, &container_name);
+        let status = match &self.network_capture {
+            Some(pcap) if self.freeze_on_network => {
+                freeze_on_egress(runtime, &container_name, &self.dir, pcap, run)
+            }
+            _ => run(),
+        };
+        if let Some(capture) = &capture {
+            self.stop_network_capture(runtime, capture)?;
+        }
+        let Some(status) = status? else {
+            return Ok(());
+        };
+        emit(Event::CommandExited {
+            name: container_name.clone(),
+            code: status.code,
+        });
+        let removed = || {
+            emit(Event::ContainerRemoved {
+                name: container_name.clone(),
+            })
+        };
+        // Kept only in case the command failed or timed out.
+        let remove_after = !self.auto_remove() && !self.keep_container && !self.reusing();
+        if status.success() {
+            if self.auto_remove() {
+                removed();
+            } else if remove_after {
+                run_checked(
+                    runtime,
+                    &args(&["rm", &container_name]),
+                    "Failed to remove container",
+                )?;
+                removed();
+            }
+            return Ok(());
+        }
+
+        // Docker exits with 125 when it couldn't create the container, e.g. because
+        // another process created one with the same name since the check above.
+        if status.code == Some(125) && container_exists(runtime, &container_name)? {
+            return Err(self.already_exists(&container_name));
+        }
+        if self.auto_remove() {
+            removed();
+        }
+        let failed = anyhow::Error::new(CommandFailed { code: status.code });
+        if let (Some(seconds), Some(124)) = (self.cmd_timeout, status.code) {
+            return Err(failed.context(format!(
+                "Command timed out after {}s. Kept container {} for inspection.\n\
+                 \tRun `safecrate resume {dir}` to get back into it, or `safecrate remove {dir}` when done.",
+                seconds,
+                container_name,
+                dir = self.dir.display()
+            )));
+        }
+        if self.keep_on_failure {
+            return Err(failed.context(format!(
+                "Command failed{}. Kept container {} for debugging.\n\
+                 \tRun `safecrate resume {dir}` to get back into it, or `safecrate remove {dir}` when done.",
+                status
+                    .code
+                    .map(|code| format!(" with exit status {}", code))
+                    .unwrap_or_default(),
+                container_name,
+                dir = self.dir.display()
+            )));
+        }
+        if remove_after
+            && runtime
+                .output(&args(&["rm", "-f", &container_name]))
+                .is_ok_and(|output| output.status.success())
+        {
+            removed();
+        }
+        Err(failed)
+    }
+}
+
+/// How long ago input last arrived on the terminal on stdin, going by the device's access
+/// time, which the kernel updates as it's read (as `w` shows it).
+fn terminal_idle() -> Option<Duration> {
+    std::fs::metadata("/dev/stdin")
+        .and_then(|metadata| metadata.accessed())
+        .ok()?
+        .elapsed()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{MockRuntime, Output};
+    use crate::testutil::project_dir;
+
+    fn open_args(sandbox: Sandbox) -> Vec<String> {
+        let runtime = MockRuntime::new();
+        sandbox.open_with(&runtime).unwrap();
+        runtime.calls().pop().unwrap()
+    }
+
+    #[test]
+    fn default_flags() {
+        let dir = project_dir("sandbox-default");
+        let volume = format!("{}:/workspace", dir.display());
+
+        assert_eq!(
+            open_args(Sandbox::new(&dir)),
+            args(&[
+                "run",
+                "-it",
+                "--detach-keys",
+                DEFAULT_DETACH_KEYS,
+                "--rm",
+                "--name",
+                "sandbox-default_isolated",
+                "--network",
+                "none",
+                "-v",
+                &volume,
+                "-w",
+                "/workspace",
+                "safecrate_default",
+                "sh",
+                "-c",
+                "nvim ."
+            ])
+        );
+    }
+
+    #[test]
+    fn no_network_keep_container_and_custom_cmd() {
+        let dir = project_dir("sandbox-flags");
+        let volume = format!("{}:/workspace", dir.display());
+
+        let sandbox = Sandbox::new(&dir)
+            .cmd("cargo build")
+            .keep_container(true)
+            .network(false);
+
+        assert_eq!(
+            open_args(sandbox),
+            args(&[
+                "run",
+                "-it",
+                "--detach-keys",
+                DEFAULT_DETACH_KEYS,
+                "--name",
+                "sandbox-flags_isolated",
+                "--network",
+                "none",
+                "-v",
+                &volume,
+                "-w",
+                "/workspace",
+                "safecrate_default",
+                "sh",
+                "-c",
+                "cargo build"
+            ])
+        );
+    }
+
+    #[test]
+    fn memory_and_extra_mounts() {
+        let dir = project_dir("sandbox-mounts");
+        let extra = project_dir("sandbox-mounts-extra");
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .memory("2g")
+                .mount(&extra, "/data")
+                .mount_read_only(&extra, "/ro"),
+        );
+
+        assert!(args.windows(2).any(|w| w == ["--memory", "2g"]));
+        let data = format!("{}:/data", extra.display());
+        let ro = format!("{}:/ro:ro", extra.display());
+        assert!(args.windows(2).any(|w| w[0] == "-v" && w[1] == data));
+        assert!(args.windows(2).any(|w| w[0] == "-v" && w[1] == ro));
+    }
+
+    #[test]
+    fn tmpfs_mounts_are_forwarded() {
+        let dir = project_dir("sandbox-tmpfs");
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .tmpfs("/scratch")
+                .tmpfs("/cache:size=64m"),
+        );
+
+        assert!(args.windows(2).any(|w| w == ["--tmpfs", "/scratch"]));
+        assert!(args.windows(2).any(|w| w == ["--tmpfs", "/cache:size=64m"]));
+    }
+
+    #[test]
+    fn read_only_rootfs_mounts_noexec_scratch() {
+        let dir = project_dir("sandbox-read-only");
+
+        let args = open_args(Sandbox::new(&dir).read_only_rootfs(true));
+        assert!(args.contains(&"--read-only".to_string()));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--tmpfs", "/tmp:rw,noexec,nosuid"]));
+
+        let args = open_args(Sandbox::new(&dir).read_only_rootfs(true).tmpfs_exec(true));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--tmpfs", "/tmp:rw,exec,nosuid"]));
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .read_only_rootfs(true)
+                .tmpfs("/tmp:size=1g"),
+        );
+        assert_eq!(args.iter().filter(|a| *a == "--tmpfs").count(), 1);
+
+        assert!(Sandbox::new(&dir).tmpfs_exec(true).docker_args().is_err());
+    }
+
+    #[test]
+    fn relative_tmpfs_is_rejected() {
+        let dir = project_dir("sandbox-tmpfs-relative");
+        let runtime = MockRuntime::new();
+
+        assert!(Sandbox::new(&dir)
+            .tmpfs("scratch")
+            .open_with(&runtime)
+            .is_err());
+        assert!(runtime.calls().is_empty());
+    }
+
+    #[test]
+    fn sysctls_are_forwarded_with_warning() {
+        let dir = project_dir("sandbox-sysctl");
+        let sandbox = Sandbox::new(&dir).sysctl("net.ipv4.ping_group_range=0 2147483647");
+
+        assert_eq!(sandbox.warnings().len(), 1);
+        let args = open_args(sandbox);
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--sysctl", "net.ipv4.ping_group_range=0 2147483647"]));
+    }
+
+    #[test]
+    fn malformed_sysctl_is_rejected() {
+        let dir = project_dir("sandbox-sysctl-malformed");
+        assert!(Sandbox::new(&dir)
+            .sysctl("kernel.msgmax")
+            .docker_args()
+            .is_err());
+    }
+
+    #[test]
+    fn stop_timeout_is_forwarded() {
+        let dir = project_dir("sandbox-stop-timeout");
+        let args = open_args(Sandbox::new(&dir).stop_timeout(3));
+        assert!(args.windows(2).any(|w| w == ["--stop-timeout", "3"]));
+    }
+
+    #[test]
+    fn memory_limit_defaults_oom_score_adj() {
+        let dir = project_dir("sandbox-oom");
+
+        let args = open_args(Sandbox::new(&dir).memory("1g"));
+        assert!(args.windows(2).any(|w| w == ["--oom-score-adj", "500"]));
+
+        let args = open_args(Sandbox::new(&dir).memory("1g").oom_score_adj(800));
+        assert!(args.windows(2).any(|w| w == ["--oom-score-adj", "800"]));
+
+        let args = open_args(Sandbox::new(&dir));
+        assert!(!args.contains(&"--oom-score-adj".to_string()));
+    }
+
+    #[test]
+    fn shm_size_is_validated_and_forwarded() {
+        let dir = project_dir("sandbox-shm");
+
+        let args = open_args(Sandbox::new(&dir).shm_size("1g"));
+        assert!(args.windows(2).any(|w| w == ["--shm-size", "1g"]));
+        assert!(Sandbox::new(&dir).shm_size("1 gig").docker_args().is_err());
+    }
+
+    #[test]
+    fn user_and_supplementary_groups() {
+        let dir = project_dir("sandbox-user");
+
+        let args = open_args(Sandbox::new(&dir).user("1000:1000").group_add("998"));
+        assert!(args.windows(2).any(|w| w == ["--user", "1000:1000"]));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let metadata = std::fs::metadata(&dir).unwrap();
+            let owner = format!("{}:{}", metadata.uid(), metadata.gid());
+            let args = open_args(Sandbox::new(&dir).mount_target_owner(true));
+            assert!(args.windows(2).any(|w| w == ["--user", owner.as_str()]));
+        }
+        assert!(Sandbox::new(&dir)
+            .mount_target_owner(true)
+            .user("1000")
+            .docker_args()
+            .is_err());
+        assert!(args.windows(2).any(|w| w == ["--group-add", "998"]));
+        assert!(Sandbox::new(&dir)
+            .group_add("docker")
+            .docker_args()
+            .is_err());
+    }
+
+    #[test]
+    fn cpu_controls() {
+        let dir = project_dir("sandbox-cpu");
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .cpu_shares(512)
+                .cpu_period(50_000)
+                .cpu_quota(100_000),
+        );
+        assert!(args.windows(2).any(|w| w == ["--cpu-shares", "512"]));
+        assert!(args.windows(2).any(|w| w == ["--cpu-period", "50000"]));
+        assert!(args.windows(2).any(|w| w == ["--cpu-quota", "100000"]));
+
+        let args = open_args(Sandbox::new(&dir).cpus("1.5"));
+        assert!(args.windows(2).any(|w| w == ["--cpus", "1.5"]));
+
+        let args = open_args(Sandbox::new(&dir).cpuset_cpus("0-3").cpuset_mems("0"));
+        assert!(args.windows(2).any(|w| w == ["--cpuset-cpus", "0-3"]));
+        assert!(args.windows(2).any(|w| w == ["--cpuset-mems", "0"]));
+        assert!(Sandbox::new(&dir).cpuset_cpus("all").docker_args().is_err());
+
+        assert!(Sandbox::new(&dir)
+            .cpus("1")
+            .cpu_quota(50_000)
+            .docker_args()
+            .is_err());
+        assert!(Sandbox::new(&dir).cpu_period(10).docker_args().is_err());
+    }
+
+    #[test]
+    fn invalid_memory_is_rejected() {
+        let dir = project_dir("sandbox-memory-invalid");
+        assert!(Sandbox::new(&dir).memory("lots").docker_args().is_err());
+    }
+
+    #[test]
+    fn subdir_is_mounted_as_workspace() {
+        let dir = project_dir("sandbox-subdir");
+        std::fs::create_dir_all(dir.join("crates/foo")).unwrap();
+
+        let args = open_args(Sandbox::new(&dir).subdir("crates/foo"));
+
+        let volume = format!("{}/crates/foo:/workspace", dir.display());
+        assert!(args.windows(2).any(|w| w[0] == "-v" && w[1] == volume));
+        assert!(args.contains(&"sandbox-subdir_isolated".to_string()));
+    }
+
+    #[test]
+    fn writable_subdirs_are_mounted_over_read_only_workspace() {
+        let dir = project_dir("sandbox-writable-subdir");
+        std::fs::create_dir_all(dir.join("output/logs")).unwrap();
+        project_dir("sandbox-writable-subdir-sibling");
+
+        let args = open_args(Sandbox::new(&dir).writable_subdir("output/logs/"));
+        let workspace = format!("{}:/workspace:ro", dir.display());
+        let output = format!("{}/output/logs:/workspace/output/logs", dir.display());
+        let volumes: Vec<_> = args
+            .windows(2)
+            .filter(|w| w[0] == "-v")
+            .map(|w| w[1].as_str())
+            .collect();
+        assert_eq!(volumes, [workspace.as_str(), output.as_str()]);
+
+        for subdir in [".", "../sandbox-writable-subdir-sibling", "/tmp", "missing"] {
+            let sandbox = Sandbox::new(&dir).writable_subdir(subdir);
+            assert!(sandbox.docker_args().is_err(), "{}", subdir);
+        }
+    }
+
+    #[test]
+    fn non_root_users_get_a_writable_home() {
+        let dir = project_dir("sandbox-home");
+
+        let args = open_args(Sandbox::new(&dir).user("1000:1000"));
+        assert!(args.windows(2).any(|w| w == ["-e", "HOME=/home/safecrate"]));
+        assert!(args.windows(2).any(|w| w
+            == [
+                "--tmpfs",
+                "/home/safecrate:exec,mode=0700,uid=1000,gid=1000"
+            ]));
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .user("builder")
+                .container_user_home("/tmp/home"),
+        );
+        assert!(args.windows(2).any(|w| w == ["-e", "HOME=/tmp/home"]));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--tmpfs", "/tmp/home:exec,mode=1777"]));
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .user("1000")
+                .container_user_home("/workspace"),
+        );
+        assert!(args.windows(2).any(|w| w == ["-e", "HOME=/workspace"]));
+        assert!(!args.contains(&"--tmpfs".to_string()));
+
+        let args = open_args(Sandbox::new(&dir).user("0:0"));
+        assert!(!args.iter().any(|arg| arg.starts_with("HOME=")));
+
+        for home in ["home", "/", "/a:b"] {
+            let sandbox = Sandbox::new(&dir).container_user_home(home);
+            assert!(sandbox.docker_args().is_err(), "{}", home);
+        }
+    }
+
+    #[test]
+    fn subdir_cannot_escape_project() {
+        let dir = project_dir("sandbox-subdir-escape");
+        project_dir("sandbox-subdir-sibling");
+
+        for subdir in ["../sandbox-subdir-sibling", "/tmp", "missing"] {
+            assert!(
+                Sandbox::new(&dir).subdir(subdir).docker_args().is_err(),
+                "{}",
+                subdir
+            );
+        }
+    }
+
+    #[test]
+    fn log_driver_and_opts_are_forwarded() {
+        let dir = project_dir("sandbox-log-opts");
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .log_driver("json-file")
+                .log_opt("max-size=10m")
+                .log_opt("max-file=3"),
+        );
+        assert!(args.windows(2).any(|w| w == ["--log-driver", "json-file"]));
+        assert!(args.windows(2).any(|w| w == ["--log-opt", "max-size=10m"]));
+        assert!(args.windows(2).any(|w| w == ["--log-opt", "max-file=3"]));
+
+        assert!(Sandbox::new(&dir)
+            .log_opt("max-size")
+            .docker_args()
+            .is_err());
+    }
+
+    #[test]
+    fn storage_opts_are_forwarded() {
+        let dir = project_dir("sandbox-storage-opt");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("overlay2\n"));
+
+        Sandbox::new(&dir)
+            .storage_opt("size=10G")
+            .open_with(&runtime)
+            .unwrap();
+
+        let calls = runtime.calls();
+        assert_eq!(calls[0], args(&["info", "--format", "{{.Driver}}"]));
+        assert!(calls
+            .last()
+            .unwrap()
+            .windows(2)
+            .any(|w| w == ["--storage-opt", "size=10G"]));
+        assert!(Sandbox::new(&dir)
+            .storage_opt("size")
+            .docker_args()
+            .is_err());
+    }
+
+    #[test]
+    fn oci_runtime_and_annotations_are_forwarded() {
+        let dir = project_dir("sandbox-oci");
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .oci_runtime("kata")
+                .annotation("io.katacontainers.config.hypervisor.default_memory=2048"),
+        );
+
+        assert!(args.windows(2).any(|w| w == ["--runtime", "kata"]));
+        assert!(args.windows(2).any(|w| w
+            == [
+                "--annotation",
+                "io.katacontainers.config.hypervisor.default_memory=2048"
+            ]));
+        assert!(Sandbox::new(&dir).annotation("bad").docker_args().is_err());
+    }
+
+    #[test]
+    fn gvisor_checks_runtime_is_registered() {
+        let dir = project_dir("sandbox-gvisor");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("io.containerd.runc.v2 runc runsc \n"));
+
+        Sandbox::new(&dir)
+            .isolation(Isolation::Gvisor)
+            .open_with(&runtime)
+            .unwrap();
+
+        let calls = runtime.calls();
+        assert_eq!(calls[0][0], "info");
+        assert!(calls[2].windows(2).any(|w| w == ["--runtime", "runsc"]));
+    }
+
+    #[test]
+    fn gvisor_without_runsc_fails_clearly() {
+        let dir = project_dir("sandbox-gvisor-missing");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("runc \n"));
+
+        let err = Sandbox::new(&dir)
+            .isolation(Isolation::Gvisor)
+            .open_with(&runtime)
+            .unwrap_err();
+
+        assert!(err.to_string().contains("runsc install"));
+        assert_eq!(runtime.calls().len(), 1);
+    }
+
+    #[test]
+    fn gvisor_conflicts_with_other_oci_runtime() {
+        let dir = project_dir("sandbox-gvisor-conflict");
+        assert!(Sandbox::new(&dir)
+            .isolation(Isolation::Gvisor)
+            .oci_runtime("kata")
+            .docker_args()
+            .is_err());
+    }
+
+    #[test]
+    fn health_check_options_are_forwarded() {
+        let dir = project_dir("sandbox-health");
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .health_cmd("curl -f localhost:8080")
+                .health_interval("5s")
+                .health_retries(3),
+        );
+
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--health-cmd", "curl -f localhost:8080"]));
+        assert!(args.windows(2).any(|w| w == ["--health-interval", "5s"]));
+        assert!(args.windows(2).any(|w| w == ["--health-retries", "3"]));
+        assert!(Sandbox::new(&dir)
+            .health_interval("often")
+            .docker_args()
+            .is_err());
+    }
+
+    #[test]
+    fn existing_container_requires_replace() {
+        let dir = project_dir("sandbox-existing");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("sandbox-existing_isolated\n"));
+
+        let err = Sandbox::new(&dir).open_with(&runtime).unwrap_err();
+
+        let message = err.to_string();
+        for hint in [
+            "safecrate resume",
+            "safecrate remove",
+            "safecrate open --replace",
+        ] {
+            assert!(message.contains(hint), "{}", message);
+        }
+        assert_eq!(runtime.calls().len(), 1);
+    }
+
+    #[test]
+    fn tty_size_is_passed_to_run_and_exec() {
+        let dir = project_dir("sandbox-tty-size");
+        let sandbox = Sandbox::new(&dir).cmd("nvim").tty_size(132, 43);
+        let args = open_args(sandbox.clone());
+        assert!(args
+            .windows(4)
+            .any(|w| w == ["-e", "COLUMNS=132", "-e", "LINES=43"]));
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("running\n"));
+        sandbox.clone().reuse(true).open_with(&runtime).unwrap();
+        let exec = runtime.calls().pop().unwrap();
+        assert_eq!(exec[..2], ["exec", "-it"]);
+        assert!(exec.windows(2).any(|w| w == ["-e", "LINES=43"]));
+
+        let args = open_args(sandbox.interactive(false));
+        assert!(!args.iter().any(|arg| arg.starts_with("COLUMNS=")));
+    }
+
+    #[test]
+    fn reuse_picks_exec_resume_or_create() {
+        let dir = project_dir("sandbox-reuse");
+        let sandbox = Sandbox::new(&dir).cmd("bash").reuse(true);
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("running\n"));
+        sandbox.open_with(&runtime).unwrap();
+        assert_eq!(
+            runtime.calls().pop().unwrap(),
+            args(&[
+                "exec",
+                "-it",
+                "--detach-keys",
+                DEFAULT_DETACH_KEYS,
+                "-w",
+                "/workspace",
+                "sandbox-reuse_isolated",
+                "sh",
+                "-c",
+                "bash"
+            ])
+        );
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("exited\n"));
+        sandbox.open_with(&runtime).unwrap();
+        assert_eq!(
+            runtime.calls().pop().unwrap(),
+            args(&[
+                "start",
+                "-ai",
+                "--detach-keys",
+                DEFAULT_DETACH_KEYS,
+                "sandbox-reuse_isolated"
+            ])
+        );
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::failure(1, "No such container"));
+        sandbox.open_with(&runtime).unwrap();
+        let run = runtime.calls().pop().unwrap();
+        assert_eq!(run[0], "run");
+        assert!(!run.contains(&"--rm".to_string()));
+    }
+
+    #[test]
+    fn replace_removes_existing_container() {
+        let dir = project_dir("sandbox-replace");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("sandbox-replace_isolated\n"));
+
+        Sandbox::new(&dir)
+            .replace(true)
+            .open_with(&runtime)
+            .unwrap();
+
+        let calls = runtime.calls();
+        assert_eq!(calls[1], args(&["rm", "-f", "sandbox-replace_isolated"]));
+        assert_eq!(calls[2][0], "run");
+    }
+
+    #[test]
+    fn stop_other_sets_existing_container_aside() {
+        let dir = project_dir("sandbox-stop-other");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("sandbox-stop-other_isolated\n"));
+        runtime.respond(Output::success("running\n"));
+        runtime.respond(Output::success(""));
+        runtime.respond(Output::success("sandbox-stop-other_isolated\n"));
+
+        Sandbox::new(&dir)
+            .stop_other(true)
+            .open_with(&runtime)
+            .unwrap();
+
+        let calls = runtime.calls();
+        assert_eq!(calls[2], args(&["stop", "sandbox-stop-other_isolated"]));
+        assert_eq!(calls[4][..2], ["rename", "sandbox-stop-other_isolated"]);
+        assert!(calls[4][2].starts_with("sandbox-stop-other-stopped-"));
+        assert!(calls[4][2].ends_with("_isolated"));
+        assert_eq!(calls[5][0], "run");
+    }
+
+    #[test]
+    fn name_conflict_after_run_gets_guidance() {
+        let dir = project_dir("sandbox-race");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(""));
+        runtime.respond(Output::failure(125, ""));
+        runtime.respond(Output::success("sandbox-race_isolated\n"));
+
+        let err = Sandbox::new(&dir).open_with(&runtime).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+    }
+
+    #[test]
+    fn image_and_pull_policy() {
+        let dir = project_dir("sandbox-image");
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .image("ghcr.io/acme/sandbox:1.2")
+                .pull(PullPolicy::Never),
+        );
+
+        let image_at = args.iter().position(|a| a == "ghcr.io/acme/sandbox:1.2");
+        assert_eq!(image_at, Some(args.len() - 4));
+        assert!(args.windows(2).any(|w| w == ["--pull", "never"]));
+        assert!("sometimes".parse::<PullPolicy>().is_err());
+    }
+
+    #[test]
+    fn static_ip_requires_user_defined_network() {
+        let dir = project_dir("sandbox-ip");
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .network_mode("testnet")
+                .ip("172.30.0.10")
+                .mac_address("02:42:ac:1e:00:0a"),
+        );
+        assert!(args.windows(2).any(|w| w == ["--network", "testnet"]));
+        assert!(args.windows(2).any(|w| w == ["--ip", "172.30.0.10"]));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--mac-address", "02:42:ac:1e:00:0a"]));
+
+        assert!(Sandbox::new(&dir).ip("172.30.0.10").docker_args().is_err());
+        assert!(Sandbox::new(&dir)
+            .network_mode("testnet")
+            .ip("not-an-ip")
+            .docker_args()
+            .is_err());
+        assert!(Sandbox::new(&dir)
+            .network(false)
+            .network_mode("testnet")
+            .docker_args()
+            .is_err());
+    }
+
+    #[test]
+    fn copy_in_creates_copies_and_starts() {
+        let dir = project_dir("sandbox-copy-in");
+        std::fs::write(dir.join("main.rs"), "fn main() {}").unwrap();
+        let runtime = MockRuntime::new();
+
+        Sandbox::new(&dir)
+            .copy_in(true)
+            .open_with(&runtime)
+            .unwrap();
+
+        let calls = runtime.calls();
+        let create = &calls[1];
+        assert_eq!(&create[..3], ["create", "-it", "--rm"]);
+        assert!(!create.iter().any(|a| a.ends_with(":/workspace")));
+        assert_eq!(calls[2][0], "cp");
+        assert!(calls[2][1].ends_with("/."));
+        assert_eq!(calls[2][2], "sandbox-copy-in_isolated:/workspace");
+        assert_eq!(
+            calls[3],
+            args(&[
+                "start",
+                "-ai",
+                "--detach-keys",
+                DEFAULT_DETACH_KEYS,
+                "sandbox-copy-in_isolated"
+            ])
+        );
+
+        let runtime = MockRuntime::new();
+        Sandbox::new(&dir)
+            .copy_in(true)
+            .mount_target_owner(true)
+            .open_with(&runtime)
+            .unwrap();
+        assert_eq!(runtime.calls()[2][..2], ["cp", "--archive"]);
+    }
+
+    #[test]
+    fn persist_cache_mounts_a_project_volume() {
+        let dir = project_dir("sandbox-cache");
+        let volume = cache_volume_name(&dir).unwrap();
+        let runtime = MockRuntime::new();
+        Sandbox::new(&dir)
+            .persist_cache(true)
+            .cache_path("/cache")
+            .open_with(&runtime)
+            .unwrap();
+        let calls = runtime.calls();
+        assert_eq!(
+            calls[1],
+            [
+                "volume",
+                "create",
+                "--label",
+                &format!("safecrate.project={}", dir.display()),
+                &volume
+            ]
+        );
+        let target = format!("type=volume,source={},target=/cache", volume);
+        assert!(calls[2]
+            .windows(2)
+            .any(|w| w == ["--mount", target.as_str()]));
+
+        assert!(Sandbox::new(&dir)
+            .persist_cache(true)
+            .cache_path("cache")
+            .docker_args()
+            .is_err());
+
+        // From an untrusted .safecrate.toml, this would bind-mount the host's root.
+        let config = crate::Config::parse(
+            "persist_cache = true\ncache_path = \"/root/.cache,type=bind,source=/\"\n",
+        )
+        .unwrap();
+        let err = config.sandbox(&dir).docker_args().unwrap_err();
+        assert!(err.to_string().contains("Invalid --cache-path"), "{}", err);
+    }
+
+    #[test]
+    fn script_quotes_the_docker_run() {
+        let dir = project_dir("sandbox-script");
+        let sandbox = Sandbox::new(&dir)
+            .cmd("cargo test -- --nocapture")
+            .memory("2g")
+            .origin(args(&["safecrate", "open"]), PathBuf::from("/"));
+        let script = sandbox.script(&Docker::new().context("ci")).unwrap();
+        let lines: Vec<&str> = script.lines().collect();
+        assert_eq!(lines[0], "#!/bin/sh");
+        assert_eq!(lines[2], "exec docker --context ci run \\");
+        assert!(lines.contains(&"  --memory 2g \\"));
+        assert_eq!(
+            lines.last().unwrap(),
+            &"  safecrate_default sh -c 'cargo test -- --nocapture'"
+        );
+        assert!(!script.contains("safecrate.origin"));
+
+        assert!(sandbox.copy_in(true).script(&Docker::new()).is_err());
+    }
+
+    #[test]
+    fn detach_runs_in_the_background() {
+        let dir = project_dir("sandbox-detach");
+        let args = open_args(
+            Sandbox::new(&dir)
+                .detach(true)
+                .cmd("python3 -m http.server"),
+        );
+        assert_eq!(args[..3], ["run", "-d", "--rm"]);
+        assert!(!args.contains(&"--detach-keys".to_string()));
+
+        let runtime = MockRuntime::new();
+        Sandbox::new(&dir)
+            .detach(true)
+            .copy_in(true)
+            .open_with(&runtime)
+            .unwrap();
+        let calls = runtime.calls();
+        assert_eq!(calls[1][..2], ["create", "--rm"]);
+        assert_eq!(calls[3], ["start", "sandbox-detach_isolated"]);
+
+        assert!(Sandbox::new(&dir)
+            .detach(true)
+            .keep_on_failure(true)
+            .docker_args()
+            .is_err());
+    }
+
+    /// Records calls like [`MockRuntime`], but detaches from every detachable command.
+    struct DetachingRuntime(MockRuntime);
+
+    impl ContainerRuntime for DetachingRuntime {
+        fn run(&self, args: &[String]) -> Result<Status> {
+            self.0.run(args)
+        }
+
+        fn output(&self, args: &[String]) -> Result<Output> {
+            self.0.output(args)
+        }
+
+        fn run_detachable(&self, args: &[String], _: &dyn Fn() -> bool) -> Result<Option<Status>> {
+            self.0.run(args)?;
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn capture_output_writes_a_transcript() {
+        let dir = project_dir("sandbox-capture");
+        let log = dir.join("run.log");
+        let sandbox = Sandbox::new(&dir)
+            .interactive(false)
+            .cmd("cargo test")
+            .capture_output(&log);
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(""));
+        runtime.respond(Output {
+            status: Status::from_code(0),
+            stdout: "running 3 tests\n".into(),
+            stderr: "warning: unused\n".into(),
+        });
+        sandbox.open_with(&runtime).unwrap();
+        assert_eq!(runtime.calls()[1][0], "run");
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            "running 3 tests\nwarning: unused\n"
+        );
+
+        assert!(Sandbox::new(&dir)
+            .capture_output(&log)
+            .docker_args()
+            .is_err());
+        assert!(sandbox.detach(true).docker_args().is_err());
+    }
+
+    #[test]
+    fn detach_on_idle_leaves_the_container_running() {
+        let dir = project_dir("sandbox-detach-on-idle");
+        let sandbox = Sandbox::new(&dir).detach_on_idle(Duration::from_secs(1800));
+        // Without --rm, so it outlives the detach; removed once the command exits.
+        let runtime = MockRuntime::new();
+        sandbox.open_with(&runtime).unwrap();
+        let calls = runtime.calls();
+        assert_eq!(calls[1][..2], ["run", "-it"]);
+        assert!(!calls[1].contains(&"--rm".to_string()));
+        assert_eq!(calls[2], ["rm", "sandbox-detach-on-idle_isolated"]);
+
+        let runtime = DetachingRuntime(MockRuntime::new());
+        sandbox.clone().open_with(&runtime).unwrap();
+        let calls = runtime.0.calls();
+        assert_eq!(calls.last().unwrap()[0], "run");
+
+        for conflict in [
+            sandbox.clone().interactive(false),
+            sandbox.clone().copy_in(true),
+            sandbox.reuse(true),
+        ] {
+            assert!(conflict.docker_args().is_err());
+        }
+    }
+
+    #[test]
+    fn readiness_wait_polls_until_ready() {
+        let dir = project_dir("sandbox-readiness");
+        let sandbox = Sandbox::new(&dir)
+            .detach(true)
+            .readiness_wait("curl -sf localhost:8080", Duration::from_secs(30));
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(""));
+        runtime.respond(Output::success("0123abcd\n"));
+        runtime.respond(Output::failure(7, ""));
+        runtime.respond(Output::success("running\n"));
+        sandbox.open_with(&runtime).unwrap();
+        let calls = runtime.calls();
+        assert_eq!(calls.len(), 5);
+        assert_eq!(
+            calls[4],
+            [
+                "exec",
+                "sandbox-readiness_isolated",
+                "sh",
+                "-c",
+                "curl -sf localhost:8080"
+            ]
+        );
+
+        let sandbox = sandbox.readiness_wait("false", Duration::ZERO);
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(""));
+        runtime.respond(Output::success("0123abcd\n"));
+        runtime.respond(Output::failure(1, ""));
+        runtime.respond(Output::success("running\n"));
+        let err = sandbox.open_with(&runtime).unwrap_err();
+        assert!(err.to_string().contains("wasn't ready within 0s"));
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(""));
+        runtime.respond(Output::success("0123abcd\n"));
+        runtime.respond(Output::failure(1, ""));
+        runtime.respond(Output::success("exited\n"));
+        let err = sandbox.open_with(&runtime).unwrap_err();
+        assert!(err.to_string().contains("stopped before it was ready"));
+    }
+
+    #[test]
+    fn failed_copy_removes_created_container() {
+        let dir = project_dir("sandbox-copy-in-failure");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(""));
+        runtime.respond(Output::success(""));
+        runtime.respond(Output::failure(1, ""));
+
+        assert!(Sandbox::new(&dir)
+            .copy_in(true)
+            .open_with(&runtime)
+            .is_err());
+        assert_eq!(
+            runtime.calls().last().unwrap(),
+            &args(&["rm", "-f", "sandbox-copy-in-failure_isolated"])
+        );
+    }
+
+    #[test]
+    fn keep_on_failure_removes_after_success() {
+        let dir = project_dir("sandbox-keep-success");
+        let runtime = MockRuntime::new();
+
+        Sandbox::new(&dir)
+            .keep_on_failure(true)
+            .open_with(&runtime)
+            .unwrap();
+
+        let calls = runtime.calls();
+        assert!(!calls[1].contains(&"--rm".to_string()));
+        assert_eq!(calls[2], args(&["rm", "sandbox-keep-success_isolated"]));
+    }
+
+    #[test]
+    fn rm_policy_maps_to_keep_flags() {
+        let dir = project_dir("sandbox-rm-policy");
+        let args = open_args(Sandbox::new(&dir).rm_policy("always".parse().unwrap()));
+        assert!(args.contains(&"--rm".to_string()));
+        let args = open_args(Sandbox::new(&dir).rm_policy(RmPolicy::Never));
+        assert!(!args.contains(&"--rm".to_string()));
+
+        let sandbox = Sandbox::new(&dir)
+            .keep_container(true)
+            .rm_policy(RmPolicy::OnSuccess);
+        assert!(!sandbox.keep_container && sandbox.keep_on_failure);
+        assert!("sometimes".parse::<RmPolicy>().is_err());
+    }
+
+    #[test]
+    fn keep_on_failure_keeps_failed_container() {
+        let dir = project_dir("sandbox-keep-failure");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(""));
+        runtime.respond(Output::failure(101, ""));
+
+        let err = Sandbox::new(&dir)
+            .keep_on_failure(true)
+            .open_with(&runtime)
+            .unwrap_err();
+
+        assert!(err.to_string().contains("exit status 101"));
+        assert!(err.to_string().contains("safecrate resume"));
+        assert_eq!(runtime.calls().len(), 2);
+    }
+
+    #[test]
+    fn cmd_timeout_keeps_timed_out_container_only() {
+        let dir = project_dir("sandbox-cmd-timeout");
+        let name = "sandbox-cmd-timeout_isolated";
+        let sandbox = Sandbox::new(&dir).cmd("cargo test").cmd_timeout(60);
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(""));
+        runtime.respond(Output::failure(124, ""));
+        let err = sandbox.open_with(&runtime).unwrap_err();
+        assert!(err.to_string().contains("timed out after 60s"));
+        let calls = runtime.calls();
+        assert_eq!(calls.len(), 2);
+        assert!(!calls[1].contains(&"--rm".to_string()));
+        assert!(calls[1].ends_with(&args(&[
+            "timeout",
+            "--foreground",
+            "--kill-after=5",
+            "60",
+            "sh",
+            "-c",
+            "cargo test"
+        ])));
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(""));
+        runtime.respond(Output::failure(101, ""));
+        sandbox.open_with(&runtime).unwrap_err();
+        assert_eq!(runtime.calls()[2], args(&["rm", "-f", name]));
+
+        let runtime = MockRuntime::new();
+        sandbox.open_with(&runtime).unwrap();
+        assert_eq!(runtime.calls()[2], args(&["rm", name]));
+
+        assert!(Sandbox::new(&dir)
+            .entrypoint("/entry")
+            .cmd_timeout(60)
+            .docker_args()
+            .is_err());
+    }
+
+    #[test]
+    fn trace_syscalls_runs_command_under_strace() {
+        let dir = project_dir("sandbox-trace-syscalls");
+        let trace = dir.join("../sandbox-trace-syscalls.trace");
+        let sandbox = Sandbox::new(&dir).cmd("cargo test").trace_syscalls(&trace);
+        assert_eq!(sandbox.warnings().len(), 1);
+
+        let args = open_args(sandbox.clone());
+        assert!(args.windows(2).any(|w| w == ["--cap-add", "SYS_PTRACE"]));
+        let mount = format!(
+            "type=bind,source={},target={}",
+            trace.display(),
+            SYSCALL_TRACE_TARGET
+        );
+        assert!(args.windows(2).any(|w| w[0] == "--mount" && w[1] == mount));
+        let command = &args[args.len() - 7..];
+        assert_eq!(command[..2], ["sh", "-c"]);
+        assert!(command[2].ends_with("exec strace -f -o /run/safecrate/syscalls.trace -- \"$@\""));
+        assert_eq!(command[3..], ["strace", "sh", "-c", "cargo test"]);
+        assert!(trace.is_file());
+
+        assert!(sandbox.clone().reuse(true).docker_args().is_err());
+        assert!(sandbox.entrypoint("/entry").docker_args().is_err());
+    }
+
+    #[test]
+    fn network_capture_runs_tcpdump_sidecar() {
+        let dir = project_dir("sandbox-network-capture");
+        let pcap = dir.join("../sandbox-network-capture.pcap");
+        let sandbox = Sandbox::new(&dir)
+            .network(true)
+            .hostname_from_project(true)
+            .network_capture(&pcap);
+
+        let runtime = MockRuntime::new();
+        sandbox.open_with(&runtime).unwrap();
+        let calls = runtime.calls();
+        let sidecar = &calls[1];
+        assert_eq!(
+            sidecar[..5],
+            [
+                "run",
+                "-d",
+                "--rm",
+                "--name",
+                "sandbox-network-capture_isolated-capture"
+            ]
+        );
+        assert!(sidecar.windows(2).any(|w| w == ["--network", "bridge"]));
+        assert!(sidecar.windows(2).any(|w| w[0] == "--hostname"));
+        assert!(sidecar.windows(2).any(|w| w == ["--cap-drop", "ALL"]));
+        let added: Vec<_> = sidecar
+            .windows(2)
+            .filter(|w| w[0] == "--cap-add")
+            .map(|w| w[1].as_str())
+            .collect();
+        assert_eq!(added, ["NET_RAW", "DAC_OVERRIDE"]);
+        assert!(sidecar
+            .windows(2)
+            .any(|w| w == ["--security-opt", "no-new-privileges"]));
+        assert!(sidecar.contains(&"--read-only".to_string()));
+        assert!(sidecar.windows(2).any(|w| w[0] == "--memory"));
+        assert!(sidecar.windows(2).any(|w| w[0] == "--pids-limit"));
+        assert!(sidecar.ends_with(&args(&[
+            "-i",
+            "any",
+            "-U",
+            "-Z",
+            "root",
+            "-w",
+            NETWORK_CAPTURE_TARGET
+        ])));
+        let run = &calls[2];
+        assert!(run.windows(2).any(|w| w
+            == [
+                "--network",
+                "container:sandbox-network-capture_isolated-capture"
+            ]));
+        assert!(!run.contains(&"--hostname".to_string()));
+        assert_eq!(
+            calls[3],
+            args(&["stop", "sandbox-network-capture_isolated-capture"])
+        );
+        assert!(pcap.is_file());
+
+        let offline = Sandbox::new(&dir).network_capture(&pcap);
+        assert!(offline.docker_args().is_err());
+    }
+
+    #[test]
+    fn auto_image_uses_detected_image_when_built() {
+        let dir = project_dir("sandbox-auto-image");
+        std::fs::write(dir.join("package.json"), "{}").unwrap();
+
+        let runtime = MockRuntime::new();
+        Sandbox::new(&dir)
+            .auto_image(true)
+            .open_with(&runtime)
+            .unwrap();
+        let calls = runtime.calls();
+        assert_eq!(calls[0][..2], ["image", "inspect"]);
+        assert!(calls
+            .last()
+            .unwrap()
+            .contains(&"safecrate_node".to_string()));
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::failure(1, "No such image"));
+        Sandbox::new(&dir)
+            .auto_image(true)
+            .open_with(&runtime)
+            .unwrap();
+        let run = runtime.calls().pop().unwrap();
+        assert!(run.contains(&DOCKER_IMAGE_NAME.to_string()));
+
+        let run = open_args(Sandbox::new(&dir).auto_image(true).image("custom"));
+        assert!(run.contains(&"custom".to_string()));
+    }
+
+    #[test]
+    fn custom_detach_keys_only_when_interactive() {
+        let dir = project_dir("sandbox-detach-keys");
+
+        let args = open_args(Sandbox::new(&dir).detach_keys("ctrl-x,x"));
+        assert!(args.windows(2).any(|w| w == ["--detach-keys", "ctrl-x,x"]));
+
+        let args = open_args(Sandbox::new(&dir).interactive(false));
+        assert!(!args.contains(&"--detach-keys".to_string()));
+
+        let args = open_args(Sandbox::new(&dir).stdin(true).cmd("bash"));
+        assert_eq!(args[1], "-i");
+        assert!(!args.contains(&"-it".to_string()));
+        assert!(!args.contains(&"--detach-keys".to_string()));
+
+        assert!(Sandbox::new(&dir)
+            .detach_keys("ctrl-1")
+            .docker_args()
+            .is_err());
+    }
+
+    #[test]
+    fn network_enabled_accounts_for_mode() {
+        let dir = project_dir("sandbox-network-enabled");
+        assert!(!Sandbox::new(&dir).network_enabled());
+        assert!(Sandbox::new(&dir).network(true).network_enabled());
+        assert!(Sandbox::new(&dir).network_mode("testnet").network_enabled());
+        assert!(!Sandbox::new(&dir).network_mode("none").network_enabled());
+        assert!(Sandbox::new(&dir)
+            .network_mode("testnet")
+            .warnings()
+            .is_empty());
+        let host = Sandbox::new(&dir).network_mode("host").warnings();
+        assert!(
+            host[0].starts_with("--network-mode host shares"),
+            "{:?}",
+            host
+        );
+
+        let args = open_args(Sandbox::new(&dir).network(true));
+        assert!(args.windows(2).any(|w| w == ["--network", "bridge"]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escaping_symlinks_block_open_unless_allowed() {
+        let dir = project_dir("sandbox-escaping-symlink");
+        std::os::unix::fs::symlink("/etc", dir.join("etc")).unwrap();
+
+        let runtime = MockRuntime::new();
+        let err = Sandbox::new(&dir).open_with(&runtime).unwrap_err();
+        assert!(err.to_string().contains("etc -> /etc"));
+        assert!(runtime.calls().is_empty());
+
+        let args = open_args(Sandbox::new(&dir).allow_escaping_symlinks(true));
+        assert_eq!(args[0], "run");
+    }
+
+    #[test]
+    fn umask_wraps_shell_and_exec_commands() {
+        let dir = project_dir("sandbox-umask");
+
+        let args = open_args(Sandbox::new(&dir).umask("077").cmd("cargo build"));
+        assert!(args.windows(2).any(|w| w == ["-e", "SAFECRATE_UMASK=077"]));
+        assert_eq!(
+            args[args.len() - 3..],
+            ["sh", "-c", "umask 077 && cargo build"]
+        );
+
+        let args = open_args(Sandbox::new(&dir).umask("077").args(["make", "all"]));
+        assert_eq!(
+            args[args.len() - 6..],
+            ["sh", "-c", "umask 077 && exec \"$@\"", "sh", "make", "all"]
+        );
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .umask("077")
+                .entrypoint("/entry")
+                .args(["run"]),
+        );
+        assert_eq!(args.last().unwrap(), "run");
+
+        assert!(Sandbox::new(&dir).umask("999").docker_args().is_err());
+    }
+
+    #[test]
+    fn setup_runs_before_the_command() {
+        let dir = project_dir("sandbox-setup");
+        let setup = "git config --global --add safe.directory /workspace";
+
+        let args = open_args(Sandbox::new(&dir).setup(setup).cmd("cargo test || true"));
+        assert_eq!(
+            args[args.len() - 3..],
+            [
+                "sh",
+                "-c",
+                "{ git config --global --add safe.directory /workspace\n} || exit\ncargo test || true"
+            ]
+        );
+
+        let args = open_args(Sandbox::new(&dir).umask("077").setup("true").args(["make"]));
+        assert_eq!(
+            args[args.len() - 4..],
+            [
+                "-c",
+                "umask 077 && { true\n} || exit\nexec \"$@\"",
+                "sh",
+                "make"
+            ]
+        );
+
+        let sandbox = Sandbox::new(&dir).setup(setup).entrypoint("/entry");
+        assert!(sandbox.docker_args().is_err());
+        assert!(Sandbox::new(&dir).setup(" ").docker_args().is_err());
+    }
+
+    #[test]
+    fn namespace_modes_warn_when_shared() {
+        let dir = project_dir("sandbox-namespaces");
+        let sandbox = Sandbox::new(&dir).ipc("none");
+        assert!(sandbox.warnings().is_empty());
+        assert!(open_args(sandbox)
+            .windows(2)
+            .any(|w| w == ["--ipc", "none"]));
+
+        let sandbox = Sandbox::new(&dir).ipc("host").pid("container:db");
+        assert_eq!(sandbox.warnings().len(), 2);
+        let args = open_args(sandbox);
+        assert!(args.windows(2).any(|w| w == ["--pid", "container:db"]));
+
+        assert!(Sandbox::new(&dir).pid("private").docker_args().is_err());
+    }
+
+    #[test]
+    fn exposed_ports_are_validated() {
+        let dir = project_dir("sandbox-expose");
+        let args = open_args(Sandbox::new(&dir).expose("8080").expose("53/udp"));
+        assert!(args.windows(2).any(|w| w == ["--expose", "8080"]));
+        assert!(args.windows(2).any(|w| w == ["--expose", "53/udp"]));
+        assert!(!args.iter().any(|a| a == "-p" || a == "--publish"));
+
+        assert!(Sandbox::new(&dir).expose("70000").docker_args().is_err());
+    }
+
+    #[test]
+    fn hostname_from_project_follows_name() {
+        let dir = project_dir("sandbox-Hostname");
+        let args = open_args(Sandbox::new(&dir).hostname_from_project(true));
+        let name = args.iter().position(|a| a == "--name").unwrap();
+        assert_eq!(args[name + 2..name + 4], ["--hostname", "sandbox-hostname"]);
+    }
+
+    #[test]
+    fn bind_propagation_is_appended_to_workspace_mount() {
+        let dir = project_dir("sandbox-propagation");
+        let sandbox = Sandbox::new(&dir).bind_propagation("rslave".parse().unwrap());
+        assert!(sandbox.warnings().is_empty());
+        let args = open_args(sandbox);
+        assert!(args.contains(&format!("{}:/workspace:rslave", dir.display())));
+
+        let shared = Sandbox::new(&dir).bind_propagation(BindPropagation::Rshared);
+        assert_eq!(shared.warnings().len(), 1);
+        assert!("private".parse::<BindPropagation>().is_err());
+    }
+
+    #[test]
+    fn mount_specs_are_forwarded() {
+        let dir = project_dir("sandbox-mount-spec");
+        let sandbox = Sandbox::new(&dir)
+            .mount_spec("type=volume,source=cache,target=/cache")
+            .mount_spec("type=tmpfs,target=/scratch,tmpfs-size=64m");
+        assert!(!sandbox.has_extra_mounts());
+        assert!(sandbox.warnings().is_empty());
+        let args = open_args(sandbox);
+        let mounts: Vec<_> = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--mount")
+            .map(|pair| pair[1].as_str())
+            .collect();
+        assert_eq!(
+            mounts,
+            [
+                "type=volume,source=cache,target=/cache",
+                "type=tmpfs,target=/scratch,tmpfs-size=64m"
+            ]
+        );
+
+        let bind = Sandbox::new(&dir).mount_spec("type=bind,source=/srv,target=/srv,readonly");
+        assert!(bind.has_extra_mounts());
+        assert_eq!(bind.warnings().len(), 1);
+        let runtime = MockRuntime::new();
+        assert!(Sandbox::new(&dir)
+            .mount_spec("type=bind,source=/,target=/workspace")
+            .open_with(&runtime)
+            .is_err());
+    }
+
+    #[test]
+    fn git_checkouts_are_labelled() {
+        let dir = project_dir("git-labels");
+        std::fs::create_dir_all(dir.join(".git/refs/heads")).unwrap();
+        std::fs::write(dir.join(".git/HEAD"), "ref: refs/heads/review\n").unwrap();
+        std::fs::write(
+            dir.join(".git/refs/heads/review"),
+            "89abcdef0123456789abcdef0123456789abcdef\n",
+        )
+        .unwrap();
+
+        let args = open_args(Sandbox::new(&dir));
+        assert!(args.windows(2).any(|pair| pair
+            == [
+                "--label",
+                "safecrate.git.commit=89abcdef0123456789abcdef0123456789abcdef"
+            ]));
+        assert!(args
+            .windows(2)
+            .any(|pair| pair == ["--label", "safecrate.git.branch=review"]));
+
+        let args = open_args(Sandbox::new(&dir).git_labels(false));
+        assert!(!args.iter().any(|arg| arg.starts_with("safecrate.git.")));
+    }
+
+    #[test]
+    fn run_id_is_a_label() {
+        let dir = project_dir("sandbox-run-id");
+        let args = open_args(Sandbox::new(&dir).run_id("nightly-7"));
+        assert!(args
+            .windows(2)
+            .any(|pair| pair == ["--label", "safecrate.run-id=nightly-7"]));
+
+        let runtime = MockRuntime::new();
+        assert!(Sandbox::new(&dir)
+            .run_id("x,y")
+            .open_with(&runtime)
+            .is_err());
+    }
+
+    #[test]
+    fn apparmor_profile_is_checked_but_not_required() {
+        let dir = project_dir("sandbox-apparmor");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(
+            "[name=seccomp,profile=builtin name=cgroupns]\n",
+        ));
+        Sandbox::new(&dir)
+            .apparmor("safecrate")
+            .open_with(&runtime)
+            .unwrap();
+
+        let calls = runtime.calls();
+        assert_eq!(
+            calls[0],
+            args(&["info", "--format", "{{.SecurityOptions}}"])
+        );
+        assert!(calls
+            .last()
+            .unwrap()
+            .windows(2)
+            .any(|w| w == ["--security-opt", "apparmor=safecrate"]));
+
+        let args = open_args(Sandbox::new(&dir).apparmor("unconfined"));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--security-opt", "apparmor=unconfined"]));
+    }
+
+    #[test]
+    fn git_files_are_mounted_read_only() {
+        let dir = project_dir("sandbox-git");
+        let home = project_dir("sandbox-git-home");
+        for file in [".gitconfig", "known_hosts", ".git-credentials"] {
+            std::fs::write(home.join(file), "").unwrap();
+        }
+        let home = std::fs::canonicalize(&home).unwrap();
+
+        let sandbox = Sandbox::new(&dir)
+            .git_config(home.join(".gitconfig"))
+            .ssh_known_hosts(home.join("known_hosts"))
+            .git_credentials(home.join(".git-credentials"));
+        assert_eq!(sandbox.warnings().len(), 2);
+        let args = open_args(sandbox);
+        for mapping in [
+            format!("{}/.gitconfig:/etc/gitconfig:ro", home.display()),
+            format!("{}/known_hosts:/etc/ssh/ssh_known_hosts:ro", home.display()),
+            format!(
+                "{}/.git-credentials:/run/safecrate/git-credentials:ro",
+                home.display()
+            ),
+        ] {
+            assert!(args.windows(2).any(|w| w == ["-v", mapping.as_str()]));
+        }
+        assert!(args.windows(2).any(|w| w
+            == [
+                "-e",
+                "GIT_CONFIG_VALUE_0=store --file=/run/safecrate/git-credentials"
+            ]));
+    }
+
+    #[test]
+    fn gpg_agent_socket_is_forwarded() {
+        let dir = project_dir("sandbox-gpg");
+        let home = std::fs::canonicalize(project_dir("sandbox-gpg-home")).unwrap();
+        std::fs::write(home.join("S.gpg-agent.extra"), "").unwrap();
+        std::fs::write(home.join("pubring.kbx"), "").unwrap();
+
+        let sandbox = Sandbox::new(&dir).gpg_agent(
+            home.join("S.gpg-agent.extra"),
+            Some(home.join("pubring.kbx")),
+        );
+        assert_eq!(sandbox.warnings().len(), 1);
+        let args = open_args(sandbox);
+        for mapping in [
+            format!(
+                "{}/S.gpg-agent.extra:/run/safecrate/gnupg/S.gpg-agent",
+                home.display()
+            ),
+            format!(
+                "{}/pubring.kbx:/run/safecrate/gnupg/pubring.kbx:ro",
+                home.display()
+            ),
+        ] {
+            assert!(args.windows(2).any(|w| w == ["-v", mapping.as_str()]));
+        }
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["-e", "GNUPGHOME=/run/safecrate/gnupg"]));
+    }
+
+    #[test]
+    fn shell_init_is_mounted_and_sourced() {
+        let dir = project_dir("sandbox-shell-init");
+        let init = dir.join("init.sh");
+        std::fs::write(&init, "alias ll='ls -la'\n").unwrap();
+        let init = std::fs::canonicalize(init).unwrap();
+
+        let args = open_args(Sandbox::new(&dir).cmd("bash -l").shell_init(&init));
+        let mapping = format!(
+            "{}:/etc/profile.d/safecrate-shell-init.sh:ro",
+            init.display()
+        );
+        assert!(args.windows(2).any(|w| w == ["-v", mapping.as_str()]));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["-e", "ENV=/etc/profile.d/safecrate-shell-init.sh"]));
+
+        let args = open_args(Sandbox::new(&dir));
+        assert!(!args.iter().any(|arg| arg.starts_with("ENV=")));
+    }
+
+    #[test]
+    fn failed_command_reports_exit_status() {
+        let dir = project_dir("sandbox-exit-status");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(""));
+        runtime.respond(Output::failure(3, ""));
+        let err = Sandbox::new(&dir)
+            .interactive(false)
+            .cmd("false")
+            .open_with(&runtime)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<CommandFailed>(),
+            Some(&CommandFailed { code: Some(3) })
+        );
+        assert!(!runtime.calls()[1].contains(&"-it".to_string()));
+    }
+
+    #[test]
+    fn open_containers_are_capped() {
+        let dir = project_dir("sandbox-max-open");
+        let running =
+            "a_isolated\trunning\t\nb_isolated\texited\t\nsandbox-max-open_isolated\trunning\t\n";
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(running));
+        Sandbox::new(&dir)
+            .max_open_containers(2)
+            .open_with(&runtime)
+            .unwrap();
+
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(running));
+        let err = Sandbox::new(&dir)
+            .max_open_containers(1)
+            .open_with(&runtime)
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("limit of 1 running safecrate containers"));
+        assert_eq!(runtime.calls().len(), 1);
+    }
+
+    #[test]
+    fn editor_replaces_default_command() {
+        let dir = project_dir("sandbox-editor");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::failure(1, ""));
+        Sandbox::new(&dir)
+            .editor(Editor::Helix)
+            .open_with(&runtime)
+            .unwrap();
+
+        let calls = runtime.calls();
+        assert!(calls[0].ends_with(&args(&["safecrate_default", "-c", "command -v hx"])));
+        assert!(calls
+            .last()
+            .unwrap()
+            .ends_with(&args(&["sh", "-c", "hx ."])));
+
+        let args = open_args(Sandbox::new(&dir).editor(Editor::Nano).cmd("cargo test"));
+        assert_eq!(args.last().unwrap(), "cargo test");
+        assert!("emacs".parse::<Editor>().is_err());
+    }
+
+    #[test]
+    fn capabilities_are_normalized_and_validated() {
+        let dir = project_dir("sandbox-caps");
+        let args = open_args(
+            Sandbox::new(&dir)
+                .cap_drop("all")
+                .cap_add("cap_net_bind_service"),
+        );
+        assert!(args.windows(2).any(|w| w == ["--cap-drop", "ALL"]));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--cap-add", "NET_BIND_SERVICE"]));
+
+        assert!(Sandbox::new(&dir)
+            .cap_add("net_bind")
+            .docker_args()
+            .is_err());
+    }
+
+    #[test]
+    fn env_passthrough_forwards_only_set_variables() {
+        let dir = project_dir("sandbox-env-passthrough");
+        let path = std::env::var("PATH").unwrap();
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .env_passthrough("PATH")
+                .env_passthrough("SAFECRATE_TEST_SURELY_UNSET"),
+        );
+        let env: Vec<&String> = args
+            .windows(2)
+            .filter(|w| w[0] == "-e")
+            .map(|w| &w[1])
+            .collect();
+        assert_eq!(env, [&format!("PATH={}", path)]);
+
+        assert!(Sandbox::new(&dir)
+            .env_passthrough("A=B")
+            .docker_args()
+            .is_err());
+    }
+
+    #[test]
+    fn blank_cmd_is_rejected() {
+        let dir = project_dir("sandbox-blank-cmd");
+        let err = Sandbox::new(&dir).cmd("  ").docker_args().unwrap_err();
+        assert!(err.to_string().contains("--cmd is empty"));
+    }
+
+    #[test]
+    fn entrypoint_and_trailing_args_precedence() {
+        let dir = project_dir("sandbox-entrypoint");
+
+        let args = open_args(Sandbox::new(&dir).entrypoint("/usr/bin/tool"));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--entrypoint", "/usr/bin/tool"]));
+        assert_eq!(args.last().unwrap(), DOCKER_IMAGE_NAME);
+
+        let args = open_args(
+            Sandbox::new(&dir)
+                .entrypoint("/usr/bin/tool")
+                .args(["--verbose", "check"]),
+        );
+        assert_eq!(
+            args[args.len() - 3..],
+            [DOCKER_IMAGE_NAME, "--verbose", "check"]
+        );
+
+        let args = open_args(Sandbox::new(&dir).entrypoint("cargo").cmd("echo hi"));
+        assert_eq!(args[args.len() - 3..], ["sh", "-c", "echo hi"]);
+
+        assert!(Sandbox::new(&dir)
+            .cmd("cargo test")
+            .args(["make"])
+            .docker_args()
+            .is_err());
+    }
+
+    #[test]
+    fn open_reports_docker_failure() {
+        let dir = project_dir("sandbox-failure");
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success(""));
+        runtime.respond(Output::failure(125, ""));
+
+        let err = Sandbox::new(&dir).open_with(&runtime).unwrap_err();
+        assert!(err.to_string().contains("Failed to open container"));
+    }
+}
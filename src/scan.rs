@@ -0,0 +1,375 @@
+//! Lightweight pre-run heuristics that flag suspicious files in a project.
+//!
+//! This is no antivirus: it points out things worth a look before running untrusted code.
+
+use anyhow::{anyhow, Context, Error, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use crate::IGNORED_DIRS;
+
+/// Files at least this large are reported.
+pub const LARGE_FILE_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Build and install scripts that are searched for [`SUSPICIOUS_PATTERNS`].
+const SCRIPT_NAMES: [&str; 5] = [
+    "build.rs",
+    "setup.py",
+    "package.json",
+    "Makefile",
+    "install.sh",
+];
+
+/// Scripts larger than this aren't searched.
+const MAX_SCRIPT_BYTES: u64 = 1024 * 1024;
+
+/// Substrings that rarely belong in a build script, with what they suggest.
+const SUSPICIOUS_PATTERNS: [(&str, &str); 7] = [
+    ("| sh", "pipes a download into a shell"),
+    ("| bash", "pipes a download into a shell"),
+    ("/dev/tcp/", "opens a raw network connection"),
+    ("base64 -d", "decodes an embedded payload"),
+    (".ssh/", "touches SSH keys"),
+    (
+        "\"preinstall\"",
+        "runs a script when dependencies are installed",
+    ),
+    (
+        "\"postinstall\"",
+        "runs a script when dependencies are installed",
+    ),
+];
+
+/// Something in the project worth checking before running it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Path relative to the project root.
+    pub path: PathBuf,
+    pub kind: FindingKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindingKind {
+    /// A file with the setuid or setgid bit.
+    Setuid,
+    /// A file of at least [`LARGE_FILE_BYTES`], with its size.
+    LargeFile(u64),
+    /// A symlink resolving outside the project, with its target.
+    EscapingSymlink(PathBuf),
+    /// A build script containing a suspicious pattern, with what it suggests.
+    SuspiciousPattern(&'static str),
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.path.display())?;
+        match &self.kind {
+            FindingKind::Setuid => write!(f, "setuid/setgid file"),
+            FindingKind::LargeFile(bytes) => {
+                write!(f, "large file ({} MiB)", bytes / (1024 * 1024))
+            }
+            FindingKind::EscapingSymlink(target) => {
+                write!(f, "symlink escapes the project (-> {})", target.display())
+            }
+            FindingKind::SuspiciousPattern(reason) => write!(f, "build script {}", reason),
+        }
+    }
+}
+
+/// Scan `dir` for setuid files, large files, escaping symlinks and suspicious build scripts.
+///
+/// [`IGNORED_DIRS`] are skipped, and symlinks are never followed.
+pub fn scan(dir: &Path) -> Result<Vec<Finding>> {
+    let root =
+        fs::canonicalize(dir).with_context(|| format!("Failed to scan {}", dir.display()))?;
+    let mut findings = Vec::new();
+    scan_dir(&root, &root, &mut findings)?;
+    findings.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(findings)
+}
+
+fn scan_dir(root: &Path, dir: &Path, findings: &mut Vec<Finding>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+        let file_type = entry.file_type()?;
+
+        if file_type.is_symlink() {
+            if let Some(target) = escaping_target(root, &path) {
+                findings.push(Finding {
+                    path: relative,
+                    kind: FindingKind::EscapingSymlink(target),
+                });
+            }
+        } else if file_type.is_dir() {
+            if !IGNORED_DIRS
+                .iter()
+                .any(|ignored| entry.file_name() == *ignored)
+            {
+                scan_dir(root, &path, findings)?;
+            }
+        } else {
+            let metadata = entry.metadata()?;
+            if is_setuid(&metadata) {
+                findings.push(Finding {
+                    path: relative.clone(),
+                    kind: FindingKind::Setuid,
+                });
+            }
+            if metadata.len() >= LARGE_FILE_BYTES {
+                findings.push(Finding {
+                    path: relative.clone(),
+                    kind: FindingKind::LargeFile(metadata.len()),
+                });
+            }
+            if metadata.len() <= MAX_SCRIPT_BYTES
+                && SCRIPT_NAMES.iter().any(|name| entry.file_name() == *name)
+            {
+                scan_script(&path, &relative, findings);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn scan_script(path: &Path, relative: &Path, findings: &mut Vec<Finding>) {
+    let Ok(content) = fs::read_to_string(path) else {
+        return;
+    };
+    let mut reasons: Vec<&'static str> = SUSPICIOUS_PATTERNS
+        .iter()
+        .filter(|(pattern, _)| content.contains(pattern))
+        .map(|(_, reason)| *reason)
+        .collect();
+    reasons.dedup();
+    findings.extend(reasons.into_iter().map(|reason| Finding {
+        path: relative.to_path_buf(),
+        kind: FindingKind::SuspiciousPattern(reason),
+    }));
+}
+
+/// Every symlink under `root` (which must be canonical) that resolves outside it, with its target.
+///
+/// Unlike [`scan`] this walks every directory, since a link anywhere is reachable.
+pub(crate) fn escaping_symlinks(root: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut found = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                if let Some(target) = escaping_target(root, &entry.path()) {
+                    let path = entry.path();
+                    found.push((
+                        path.strip_prefix(root).unwrap_or(&path).to_path_buf(),
+                        target,
+                    ));
+                }
+            } else if file_type.is_dir() {
+                pending.push(entry.path());
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// Where the symlink at `link` points, if that is outside `root` (which must be canonical).
+pub(crate) fn escaping_target(root: &Path, link: &Path) -> Option<PathBuf> {
+    let target = fs::read_link(link).ok()?;
+    let joined = link.parent()?.join(&target);
+    // Dangling links can't be canonicalized; judge them by their lexical path instead.
+    let resolved = fs::canonicalize(&joined).unwrap_or_else(|_| normalize(&joined));
+    (!resolved.starts_with(root)).then_some(target)
+}
+
+/// A size written as a number with an optional `k`, `m` or `g` suffix (powers of 1024),
+/// e.g. `512m` or `2g`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid size '{}': expected e.g. 512m or 2g", s);
+        let (number, scale) = match s.char_indices().last().ok_or_else(invalid)? {
+            (at, 'k' | 'K') => (&s[..at], 1 << 10),
+            (at, 'm' | 'M') => (&s[..at], 1 << 20),
+            (at, 'g' | 'G') => (&s[..at], 1 << 30),
+            _ => (s, 1),
+        };
+        let number: u64 = number.parse().map_err(|_| invalid())?;
+        number.checked_mul(scale).map(ByteSize).ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (unit, shift) in [("g", 30), ("m", 20), ("k", 10)] {
+            if self.0 != 0 && self.0.is_multiple_of(1 << shift) {
+                return write!(f, "{}{}", self.0 >> shift, unit);
+            }
+        }
+        write!(f, "{}", self.0)
+    }
+}
+
+/// What a directory holds, as counted by [`dir_size`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirSize {
+    pub bytes: u64,
+    pub files: u64,
+    /// Counting stopped early, so the directory holds at least this much.
+    pub partial: bool,
+}
+
+impl fmt::Display for DirSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{} MiB in {} files",
+            if self.partial { "over " } else { "" },
+            self.bytes / (1024 * 1024),
+            self.files
+        )
+    }
+}
+
+/// Add up the sizes of the files under `dir`, including [`IGNORED_DIRS`] but without
+/// following symlinks, stopping once they exceed `limit`.
+pub fn dir_size(dir: &Path, limit: u64) -> Result<DirSize> {
+    let mut size = DirSize {
+        bytes: 0,
+        files: 0,
+        partial: false,
+    };
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries =
+            fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                size.bytes += entry.metadata()?.len();
+                size.files += 1;
+                if size.bytes > limit {
+                    size.partial = true;
+                    return Ok(size);
+                }
+            }
+        }
+    }
+    Ok(size)
+}
+
+/// Resolve `.` and `..` components without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(unix)]
+fn is_setuid(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o6000 != 0
+}
+
+#[cfg(not(unix))]
+fn is_setuid(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::project_dir;
+
+    #[test]
+    fn sizes_directories_up_to_a_limit() {
+        let dir = project_dir("scan-size");
+        fs::create_dir_all(dir.join("node_modules/left-pad")).unwrap();
+        fs::write(dir.join("index.js"), [0; 600]).unwrap();
+        fs::write(dir.join("node_modules/left-pad/index.js"), [0; 600]).unwrap();
+
+        let whole = dir_size(&dir, 2000).unwrap();
+        assert_eq!((whole.bytes, whole.files, whole.partial), (1200, 2, false));
+        assert!(dir_size(&dir, 1000).unwrap().partial);
+
+        assert_eq!("2g".parse::<ByteSize>().unwrap(), ByteSize(2 << 30));
+        assert_eq!("512".parse::<ByteSize>().unwrap().to_string(), "512");
+        assert_eq!(ByteSize(3 << 20).to_string(), "3m");
+        assert!("2t".parse::<ByteSize>().is_err());
+        assert!("g".parse::<ByteSize>().is_err());
+    }
+
+    #[test]
+    fn flags_suspicious_build_scripts() {
+        let dir = project_dir("scan-scripts");
+        fs::write(
+            dir.join("build.rs"),
+            "fn main() { std::process::Command::new(\"sh\").arg(\"-c\").arg(\"curl x | sh\"); }",
+        )
+        .unwrap();
+        fs::write(dir.join("README.md"), "curl x | sh").unwrap();
+
+        let findings = scan(&dir).unwrap();
+        assert_eq!(
+            findings,
+            vec![Finding {
+                path: PathBuf::from("build.rs"),
+                kind: FindingKind::SuspiciousPattern("pipes a download into a shell"),
+            }]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn flags_escaping_symlinks_and_setuid_files() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = project_dir("scan-unix");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        symlink("/", dir.join("evil")).unwrap();
+        symlink("../../..", dir.join("src/up")).unwrap();
+        symlink("../README.md", dir.join("src/readme")).unwrap();
+        fs::write(dir.join("README.md"), "").unwrap();
+        fs::write(dir.join("tool"), "").unwrap();
+        fs::set_permissions(dir.join("tool"), fs::Permissions::from_mode(0o4755)).unwrap();
+
+        let findings = scan(&dir).unwrap();
+        let shown: Vec<String> = findings.iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            shown,
+            vec![
+                "evil: symlink escapes the project (-> /)",
+                "src/up: symlink escapes the project (-> ../../..)",
+                "tool: setuid/setgid file",
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize_resolves_parent_components() {
+        assert_eq!(
+            normalize(Path::new("/a/b/../c/./d")),
+            PathBuf::from("/a/c/d")
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Emoji markers and ANSI color for terminal output, switched off for pipes and `NO_COLOR`.
+
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::io::IsTerminal;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Whether output is styled: `auto` styles it only when writing to a terminal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(anyhow!(
+                "Unknown color choice '{}': expected auto, always, or never",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        })
+    }
+}
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+/// Choose whether output is styled. Only the first call has an effect, so make it before
+/// printing anything; without it `auto` applies.
+pub fn set_color(choice: ColorChoice) {
+    let _ = ENABLED.set(resolve(choice));
+}
+
+/// Whether emoji and color are used.
+pub fn color_enabled() -> bool {
+    *ENABLED.get_or_init(|| resolve(ColorChoice::Auto))
+}
+
+fn resolve(choice: ColorChoice) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            // https://no-color.org: set and non-empty means no color.
+            std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::io::stdout().is_terminal()
+                && std::io::stderr().is_terminal()
+        }
+    }
+}
+
+/// Leading marker for a line of output: an emoji, or a plain-text tag when unstyled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Marker {
+    Ok,
+    Error,
+    Warning,
+    Step,
+    Watch,
+}
+
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (styled, plain) = match self {
+            Marker::Ok => ("✅", "[ok]"),
+            Marker::Error => ("❌", "[error]"),
+            // The emoji is often drawn two columns wide but counted as one.
+            Marker::Warning => ("⚠️ ", "[!]"),
+            Marker::Step => ("▶", ">"),
+            Marker::Watch => ("👀", ">"),
+        };
+        f.write_str(if color_enabled() { styled } else { plain })
+    }
+}
+
+/// `text` in green when styled.
+pub fn green(text: impl fmt::Display) -> String {
+    paint("32", text)
+}
+
+/// `text` in red when styled.
+pub fn red(text: impl fmt::Display) -> String {
+    paint("31", text)
+}
+
+fn paint(code: &str, text: impl fmt::Display) -> String {
+    if color_enabled() {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explicit_choices_ignore_the_terminal() {
+        assert!(resolve(ColorChoice::Always));
+        assert!(!resolve(ColorChoice::Never));
+        assert_eq!("never".parse::<ColorChoice>().unwrap(), ColorChoice::Never);
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+    }
+}
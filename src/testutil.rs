@@ -0,0 +1,10 @@
+use std::path::PathBuf;
+
+/// Create (or reuse) an empty project directory under the system temp dir.
+pub(crate) fn project_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("safecrate-test-{}", std::process::id()))
+        .join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::canonicalize(dir).unwrap()
+}
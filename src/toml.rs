@@ -0,0 +1,263 @@
+//! A minimal TOML subset: top-level `key = value` pairs with string, integer,
+//! boolean, and single-line array values. Enough for `.safecrate.toml`.
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Bool(_) => "boolean",
+            Value::Array(_) => "array",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// Parse a document into its top-level key/value pairs.
+pub(crate) fn parse(input: &str) -> Result<BTreeMap<String, Value>> {
+    let mut table = BTreeMap::new();
+
+    for (index, line) in input.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err(anyhow!("line {}: tables are not supported", line_no));
+        }
+
+        let (key, rest) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("line {}: expected `key = value`", line_no))?;
+        let key = key.trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(anyhow!("line {}: invalid key '{}'", line_no, key));
+        }
+
+        let mut parser = Parser {
+            chars: rest.trim().chars().collect(),
+            pos: 0,
+        };
+        let value = parser
+            .value()
+            .map_err(|e| anyhow!("line {}: {}", line_no, e))?;
+        parser.skip_whitespace();
+        if !parser.at_end_or_comment() {
+            return Err(anyhow!("line {}: unexpected trailing characters", line_no));
+        }
+
+        if table.insert(key.to_string(), value).is_some() {
+            return Err(anyhow!("line {}: duplicate key '{}'", line_no, key));
+        }
+    }
+
+    Ok(table)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn at_end_or_comment(&self) -> bool {
+        matches!(self.peek(), None | Some('#'))
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"') => self.string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some(_) => self.bare(),
+            None => Err(anyhow!("missing value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(anyhow!("unterminated string")),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some(c) => return Err(anyhow!("unsupported escape '\\{}'", c)),
+                        None => return Err(anyhow!("unterminated string")),
+                    };
+                    out.push(escaped);
+                    self.pos += 1;
+                }
+                Some(c) => {
+                    out.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String> {
+        self.pos += 1;
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c == '\'' {
+                let out = self.chars[start..self.pos].iter().collect();
+                self.pos += 1;
+                return Ok(out);
+            }
+            self.pos += 1;
+        }
+        Err(anyhow!("unterminated string"))
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return Err(anyhow!("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn bare(&mut self) -> Result<Value> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if !c.is_whitespace() && c != ',' && c != ']' && c != '#')
+        {
+            self.pos += 1;
+        }
+        let word: String = self.chars[start..self.pos].iter().collect();
+        match word.as_str() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => word
+                .replace('_', "")
+                .parse()
+                .map(Value::Integer)
+                .map_err(|_| anyhow!("invalid value '{}'", word)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_scalars_and_arrays() {
+        let table = parse(
+            r#"
+            # comment
+            cmd = "cargo \"test\""
+            keep_container = true
+            retries = 3 # trailing comment
+            tmpfs = ["/a", '/b:size=1m']
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(table["cmd"], Value::String("cargo \"test\"".to_string()));
+        assert_eq!(table["keep_container"], Value::Bool(true));
+        assert_eq!(table["retries"], Value::Integer(3));
+        assert_eq!(
+            table["tmpfs"],
+            Value::Array(vec![
+                Value::String("/a".to_string()),
+                Value::String("/b:size=1m".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn round_trips_strings() {
+        let value = Value::String("a \"quoted\"\\path\n".to_string());
+        let table = parse(&format!("key = {}", value)).unwrap();
+        assert_eq!(table["key"], value);
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        assert!(parse("[table]").is_err());
+        assert!(parse("key").is_err());
+        assert!(parse("key = \"open").is_err());
+        assert!(parse("key = nope").is_err());
+        assert!(parse("a = 1\na = 2").is_err());
+    }
+}
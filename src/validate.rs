@@ -0,0 +1,638 @@
+//! Validation of user-supplied values before they are forwarded to Docker.
+
+use anyhow::{anyhow, Result};
+
+/// Validate a `PATH[:options]` tmpfs spec, requiring an absolute path.
+pub(crate) fn tmpfs(spec: &str) -> Result<()> {
+    let path = spec.split(':').next().unwrap_or_default();
+    if !path.starts_with('/') {
+        return Err(anyhow!(
+            "Invalid --tmpfs '{}': mount path must be absolute",
+            spec
+        ));
+    }
+    Ok(())
+}
+
+/// Validate a Docker size string: a number with an optional `b`, `k`, `m`, or `g` unit.
+pub(crate) fn size(flag: &str, value: &str) -> Result<()> {
+    let digits = value.trim_end_matches(|c: char| "bkmgBKMG".contains(c));
+    let unit_len = value.len() - digits.len();
+    if digits.is_empty() || unit_len > 1 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(anyhow!(
+            "Invalid {} '{}': expected a size like 512m or 2g",
+            flag,
+            value
+        ));
+    }
+    Ok(())
+}
+
+/// Validate an OOM score adjustment, which the kernel limits to -1000..=1000.
+pub(crate) fn oom_score_adj(value: i32) -> Result<()> {
+    if !(-1000..=1000).contains(&value) {
+        return Err(anyhow!(
+            "Invalid --oom-score-adj {}: must be between -1000 and 1000",
+            value
+        ));
+    }
+    Ok(())
+}
+
+/// Validate a Go-style duration as accepted by Docker, e.g. `30s`, `1m30s`, or `500ms`.
+pub(crate) fn go_duration(flag: &str, value: &str) -> Result<()> {
+    let invalid = || {
+        anyhow!(
+            "Invalid {} '{}': expected a duration like 30s, 1m30s, or 500ms",
+            flag,
+            value
+        )
+    };
+
+    let mut rest = value;
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    while !rest.is_empty() {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return Err(invalid());
+        }
+        rest = &rest[digits..];
+        let unit = ["ns", "us", "ms", "s", "m", "h"]
+            .into_iter()
+            .find(|unit| rest.starts_with(unit))
+            .ok_or_else(invalid)?;
+        rest = &rest[unit.len()..];
+    }
+    Ok(())
+}
+
+/// Validate an IPv4 or IPv6 address.
+pub(crate) fn ip(value: &str) -> Result<()> {
+    value
+        .parse::<std::net::IpAddr>()
+        .map(|_| ())
+        .map_err(|_| anyhow!("Invalid --ip '{}': expected an IPv4 or IPv6 address", value))
+}
+
+/// Validate a MAC address written as six colon-separated hex octets.
+pub(crate) fn mac_address(value: &str) -> Result<()> {
+    let octets: Vec<&str> = value.split(':').collect();
+    let valid = octets.len() == 6
+        && octets
+            .iter()
+            .all(|o| o.len() == 2 && o.chars().all(|c| c.is_ascii_hexdigit()));
+    if !valid {
+        return Err(anyhow!(
+            "Invalid --mac-address '{}': expected six hex octets like 02:42:ac:11:00:02",
+            value
+        ));
+    }
+    Ok(())
+}
+
+/// Validate a numeric group ID.
+pub(crate) fn gid(value: &str) -> Result<()> {
+    value
+        .parse::<u32>()
+        .map(|_| ())
+        .map_err(|_| anyhow!("Invalid --group-add '{}': expected a numeric GID", value))
+}
+
+/// Validate a `USER[:GROUP]` spec, by name or numeric ID.
+pub(crate) fn user(value: &str) -> Result<()> {
+    let valid_part =
+        |part: &str| !part.is_empty() && !part.contains(|c: char| c.is_whitespace() || c == ':');
+    let valid = match value.split_once(':') {
+        Some((user, group)) => valid_part(user) && valid_part(group),
+        None => valid_part(value),
+    };
+    if !valid {
+        return Err(anyhow!(
+            "Invalid --user '{}': expected USER[:GROUP], e.g. 1000:1000",
+            value
+        ));
+    }
+    Ok(())
+}
+
+/// Reject a blank command, which would otherwise leave `sh -c` with nothing to run.
+pub(crate) fn cmd(cmd: &str) -> Result<()> {
+    if cmd.trim().is_empty() {
+        return Err(anyhow!(
+            "--cmd is empty. Pass a command such as --cmd bash, or leave it out to run `{}`",
+            crate::DEFAULT_CMD
+        ));
+    }
+    Ok(())
+}
+
+/// Validate a Docker `--detach-keys` sequence: comma-separated single characters or
+/// `ctrl-X`, where X is a letter or one of `@ [ \ ] ^ _`.
+pub(crate) fn detach_keys(keys: &str) -> Result<()> {
+    let valid = keys.split(',').all(|key| match key.strip_prefix("ctrl-") {
+        Some(rest) => {
+            let mut chars = rest.chars();
+            matches!(
+                (chars.next(), chars.next()),
+                (Some('a'..='z' | '@' | '[' | '\\' | ']' | '^' | '_'), None)
+            )
+        }
+        None => key.chars().count() == 1,
+    });
+    if !valid {
+        return Err(anyhow!(
+            "Invalid --detach-keys '{}': expected e.g. ctrl-_,ctrl-q",
+            keys
+        ));
+    }
+    Ok(())
+}
+
+/// Validate an octal umask of three or four digits, e.g. `077` or `0027`.
+pub(crate) fn umask(value: &str) -> Result<()> {
+    if !(3..=4).contains(&value.len()) || !value.chars().all(|c| ('0'..='7').contains(&c)) {
+        return Err(anyhow!(
+            "Invalid --umask '{}': expected octal digits like 077",
+            value
+        ));
+    }
+    Ok(())
+}
+
+/// Validate a positive, possibly fractional, number of CPUs.
+pub(crate) fn cpus(value: &str) -> Result<()> {
+    match value.parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(()),
+        _ => Err(anyhow!(
+            "Invalid --cpus '{}': expected a positive number like 1.5",
+            value
+        )),
+    }
+}
+
+/// Validate a cpuset list such as `0-3,8`: numbers or ascending ranges, comma-separated.
+pub(crate) fn cpuset(flag: &str, value: &str) -> Result<()> {
+    let number = |s: &str| {
+        (!s.is_empty() && s.chars().all(|c| c.is_ascii_digit()))
+            .then(|| s.parse::<u32>().ok())
+            .flatten()
+    };
+    let valid = value.split(',').all(|item| match item.split_once('-') {
+        Some((start, end)) => matches!((number(start), number(end)), (Some(s), Some(e)) if s <= e),
+        None => number(item).is_some(),
+    });
+    if !valid {
+        return Err(anyhow!(
+            "Invalid {} '{}': expected numbers or ranges like 0-3,8",
+            flag,
+            value
+        ));
+    }
+    Ok(())
+}
+
+/// Validate that a numeric cgroup setting lies within the kernel's accepted range.
+pub(crate) fn in_range(flag: &str, value: u32, min: u32, max: u32) -> Result<()> {
+    if !(min..=max).contains(&value) {
+        return Err(anyhow!(
+            "Invalid {} {}: must be between {} and {}",
+            flag,
+            value,
+            min,
+            max
+        ));
+    }
+    Ok(())
+}
+
+/// Validate a `KEY=VALUE` pair with a non-empty key.
+pub(crate) fn key_value(flag: &str, spec: &str) -> Result<()> {
+    match spec.split_once('=') {
+        Some((key, _)) if !key.trim().is_empty() => Ok(()),
+        _ => Err(anyhow!("Invalid {} '{}': expected KEY=VALUE", flag, spec)),
+    }
+}
+
+/// Linux capabilities as Docker names them, without the `CAP_` prefix.
+const CAPABILITIES: [&str; 41] = [
+    "AUDIT_CONTROL",
+    "AUDIT_READ",
+    "AUDIT_WRITE",
+    "BLOCK_SUSPEND",
+    "BPF",
+    "CHECKPOINT_RESTORE",
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "DAC_READ_SEARCH",
+    "FOWNER",
+    "FSETID",
+    "IPC_LOCK",
+    "IPC_OWNER",
+    "KILL",
+    "LEASE",
+    "LINUX_IMMUTABLE",
+    "MAC_ADMIN",
+    "MAC_OVERRIDE",
+    "MKNOD",
+    "NET_ADMIN",
+    "NET_BIND_SERVICE",
+    "NET_BROADCAST",
+    "NET_RAW",
+    "PERFMON",
+    "SETFCAP",
+    "SETGID",
+    "SETPCAP",
+    "SETUID",
+    "SYSLOG",
+    "SYS_ADMIN",
+    "SYS_BOOT",
+    "SYS_CHROOT",
+    "SYS_MODULE",
+    "SYS_NICE",
+    "SYS_PACCT",
+    "SYS_PTRACE",
+    "SYS_RAWIO",
+    "SYS_RESOURCE",
+    "SYS_TIME",
+    "SYS_TTY_CONFIG",
+    "WAKE_ALARM",
+];
+
+/// Normalize a capability name, in any case and with or without `CAP_`, to Docker's form
+/// (e.g. `cap_net_raw` to `NET_RAW`). `ALL` is accepted too.
+pub(crate) fn capability(flag: &str, name: &str) -> Result<String> {
+    let upper = name.to_ascii_uppercase();
+    let bare = upper.strip_prefix("CAP_").unwrap_or(&upper);
+    if bare == "ALL" || CAPABILITIES.contains(&bare) {
+        return Ok(bare.to_string());
+    }
+    Err(anyhow!(
+        "Unknown capability '{}' for {}. Expected ALL or one of: {}",
+        name,
+        flag,
+        CAPABILITIES.join(", ")
+    ))
+}
+
+/// Validate an AppArmor profile name, which Docker passes on as one `--security-opt`.
+pub(crate) fn apparmor_profile(profile: &str) -> Result<()> {
+    if profile.is_empty() || profile.contains(|c: char| c.is_whitespace() || c == ',') {
+        return Err(anyhow!(
+            "Invalid --apparmor '{}': expected a profile name such as safecrate",
+            profile
+        ));
+    }
+    Ok(())
+}
+
+/// Validate a `PORT[-END][/PROTOCOL]` spec for `--expose`, with ports in 1..=65535.
+pub(crate) fn expose(spec: &str) -> Result<()> {
+    let (ports, protocol) = spec.split_once('/').unwrap_or((spec, "tcp"));
+    let port = |s: &str| s.parse::<u16>().ok().filter(|port| *port != 0);
+    let range = match ports.split_once('-') {
+        Some((start, end)) => port(start)
+            .zip(port(end))
+            .filter(|(start, end)| start <= end),
+        None => port(ports).map(|port| (port, port)),
+    };
+    if range.is_none() || !["tcp", "udp", "sctp"].contains(&protocol) {
+        return Err(anyhow!(
+            "Invalid --expose '{}': expected a port from 1 to 65535, a range like 8000-8010, \
+             and optionally /tcp, /udp or /sctp",
+            spec
+        ));
+    }
+    Ok(())
+}
+
+/// Validate an `--ipc` or `--pid` namespace mode: one of `modes`, or `container:NAME`.
+pub(crate) fn namespace_mode(flag: &str, mode: &str, modes: &[&str]) -> Result<()> {
+    let valid = modes.contains(&mode)
+        || mode
+            .strip_prefix("container:")
+            .is_some_and(|name| !name.is_empty());
+    if !valid {
+        return Err(anyhow!(
+            "Invalid {} '{}': expected {} or container:NAME",
+            flag,
+            mode,
+            modes.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Signals `docker kill` accepts by name, without the `SIG` prefix.
+const SIGNALS: [&str; 31] = [
+    "ABRT", "ALRM", "BUS", "CHLD", "CONT", "FPE", "HUP", "ILL", "INT", "IO", "KILL", "PIPE",
+    "PROF", "PWR", "QUIT", "SEGV", "STKFLT", "STOP", "SYS", "TERM", "TRAP", "TSTP", "TTIN", "TTOU",
+    "URG", "USR1", "USR2", "VTALRM", "WINCH", "XCPU", "XFSZ",
+];
+
+/// Normalize a signal name, in any case and with or without `SIG`, to e.g. `SIGTERM`.
+/// Numbers from 1 to 64 are passed through.
+pub(crate) fn signal(name: &str) -> Result<String> {
+    if name.parse::<u8>().is_ok_and(|n| (1..=64).contains(&n)) {
+        return Ok(name.to_string());
+    }
+    let upper = name.to_ascii_uppercase();
+    let bare = upper.strip_prefix("SIG").unwrap_or(&upper);
+    if SIGNALS.contains(&bare) {
+        return Ok(format!("SIG{}", bare));
+    }
+    Err(anyhow!(
+        "Unknown signal '{}'. Expected a number from 1 to 64 or one of: SIG{}",
+        name,
+        SIGNALS.join(", SIG")
+    ))
+}
+
+/// Validate an environment variable name: letters, digits and `_`, not starting with a digit.
+pub(crate) fn env_name(name: &str) -> Result<()> {
+    let valid = !name.starts_with(|c: char| c.is_ascii_digit())
+        && !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(anyhow!(
+            "Invalid --env-passthrough '{}': expected a variable name like HTTP_PROXY",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Validate a long-syntax `--mount` spec such as `type=bind,source=/a,target=/b,readonly`.
+///
+/// Only the shape is checked: a known type, an absolute target other than `/workspace`, and
+/// a source where the type needs one. Other options are left to Docker.
+pub(crate) fn mount_spec(spec: &str) -> Result<()> {
+    let invalid = |reason: &str| anyhow!("Invalid --mount-spec '{}': {}", spec, reason);
+    let (mut kind, mut source, mut target) = (None, None, None);
+    for field in spec.split(',') {
+        let (key, value) = field.split_once('=').unwrap_or((field, ""));
+        match key {
+            "type" => kind = Some(value),
+            "source" | "src" => source = Some(value),
+            "target" | "destination" | "dst" => target = Some(value),
+            "" => return Err(invalid("empty option")),
+            _ => {}
+        }
+    }
+    let kind = kind.ok_or_else(|| invalid("type= is required"))?;
+    if !["bind", "volume", "tmpfs"].contains(&kind) {
+        return Err(invalid("type must be bind, volume or tmpfs"));
+    }
+    match target {
+        Some(target) if target.starts_with('/') => {
+            if target.trim_end_matches('/') == "/workspace" || target == "/" {
+                return Err(invalid("it can't replace the project mount"));
+            }
+        }
+        _ => return Err(invalid("target= must be an absolute path")),
+    }
+    match (kind, source) {
+        ("bind", Some(source)) if source.starts_with('/') => Ok(()),
+        ("bind", _) => Err(invalid("a bind mount needs an absolute source=")),
+        ("tmpfs", Some(_)) => Err(invalid("a tmpfs mount has no source")),
+        _ => Ok(()),
+    }
+}
+
+/// Validate an image reference, which Docker and cosign take as a positional argument and
+/// would read as a flag if it started with `-`, e.g. `--privileged` from the config.
+pub(crate) fn image(image: &str) -> Result<()> {
+    if image.is_empty() || image.starts_with('-') || image.contains(char::is_whitespace) {
+        return Err(anyhow!(
+            "Invalid image '{}': expected a reference like safecrate_default or ghcr.io/org/img:tag",
+            image
+        ));
+    }
+    Ok(())
+}
+
+/// Validate a workflow run id: up to 64 letters, digits, `-`, `_` and `.`.
+pub(crate) fn run_id(id: &str) -> Result<()> {
+    let valid = !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+    if !valid {
+        return Err(anyhow!(
+            "Invalid --run-id '{}': expected up to 64 letters, digits, '-', '_' or '.'",
+            id
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_rejects_flags() {
+        for ok in [
+            "safecrate_default",
+            "ghcr.io/org/img@sha256:abc",
+            "localhost:5000/a-b",
+        ] {
+            assert!(image(ok).is_ok(), "{}", ok);
+        }
+        for bad in ["--privileged", "-v/:/host", "", "img --privileged"] {
+            assert!(image(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn tmpfs_requires_absolute_path() {
+        assert!(tmpfs("/scratch").is_ok());
+        assert!(tmpfs("/scratch:size=64m,mode=1777").is_ok());
+        assert!(tmpfs("scratch").is_err());
+        assert!(tmpfs(":size=64m").is_err());
+    }
+
+    #[test]
+    fn size_accepts_docker_units() {
+        for ok in ["512m", "2g", "2G", "1024", "10k", "100b"] {
+            assert!(size("--memory", ok).is_ok(), "{}", ok);
+        }
+        for bad in ["", "m", "2gb", "1.5g", "-1g", "big"] {
+            assert!(size("--memory", bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn oom_score_adj_range() {
+        assert!(oom_score_adj(-1000).is_ok());
+        assert!(oom_score_adj(1000).is_ok());
+        assert!(oom_score_adj(1001).is_err());
+    }
+
+    #[test]
+    fn go_duration_units() {
+        for ok in ["30s", "1m30s", "500ms", "2h"] {
+            assert!(go_duration("--health-interval", ok).is_ok(), "{}", ok);
+        }
+        for bad in ["", "30", "s", "1.5s", "10d", "1m30"] {
+            assert!(go_duration("--health-interval", bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn ip_and_mac_formats() {
+        assert!(ip("172.20.0.5").is_ok());
+        assert!(ip("fd00::5").is_ok());
+        assert!(ip("172.20.0").is_err());
+        assert!(mac_address("02:42:ac:11:00:02").is_ok());
+        assert!(mac_address("02:42:AC:11:00:02").is_ok());
+        assert!(mac_address("02:42:ac:11:00").is_err());
+        assert!(mac_address("02-42-ac-11-00-02").is_err());
+        assert!(mac_address("02:42:ac:11:00:zz").is_err());
+    }
+
+    #[test]
+    fn gid_and_user_formats() {
+        assert!(gid("998").is_ok());
+        assert!(gid("docker").is_err());
+        assert!(gid("-1").is_err());
+        assert!(user("1000").is_ok());
+        assert!(user("1000:1000").is_ok());
+        assert!(user("dev:staff").is_ok());
+        assert!(user("").is_err());
+        assert!(user("1000:").is_err());
+        assert!(user("a:b:c").is_err());
+    }
+
+    #[test]
+    fn detach_key_sequences() {
+        assert!(detach_keys("ctrl-_,ctrl-q").is_ok());
+        assert!(detach_keys("ctrl-x,x").is_ok());
+        assert!(detach_keys("ctrl-").is_err());
+        assert!(detach_keys("ctrl-1").is_err());
+        assert!(detach_keys("ab").is_err());
+        assert!(detach_keys("").is_err());
+    }
+
+    #[test]
+    fn umask_is_octal() {
+        assert!(umask("077").is_ok());
+        assert!(umask("0027").is_ok());
+        assert!(umask("77").is_err());
+        assert!(umask("089").is_err());
+    }
+
+    #[test]
+    fn cpu_values() {
+        assert!(cpus("1.5").is_ok());
+        assert!(cpus("0").is_err());
+        assert!(cpus("two").is_err());
+        assert!(in_range("--cpu-shares", 2, 2, 262_144).is_ok());
+        assert!(in_range("--cpu-shares", 1, 2, 262_144).is_err());
+
+        assert!(cpuset("--cpuset-cpus", "0-3,8").is_ok());
+        assert!(cpuset("--cpuset-mems", "0").is_ok());
+        for value in ["", "3-1", "0,", "a", "-1", "0-"] {
+            assert!(cpuset("--cpuset-cpus", value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn key_value_requires_key_and_equals() {
+        assert!(key_value("--sysctl", "net.ipv4.ping_group_range=0 2147483647").is_ok());
+        assert!(key_value("--sysctl", "kernel.msgmax=").is_ok());
+        assert!(key_value("--sysctl", "kernel.msgmax").is_err());
+        assert!(key_value("--sysctl", "=1").is_err());
+    }
+
+    #[test]
+    fn env_names() {
+        assert!(env_name("HTTP_PROXY").is_ok());
+        assert!(env_name("_x1").is_ok());
+        assert!(env_name("").is_err());
+        assert!(env_name("1X").is_err());
+        assert!(env_name("A=B").is_err());
+    }
+
+    #[test]
+    fn capabilities_are_normalized() {
+        for name in [
+            "net_bind_service",
+            "NET_BIND_SERVICE",
+            "CAP_NET_BIND_SERVICE",
+            "Cap_Net_Bind_Service",
+        ] {
+            assert_eq!(capability("--cap-add", name).unwrap(), "NET_BIND_SERVICE");
+        }
+        assert_eq!(capability("--cap-drop", "all").unwrap(), "ALL");
+        let err = capability("--cap-add", "NET_BIND").unwrap_err().to_string();
+        assert!(err.contains("Unknown capability 'NET_BIND' for --cap-add"));
+        assert!(err.contains("SYS_PTRACE"));
+    }
+
+    #[test]
+    fn expose_ports() {
+        for ok in ["8080", "53/udp", "8000-8010/tcp", "65535"] {
+            assert!(expose(ok).is_ok(), "{}", ok);
+        }
+        for bad in ["0", "65536", "http", "8010-8000", "80/icmp", ""] {
+            assert!(expose(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn namespace_modes() {
+        let modes = ["none", "private", "shareable", "host"];
+        assert!(namespace_mode("--ipc", "none", &modes).is_ok());
+        assert!(namespace_mode("--ipc", "container:db", &modes).is_ok());
+        assert!(namespace_mode("--ipc", "container:", &modes).is_err());
+        assert!(namespace_mode("--pid", "private", &["host"]).is_err());
+    }
+
+    #[test]
+    fn signals_are_normalized() {
+        for name in ["term", "TERM", "SIGTERM", "SigTerm"] {
+            assert_eq!(signal(name).unwrap(), "SIGTERM");
+        }
+        assert_eq!(signal("9").unwrap(), "9");
+        assert!(signal("0").is_err());
+        assert!(signal("65").is_err());
+        assert!(signal("SIGFOO")
+            .unwrap_err()
+            .to_string()
+            .contains("SIGUSR1"));
+    }
+
+    #[test]
+    fn run_ids() {
+        assert!(run_id("build-42.a_b").is_ok());
+        assert!(run_id("").is_err());
+        assert!(run_id("a=b").is_err());
+        assert!(run_id(&"x".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn mount_specs() {
+        for spec in [
+            "type=bind,source=/data,target=/data,readonly,bind-propagation=rslave",
+            "type=volume,src=cache,dst=/cache,volume-nocopy",
+            "type=volume,target=/scratch",
+            "type=tmpfs,target=/run/scratch,tmpfs-size=64m",
+        ] {
+            assert!(mount_spec(spec).is_ok(), "{}", spec);
+        }
+        for spec in [
+            "source=/data,target=/data",
+            "type=npipe,target=/data",
+            "type=bind,source=data,target=/data",
+            "type=bind,source=/data,target=relative",
+            "type=bind,source=/evil,target=/workspace/",
+            "type=tmpfs,source=/x,target=/tmp/x",
+            "type=volume,,target=/x",
+        ] {
+            assert!(mount_spec(spec).is_err(), "{}", spec);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Version snapshot for bug reports: safecrate, the container engine and the base image.
+
+use std::fmt;
+
+use crate::runtime::{args, ContainerRuntime};
+use crate::validate;
+use crate::DOCKER_IMAGE_NAME;
+
+/// Versions of safecrate and of what it runs on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionInfo {
+    pub safecrate: String,
+    pub client: Option<String>,
+    pub server: Option<String>,
+    /// Engine name reported by the server, e.g. `Docker Engine - Community` or `Podman Engine`.
+    pub engine: Option<String>,
+    /// Image ID of [`DOCKER_IMAGE_NAME`], if it has been built.
+    pub image_id: Option<String>,
+}
+
+/// Collect versions, leaving out whatever the runtime can't report (even if it isn't installed).
+pub fn version(runtime: &dyn ContainerRuntime) -> VersionInfo {
+    let query = |query_args: &[&str]| -> Option<String> {
+        let output = runtime.output(&args(query_args)).ok()?;
+        let value = output.stdout.trim();
+        (output.status.success() && !value.is_empty()).then(|| value.to_string())
+    };
+
+    VersionInfo {
+        safecrate: env!("CARGO_PKG_VERSION").to_string(),
+        client: query(&["version", "--format", "{{.Client.Version}}"]),
+        server: query(&["version", "--format", "{{.Server.Version}}"]),
+        engine: query(&["version", "--format", "{{.Server.Platform.Name}}"]),
+        image_id: validate::image(DOCKER_IMAGE_NAME)
+            .ok()
+            .and_then(|()| query(&["image", "inspect", "--format", "{{.Id}}", DOCKER_IMAGE_NAME])),
+    }
+}
+
+impl fmt::Display for VersionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unknown = |value: &Option<String>| value.clone().unwrap_or_else(|| "unknown".into());
+        writeln!(f, "safecrate {}", self.safecrate)?;
+        writeln!(f, "client:    {}", unknown(&self.client))?;
+        write!(f, "server:    {}", unknown(&self.server))?;
+        if let Some(engine) = &self.engine {
+            write!(f, " ({})", engine)?;
+        }
+        writeln!(f)?;
+        write!(
+            f,
+            "image:     {} {}",
+            DOCKER_IMAGE_NAME,
+            self.image_id.as_deref().unwrap_or("(not built)")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{MockRuntime, Output};
+
+    #[test]
+    fn collects_versions_and_tolerates_failures() {
+        let runtime = MockRuntime::new();
+        runtime.respond(Output::success("27.1.1\n"));
+        runtime.respond(Output::failure(1, "Cannot connect to the Docker daemon"));
+        runtime.respond(Output::failure(1, "Cannot connect to the Docker daemon"));
+        runtime.respond(Output::success("sha256:abc\n"));
+
+        let info = version(&runtime);
+        assert_eq!(info.client.as_deref(), Some("27.1.1"));
+        assert_eq!(info.server, None);
+        assert_eq!(info.image_id.as_deref(), Some("sha256:abc"));
+
+        let shown = info.to_string();
+        assert!(shown.starts_with(&format!("safecrate {}", env!("CARGO_PKG_VERSION"))));
+        assert!(shown.contains("server:    unknown"));
+        assert!(shown.contains("safecrate_default sha256:abc"));
+    }
+}
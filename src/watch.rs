@@ -0,0 +1,245 @@
+//! Re-run a command in a persistent sandbox whenever the project changes.
+//!
+//! The host directory is polled for changes; each run is a `docker exec` into a
+//! long-lived container, so there is no container startup cost per run.
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::thread::{self, ScopedJoinHandle};
+use std::time::{Duration, SystemTime};
+
+use crate::runtime::{args, run_checked, ContainerRuntime};
+use crate::style::Marker;
+use crate::validate;
+use crate::Sandbox;
+
+/// Directories skipped when looking for changes, so build output written by the
+/// command itself does not trigger another run.
+pub const IGNORED_DIRS: &[&str] = &[".git", "target", "node_modules"];
+
+/// Timing for [`watch`].
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// How often the directory is scanned for changes.
+    pub poll_interval: Duration,
+    /// How long the directory must stay unchanged before the command is re-run.
+    pub debounce: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(500),
+            debounce: Duration::from_millis(300),
+        }
+    }
+}
+
+/// Modification time and size of every watched file.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Snapshot(BTreeMap<PathBuf, (Option<SystemTime>, u64)>);
+
+impl Snapshot {
+    fn take(root: &Path) -> Self {
+        let mut snapshot = Snapshot::default();
+        snapshot.scan(root);
+        snapshot
+    }
+
+    fn scan(&mut self, dir: &Path) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                if !IGNORED_DIRS
+                    .iter()
+                    .any(|ignored| entry.file_name() == *ignored)
+                {
+                    self.scan(&path);
+                }
+            } else if let Ok(metadata) = entry.metadata() {
+                self.0
+                    .insert(path, (metadata.modified().ok(), metadata.len()));
+            }
+        }
+    }
+}
+
+/// Run the sandbox's command now and again after every change to the project.
+///
+/// Runs until interrupted. The container is started with `--init` and attached in the
+/// background, so Ctrl-C stops and removes it along with safecrate.
+pub fn watch(
+    runtime: &dyn ContainerRuntime,
+    sandbox: &Sandbox,
+    options: &WatchOptions,
+) -> Result<()> {
+    if sandbox.is_copy_in() {
+        return Err(anyhow!(
+            "`safecrate watch` needs the project mounted; it can't be combined with --no-mount"
+        ));
+    }
+    if sandbox.has_exec_form() {
+        return Err(anyhow!(
+            "`safecrate watch` re-runs --cmd in a shell; it can't be combined with --entrypoint or trailing arguments"
+        ));
+    }
+    if sandbox.has_cmd_timeout() {
+        return Err(anyhow!("`safecrate watch` doesn't support --cmd-timeout"));
+    }
+    validate::cmd(sandbox.command())?;
+    sandbox.check_symlinks()?;
+    let sandbox = &sandbox.with_detected_image(runtime)?;
+    let container_name = sandbox.container_name()?;
+    let root = sandbox.workspace_source()?;
+    let run_args = keepalive(sandbox).docker_args()?;
+    let exec_args = exec_args(&container_name, &sandbox.shell_script(sandbox.command()));
+    sandbox.clear_existing(runtime)?;
+
+    thread::scope(|scope| {
+        let container =
+            scope.spawn(|| run_checked(runtime, &run_args, "Failed to start watch container"));
+
+        let result = wait_until_running(runtime, &container_name, &container).and_then(|()| {
+            say!(
+                "{} Watching {} for changes...",
+                Marker::Watch,
+                root.display()
+            );
+            let mut last = Snapshot::take(&root);
+            loop {
+                say!("\n{} {}", Marker::Step, sandbox.command());
+                match runtime.run(&exec_args)?.code {
+                    Some(0) => say!("{} Command succeeded", Marker::Ok),
+                    Some(code) => say!("{} Command exited with status {}", Marker::Error, code),
+                    None => say!("{} Command was terminated by a signal", Marker::Error),
+                }
+                last = wait_for_change(&root, last, options, &container)?;
+            }
+        });
+
+        let _ = runtime.output(&args(&["rm", "-f", &container_name]));
+        let _ = container.join();
+        result
+    })
+}
+
+/// The sandbox as a long-lived, non-interactive container that exec runs happen in.
+fn keepalive(sandbox: &Sandbox) -> Sandbox {
+    sandbox
+        .clone()
+        .cmd("sleep infinity")
+        .interactive(false)
+        .keep_container(false)
+        .init(true)
+}
+
+fn exec_args(container_name: &str, cmd: &str) -> Vec<String> {
+    let mut exec = vec!["exec"];
+    if std::io::stdout().is_terminal() {
+        exec.push("-t");
+    }
+    exec.extend(["-w", "/workspace", container_name, "sh", "-c", cmd]);
+    args(&exec)
+}
+
+fn wait_until_running(
+    runtime: &dyn ContainerRuntime,
+    container_name: &str,
+    container: &ScopedJoinHandle<'_, Result<()>>,
+) -> Result<()> {
+    let inspect = args(&["inspect", "--format", "{{.State.Running}}", container_name]);
+    for _ in 0..100 {
+        if container.is_finished() {
+            return Err(anyhow!("Watch container exited before it was ready"));
+        }
+        let output = runtime.output(&inspect)?;
+        if output.status.success() && output.stdout.trim() == "true" {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    Err(anyhow!("Timed out waiting for watch container to start"))
+}
+
+/// Block until the directory changes and then stays unchanged for the debounce period.
+fn wait_for_change(
+    root: &Path,
+    last: Snapshot,
+    options: &WatchOptions,
+    container: &ScopedJoinHandle<'_, Result<()>>,
+) -> Result<Snapshot> {
+    let mut current = loop {
+        if container.is_finished() {
+            return Err(anyhow!("Watch container exited"));
+        }
+        thread::sleep(options.poll_interval);
+        let current = Snapshot::take(root);
+        if current != last {
+            break current;
+        }
+    };
+
+    loop {
+        thread::sleep(options.debounce);
+        let settled = Snapshot::take(root);
+        if settled == current {
+            return Ok(settled);
+        }
+        current = settled;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::project_dir;
+
+    #[test]
+    fn snapshot_detects_changes_outside_ignored_dirs() {
+        let dir = project_dir("watch-snapshot");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("target/debug")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "a").unwrap();
+
+        let before = Snapshot::take(&dir);
+        fs::write(dir.join("target/debug/out"), "build output").unwrap();
+        assert_eq!(Snapshot::take(&dir), before);
+
+        fs::write(dir.join("src/lib.rs"), "changed").unwrap();
+        assert_ne!(Snapshot::take(&dir), before);
+    }
+
+    #[test]
+    fn keepalive_container_is_detached_from_terminal() {
+        let dir = project_dir("watch-keepalive");
+        let sandbox = Sandbox::new(&dir).cmd("cargo test").network(false);
+
+        let run = keepalive(&sandbox).docker_args().unwrap();
+
+        assert_eq!(&run[..3], ["run", "--init", "--rm"]);
+        assert!(run.ends_with(&args(&["sh", "-c", "sleep infinity"])));
+        assert!(run.windows(2).any(|w| w == ["--network", "none"]));
+    }
+
+    #[test]
+    fn exec_runs_command_in_workspace() {
+        let exec = exec_args("proj_isolated", "cargo test");
+        assert!(exec.ends_with(&args(&[
+            "-w",
+            "/workspace",
+            "proj_isolated",
+            "sh",
+            "-c",
+            "cargo test"
+        ])));
+    }
+}
@@ -0,0 +1,511 @@
+//! End-to-end tests of the `safecrate` binary against a fake `docker` on `PATH`.
+//!
+//! The fake records each invocation, so the tests assert on the exact argument lists
+//! safecrate hands to Docker without needing a daemon.
+
+#![cfg(unix)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Appends its arguments to `$FAKE_DOCKER_LOG` (unit separator between arguments, record
+/// separator after each call), prints `$FAKE_DOCKER_PS` for `ps` and `$FAKE_DOCKER_INSPECT`
+/// for `inspect`, and prints "output" and exits with `$FAKE_DOCKER_EXIT` for `run`.
+const FAKE_DOCKER: &str = r#"#!/bin/sh
+for arg in "$@"; do printf '%s\037' "$arg"; done >> "$FAKE_DOCKER_LOG"
+printf '\036' >> "$FAKE_DOCKER_LOG"
+case "$1" in
+  ps) printf '%s' "$FAKE_DOCKER_PS" ;;
+  inspect) printf '%s' "$FAKE_DOCKER_INSPECT" ;;
+  run) echo output; exit "${FAKE_DOCKER_EXIT:-0}" ;;
+esac
+"#;
+
+struct FakeDocker {
+    root: PathBuf,
+    ps: String,
+    inspect: String,
+    exit: i32,
+}
+
+impl FakeDocker {
+    fn new(name: &str) -> Self {
+        let root = std::env::temp_dir()
+            .join(format!("safecrate-cli-{}", std::process::id()))
+            .join(name);
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("bin")).unwrap();
+        std::fs::create_dir_all(root.join("project")).unwrap();
+        std::fs::create_dir_all(root.join("home")).unwrap();
+        let docker = root.join("bin/docker");
+        std::fs::write(&docker, FAKE_DOCKER).unwrap();
+        std::fs::set_permissions(&docker, std::fs::Permissions::from_mode(0o755)).unwrap();
+        Self {
+            root: std::fs::canonicalize(root).unwrap(),
+            ps: String::new(),
+            inspect: String::new(),
+            exit: 0,
+        }
+    }
+
+    /// Pretend these containers exist, one name per line.
+    fn with_containers(mut self, names: &str) -> Self {
+        self.ps = names.to_string();
+        self
+    }
+
+    /// Print this for `docker inspect`.
+    fn with_inspect(mut self, output: &str) -> Self {
+        self.inspect = output.to_string();
+        self
+    }
+
+    /// Make `docker run` exit with this code.
+    fn with_exit(mut self, code: i32) -> Self {
+        self.exit = code;
+        self
+    }
+
+    fn project(&self) -> PathBuf {
+        self.root.join("project")
+    }
+
+    fn log(&self) -> PathBuf {
+        self.root.join("docker.log")
+    }
+
+    /// Run `safecrate` from the test's root directory with the fake `docker` first on PATH.
+    fn safecrate(&self, args: &[&str]) -> Output {
+        let path = format!(
+            "{}:{}",
+            self.root.join("bin").display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        Command::new(env!("CARGO_BIN_EXE_safecrate"))
+            .args(args)
+            .current_dir(&self.root)
+            .env("PATH", path)
+            .env("HOME", self.root.join("home"))
+            .env("TMPDIR", &self.root)
+            .env("FAKE_DOCKER_LOG", self.log())
+            .env("FAKE_DOCKER_PS", &self.ps)
+            .env("FAKE_DOCKER_INSPECT", &self.inspect)
+            .env("FAKE_DOCKER_EXIT", self.exit.to_string())
+            .env_remove("DOCKER_HOST")
+            .env_remove("SAFECRATE_CARGO_REGISTRY")
+            .env_remove("SAFECRATE_NAME_TEMPLATE")
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap()
+    }
+
+    /// Like [`FakeDocker::safecrate`], failing the test unless it exits successfully.
+    fn safecrate_ok(&self, args: &[&str]) -> Output {
+        let output = self.safecrate(args);
+        assert!(
+            output.status.success(),
+            "safecrate {:?} failed:\n{}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    /// Every recorded `docker` invocation, in order.
+    fn calls(&self) -> Vec<Vec<String>> {
+        let log = std::fs::read_to_string(self.log()).unwrap_or_default();
+        log.split('\u{1e}')
+            .filter(|call| !call.is_empty())
+            .map(|call| {
+                call.split_terminator('\u{1f}')
+                    .map(str::to_string)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The `docker run` invocation, with the origin labels dropped.
+    fn run_call(&self) -> Vec<String> {
+        let run = self
+            .calls()
+            .into_iter()
+            .find(|call| call[0] == "run")
+            .expect("no docker run");
+        let mut args = Vec::new();
+        let mut iter = run.into_iter();
+        while let Some(arg) = iter.next() {
+            if arg == "--label" {
+                iter.next();
+            } else {
+                args.push(arg);
+            }
+        }
+        args
+    }
+}
+
+fn strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+fn volume(dir: &Path) -> String {
+    format!("{}:/workspace", dir.display())
+}
+
+#[test]
+fn open_defaults_to_no_network_and_removal() {
+    let docker = FakeDocker::new("open-default");
+    docker.safecrate_ok(&["open", "project"]);
+
+    let calls = docker.calls();
+    assert_eq!(
+        calls[0],
+        strings(&[
+            "ps",
+            "-a",
+            "--filter",
+            "name=project_isolated",
+            "--format",
+            "{{.Names}}"
+        ])
+    );
+    assert_eq!(
+        docker.run_call(),
+        strings(&[
+            "run",
+            "-it",
+            "--detach-keys",
+            "ctrl-_,ctrl-q",
+            "--rm",
+            "--name",
+            "project_isolated",
+            "--network",
+            "none",
+            "-v",
+            &volume(&docker.project()),
+            "-w",
+            "/workspace",
+            "safecrate_default",
+            "sh",
+            "-c",
+            "nvim .",
+        ])
+    );
+}
+
+#[test]
+fn open_records_its_origin() {
+    let docker = FakeDocker::new("open-origin");
+    docker.safecrate_ok(&["open", "project", "--cmd", "make"]);
+
+    let run = docker.calls().into_iter().find(|c| c[0] == "run").unwrap();
+    assert!(run.contains(&"safecrate.origin=safecrate open project --cmd make".to_string()));
+    assert!(run.contains(&format!("safecrate.origin-dir={}", docker.root.display())));
+}
+
+#[test]
+fn recreate_doesnt_run_recorded_host_commands() {
+    let docker = FakeDocker::new("recreate");
+    let origin = format!(
+        "safecrate open project --cmd make --post-run 'touch pwned' --record pwned.tar\u{1e}{}\u{1e}\u{1e}",
+        docker.root.display()
+    );
+    let docker = docker.with_inspect(&origin);
+    let output = docker.safecrate_ok(&["recreate", "project"]);
+
+    let run = docker.run_call();
+    assert!(run.ends_with(&strings(&["sh", "-c", "make"])));
+    assert!(!docker.root.join("pwned").exists());
+    assert!(!docker.root.join("pwned.tar").exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Not running the recorded --post-run hook"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn keep_container_skips_removal() {
+    let docker = FakeDocker::new("open-keep");
+    docker.safecrate_ok(&["open", "project", "--keep-container"]);
+    assert!(!docker.run_call().contains(&"--rm".to_string()));
+
+    let docker = FakeDocker::new("open-rm-policy");
+    docker.safecrate_ok(&["open", "project", "--rm-policy", "never"]);
+    assert!(!docker.run_call().contains(&"--rm".to_string()));
+}
+
+#[test]
+fn custom_cmd_and_trailing_args() {
+    let docker = FakeDocker::new("open-cmd");
+    docker.safecrate_ok(&["open", "project", "--cmd", "cargo test -- --nocapture"]);
+    assert!(docker
+        .run_call()
+        .ends_with(&strings(&["sh", "-c", "cargo test -- --nocapture"])));
+
+    let docker = FakeDocker::new("open-args");
+    docker.safecrate_ok(&["open", "project", "--", "cargo", "build", "--release"]);
+    assert!(docker.run_call().ends_with(&strings(&[
+        "safecrate_default",
+        "cargo",
+        "build",
+        "--release"
+    ])));
+}
+
+#[test]
+fn cmd_stdin_drops_the_terminal() {
+    let docker = FakeDocker::new("open-cmd-stdin");
+    docker.safecrate_ok(&["open", "project", "--cmd-stdin", "bash"]);
+    let run = docker.run_call();
+    assert_eq!(run[1], "-i");
+    assert!(!run.contains(&"--detach-keys".to_string()));
+    assert!(run.ends_with(&strings(&["sh", "-c", "bash"])));
+}
+
+#[test]
+fn network_is_opt_in() {
+    let docker = FakeDocker::new("open-network");
+    docker.safecrate_ok(&["open", "project", "--network"]);
+    let run = docker.run_call();
+    assert!(run.windows(2).any(|pair| pair == ["--network", "bridge"]));
+    assert!(!run.contains(&"none".to_string()));
+}
+
+#[test]
+fn open_refuses_an_existing_container() {
+    let docker = FakeDocker::new("open-existing").with_containers("project_isolated\n");
+    let output = docker.safecrate(&["open", "project"]);
+    assert!(!output.status.success());
+    assert!(docker.calls().iter().all(|call| call[0] != "run"));
+}
+
+#[test]
+fn spec_describes_the_invocation() {
+    let docker = FakeDocker::new("open-spec");
+    std::fs::write(
+        docker.root.join("run.toml"),
+        "dir = \"project\"\ncmd = \"cargo test\"\nmemory = \"2g\"\n",
+    )
+    .unwrap();
+    docker.safecrate_ok(&["open", "--spec", "run.toml", "--memory", "4g"]);
+    let run = docker.run_call();
+    assert!(run.contains(&"project_isolated".to_string()));
+    assert!(run.windows(2).any(|pair| pair == ["--memory", "4g"]));
+    assert!(run.ends_with(&strings(&["sh", "-c", "cargo test"])));
+}
+
+#[test]
+fn replay_reopens_a_recording() {
+    let docker = FakeDocker::new("open-record");
+    std::fs::write(docker.project().join("main.rs"), "fn main() {}").unwrap();
+    docker.safecrate_ok(&[
+        "open",
+        "project",
+        "--record",
+        "session.tar",
+        "--memory",
+        "2g",
+        "--cmd",
+        "cargo test",
+    ]);
+    std::fs::remove_file(docker.log()).unwrap();
+
+    docker.safecrate_ok(&["replay", "session.tar"]);
+    let run = docker.run_call();
+    assert!(run.contains(&"project_isolated".to_string()));
+    assert!(run.windows(2).any(|pair| pair == ["--memory", "2g"]));
+    assert!(run.ends_with(&strings(&["sh", "-c", "cargo test"])));
+    let workspace = run.iter().find(|arg| arg.ends_with(":/workspace")).unwrap();
+    assert!(workspace.contains("safecrate-replay-"), "{}", workspace);
+}
+
+#[test]
+fn record_takes_one_directory() {
+    let docker = FakeDocker::new("open-record-batch");
+    std::fs::create_dir_all(docker.root.join("other")).unwrap();
+    let output = docker.safecrate(&["open", "project", "other", "--record", "session.tar"]);
+    assert!(!output.status.success());
+    assert!(docker.calls().is_empty());
+    assert!(!docker.root.join("session.tar").exists());
+}
+
+#[test]
+fn open_url_clones_into_a_private_dir() {
+    let docker = FakeDocker::new("open-url");
+    docker.safecrate_ok(&["open-url", "https://example.com/org/tool.git", "--keep"]);
+    let run = docker.run_call();
+    let workspace = run.iter().find(|arg| arg.ends_with(":/workspace")).unwrap();
+    let clone = Path::new(workspace.split(':').next().unwrap());
+    assert_eq!(clone.file_name().unwrap(), "tool");
+    let parent = clone.parent().unwrap();
+    assert!(parent.starts_with(&docker.root), "{}", parent.display());
+    let mode = std::fs::metadata(parent).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o700);
+}
+
+#[test]
+fn print_command_only_prints_a_script() {
+    let docker = FakeDocker::new("open-print");
+    let output = docker.safecrate_ok(&[
+        "open",
+        "project",
+        "--print-command-only",
+        "--cmd",
+        "echo 'hi there'",
+    ]);
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.starts_with("#!/bin/sh\n"));
+    assert!(script.contains("exec docker run \\\n  -it \\\n"));
+    assert!(script.ends_with("safecrate_default sh -c 'echo '\\''hi there'\\'''\n"));
+    assert!(docker.calls().is_empty());
+}
+
+#[test]
+fn run_exits_with_the_command_status() {
+    let docker = FakeDocker::new("run-exit").with_exit(3);
+    let output = docker.safecrate(&["run", "project", "--", "cargo", "test"]);
+    assert_eq!(output.status.code(), Some(3));
+    let run = docker.run_call();
+    assert!(!run.contains(&"-it".to_string()));
+    assert!(run.contains(&"--rm".to_string()));
+}
+
+#[test]
+fn run_is_offline_despite_configured_network_keys() {
+    let docker = FakeDocker::new("run-offline");
+    std::fs::write(
+        docker.project().join(".safecrate.toml"),
+        "network_mode = \"testnet\"\nip = \"172.30.0.10\"\nmac_address = \"02:42:ac:1e:00:0a\"\n",
+    )
+    .unwrap();
+    docker.safecrate_ok(&["run", "project", "--", "make"]);
+    let run = docker.run_call();
+    assert!(run.windows(2).any(|pair| pair == ["--network", "none"]));
+    assert!(!run.contains(&"--ip".to_string()));
+    assert!(!run.contains(&"--mac-address".to_string()));
+}
+
+#[test]
+fn capture_output_saves_a_transcript() {
+    let docker = FakeDocker::new("run-capture");
+    let output = docker.safecrate_ok(&[
+        "run",
+        "project",
+        "--capture-output",
+        "run.log",
+        "--",
+        "cargo",
+        "test",
+    ]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("output\n"));
+    assert_eq!(
+        std::fs::read_to_string(docker.root.join("run.log")).unwrap(),
+        "output\n"
+    );
+
+    let docker = FakeDocker::new("open-capture");
+    let output = docker.safecrate(&["open", "project", "--capture-output", "run.log"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn unverified_provenance_blocks_open() {
+    let docker = FakeDocker::new("open-provenance");
+    let cosign = docker.root.join("bin/cosign");
+    std::fs::write(
+        &cosign,
+        "#!/bin/sh\necho 'no matching attestations' >&2\nexit 1\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&cosign, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let args = [
+        "open",
+        "project",
+        "--image",
+        "ghcr.io/org/img@sha256:abc",
+        "--verify-image-provenance",
+        "--provenance-key",
+        "cosign.pub",
+    ];
+
+    let output = docker.safecrate(&args);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no matching attestations"));
+    assert!(!docker.calls().iter().any(|call| call[0] == "run"));
+
+    let mut warn = args.to_vec();
+    warn.insert(5, "warn");
+    docker.safecrate_ok(&warn);
+    assert!(docker
+        .run_call()
+        .contains(&"ghcr.io/org/img@sha256:abc".to_string()));
+}
+
+#[test]
+fn resume_starts_the_existing_container() {
+    let docker = FakeDocker::new("resume").with_containers("project_isolated\n");
+    docker.safecrate_ok(&["resume", "project"]);
+    assert_eq!(
+        docker.calls()[1],
+        strings(&[
+            "start",
+            "-ai",
+            "--detach-keys",
+            "ctrl-_,ctrl-q",
+            "project_isolated"
+        ])
+    );
+
+    let docker = FakeDocker::new("resume-missing");
+    assert!(!docker.safecrate(&["resume", "project"]).status.success());
+}
+
+#[test]
+fn remove_stops_then_removes() {
+    let docker = FakeDocker::new("remove");
+    docker.safecrate_ok(&["remove", "project"]);
+    assert_eq!(docker.calls(), [strings(&["rm", "project_isolated"])]);
+
+    let docker = FakeDocker::new("remove-stop");
+    docker.safecrate_ok(&["remove", "project", "--force", "--stop-timeout", "5"]);
+    assert_eq!(
+        docker.calls(),
+        [
+            strings(&["stop", "-t", "5", "project_isolated"]),
+            strings(&["rm", "-f", "project_isolated"]),
+        ]
+    );
+}
+
+#[test]
+fn name_template_applies_to_every_command() {
+    let docker = FakeDocker::new("name-template");
+    docker.safecrate_ok(&["--name-template", "{project}-sandbox", "open", "project"]);
+    assert!(docker.run_call().contains(&"project-sandbox".to_string()));
+
+    let docker = FakeDocker::new("name-prefix");
+    docker.safecrate_ok(&["remove", "--prefix", "sc-", "project"]);
+    assert_eq!(docker.calls(), [strings(&["rm", "sc-project"])]);
+
+    let docker = FakeDocker::new("name-invalid");
+    assert!(!docker
+        .safecrate(&["--name-template", "sandbox", "open", "project"])
+        .status
+        .success());
+    assert!(docker.calls().is_empty());
+}
+
+#[test]
+fn init_builds_the_default_image() {
+    let docker = FakeDocker::new("init");
+    docker.safecrate_ok(&["init", "--tag", "safecrate_rust"]);
+
+    let calls = docker.calls();
+    let build = &calls[0];
+    assert_eq!(build[..3], strings(&["build", "-t", "safecrate_rust"]));
+    assert_eq!(build[3], "-f");
+    assert_eq!(build.last().unwrap(), ".");
+}